@@ -2,20 +2,17 @@
 use std::env;
 use std::path::Path;
 
-use base64::Engine;
-use base64::prelude::BASE64_STANDARD;
 use chrono::{TimeDelta, Utc};
 use dotenvy::dotenv;
 use http::Uri;
-use jsonwebtoken::{Algorithm, Header};
-use sha2::{Digest, Sha512};
-use tracing::debug;
-use vaultrs::api::transit::MarshalingAlgorithm;
-use vaultrs::api::transit::requests::SignDataRequestBuilder;
-use vaultrs::client::{VaultClient, VaultClientSettingsBuilder};
-use vaultrs::transit::data::sign;
+use jsonwebtoken::Algorithm;
 
+use nr_auth::http::client::HttpClient;
+use nr_auth::http::config::HttpConfig;
 use nr_auth::jwt::claims::Claims;
+use nr_auth::jwt::signer::vault::{VaultAuth, VaultTransitConfig, VaultTransitSigner};
+use nr_auth::jwt::signer::{JwtSigner, JwtSignerImpl};
+use nr_auth::parameters::DEFAULT_AUTHENTICATOR_TIMEOUT;
 
 /// A signed JWT should live enough for the System Identity Service to consume it.
 const DEFAULT_JWT_CLAIM_EXP: TimeDelta = TimeDelta::seconds(180);
@@ -35,8 +32,7 @@ const DEFAULT_AUDIENCE: &str = "https://www.newrelic.com/";
 /// - The `.env` file is missing or cannot be loaded.
 /// - Required environment variables are not set.
 /// - The JWT signing process fails.
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Set the current directory to the example's path
     let example_dir = Path::new("examples/jwt-signer-vault");
     env::set_current_dir(example_dir).expect("Failed to change directory");
@@ -53,15 +49,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let key_name = env::var("TRANSIT_KEY_NAME")?;
     let client_id = env::var("SYSTEM_IDENTITY_CLIENT_ID")?;
 
-    // Create a vault client
-    let client = VaultClient::new(
-        VaultClientSettingsBuilder::default()
-            .address(vault_addr)
-            .token(token)
-            .build()
-            .map_err(|e| format!("cannot build vault client settings {}", e))?,
+    let http_client = HttpClient::new(HttpConfig::new(
+        DEFAULT_AUTHENTICATOR_TIMEOUT,
+        DEFAULT_AUTHENTICATOR_TIMEOUT,
+        Default::default(),
+    ))?;
+
+    // Create a vault signer
+    let config = VaultTransitConfig::new(
+        Uri::try_from(vault_addr)?,
+        key_name,
+        VaultAuth::Token(token),
     )
-    .map_err(|e| format!("cannot build vault client {}", e))?;
+    .with_algorithm(Algorithm::RS512);
+    let jwt_signer =
+        JwtSignerImpl::VaultTransit(Box::new(VaultTransitSigner::new(http_client, config)?));
 
     // Build claims
     let url = Uri::try_from(DEFAULT_AUDIENCE).expect("constant valid url value");
@@ -72,32 +74,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map_err(|_| "converting token expiration time")?;
     let claims = Claims::new(client_id, url, timestamp);
 
-    // Based on https://github.com/alexadamm/jwt-vault-go/blob/main/pkg/vault/vault.go#L245
-    let encoded_header = base64_encode(serde_json::to_vec(&Header::new(Algorithm::RS512))?);
-    let encoded_claims = base64_encode(serde_json::to_vec(&claims)?);
-    let input = format!("{}.{}", encoded_header, encoded_claims);
-    // Hash the input data
-    let mut hasher = Sha512::new();
-    hasher.update(input);
-    let digest = hasher.finalize().to_vec();
-    let hashed_input = base64_encode(digest);
-    debug!("hashed_input: {}", hashed_input);
-
-    let response = sign(
-        &client,
-        "transit",
-        key_name.as_str(),
-        hashed_input.as_str(),
-        Some(SignDataRequestBuilder::default().marshaling_algorithm(MarshalingAlgorithm::Jws)),
-    )
-    .await
-    .map_err(|e| format!("cannot sign data {}", e))?;
+    let signed_jwt = jwt_signer
+        .sign(claims)
+        .map_err(|e| format!("signing token failed: {}", e))?;
 
-    println!("{}", response.signature);
+    println!("{}", signed_jwt.value());
 
     Ok(())
 }
-
-fn base64_encode<T: AsRef<[u8]>>(input: T) -> String {
-    BASE64_STANDARD.encode(input)
-}
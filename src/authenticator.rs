@@ -21,6 +21,14 @@ pub enum AuthenticateError {
     HttpTransportError(String),
 }
 
+impl AuthenticateError {
+    /// Whether the identity server rejected the client credentials (OAuth `invalid_client` error),
+    /// as it happens when a client assertion is signed with a key it does not know.
+    pub fn is_invalid_client(&self) -> bool {
+        matches!(self, Self::HttpResponseError(400 | 401, body) if body.contains("invalid_client"))
+    }
+}
+
 pub trait Authenticator {
     fn authenticate(
         &self,
@@ -137,6 +145,7 @@ pub mod test {
     use assert_matches::assert_matches;
     use http::{Method, Uri};
     use mockall::mock;
+    use rstest::rstest;
 
     use super::{
         AuthCredential, ClientAssertion, ClientAssertionType, ClientID, GrantType,
@@ -253,6 +262,16 @@ pub mod test {
         assert_matches!(error, AuthenticateError::HttpResponseError(500, _));
     }
 
+    #[rstest]
+    #[case::unauthorized(401, r#"{"error":"invalid_client"}"#, true)]
+    #[case::bad_request(400, r#"{"error":"invalid_client"}"#, true)]
+    #[case::other_oauth_error(400, r#"{"error":"invalid_grant"}"#, false)]
+    #[case::server_error(500, "invalid_client", false)]
+    fn test_is_invalid_client(#[case] status: u16, #[case] body: &str, #[case] expected: bool) {
+        let error = AuthenticateError::HttpResponseError(status, body.to_string());
+        assert_eq!(error.is_invalid_client(), expected);
+    }
+
     #[test]
     fn test_request_serialization_and_deserialization() {
         let request = TokenRetrievalRequest {
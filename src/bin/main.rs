@@ -1,17 +1,31 @@
 use clap::Parser;
 use nr_auth::authenticator::HttpAuthenticator;
-use nr_auth::commands::create::CreateCommand;
+use nr_auth::commands::bootstrap::BootstrapIdentityCreator;
+use nr_auth::commands::create::{CreateCommand, EnsuredIdentity};
+use nr_auth::commands::group::groups_table;
+use nr_auth::commands::identity::identities_table;
+use nr_auth::commands::key::{export_jwks, validate_key};
 use nr_auth::commands::retrieve_token::RetrieveTokenCommand;
+use nr_auth::commands::rotate_key::{RotateKeyCommand, RotatedIdentity};
 use nr_auth::http::client::HttpClient;
 use nr_auth::http::config::HttpConfig;
 use nr_auth::parameters::{
-    AuthenticationArgs, Commands, DEFAULT_AUTHENTICATOR_TIMEOUT, IdentityCreationCredential,
-    IdentityType, IdentityTypeBootstrap, OutputTokenFormat, ProxyArgs, build_proxy_args,
+    AuthenticationArgs, Commands, DEFAULT_AUTHENTICATOR_TIMEOUT, DeleteIdentityArgs, GroupCommand,
+    IdentityCommand, IdentityCreationCredential, IdentityType, IdentityTypeBootstrap, KeyCommand,
+    OrganizationArgs, OutputFormat, OutputTokenFormat, ProxyArgs, RotateKeyArgs, build_proxy_args,
     create_metadata_for_bootstrap_identity_creation, create_metadata_for_identity_creation,
+    create_metadata_for_key_rotation, create_metadata_for_organization,
     create_metadata_for_token_retrieve, extract_api_key_from_bootstrap,
-    extract_identity_creation_credential, select_output_platform, select_output_platform_bootstrap,
+    extract_identity_creation_credential, extract_key_rotation_credential,
+    extract_organization_credential, select_certificate, select_certificate_bootstrap,
+    select_deletion_filter, select_identity_filter, select_identity_ids_deletion,
+    select_identity_update, select_if_not_exists, select_key_import, select_key_import_bootstrap,
+    select_key_passphrase, select_key_passphrase_bootstrap, select_key_passphrase_rotation,
+    select_key_type, select_key_type_bootstrap, select_key_type_rotation, select_output_platform,
+    select_output_platform_bootstrap, select_validate_key_passphrase,
 };
 use nr_auth::system_identity::iam_client::http::{HttpIAMClient, IAMAuthCredential};
+use nr_auth::system_identity::query::SystemIdentityPage;
 use std::error::Error;
 
 #[derive(Parser, Debug)]
@@ -41,6 +55,276 @@ fn main() -> Result<(), Box<dyn Error>> {
             auth_args,
             output_token_format,
         } => handle_authenticate_command(http_client, auth_args, output_token_format),
+        Commands::RotateKey(args) => handle_rotate_key_command(http_client, args),
+        Commands::Key { command } => handle_key_command(command),
+        Commands::Identity { command } => handle_identity_command(http_client, command),
+        Commands::DeleteIdentity(args) => handle_delete_identity_command(http_client, args),
+        Commands::Group { command } => handle_group_command(http_client, command),
+    }
+}
+
+fn handle_group_command(
+    http_client: HttpClient,
+    command: GroupCommand,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        GroupCommand::Create(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+
+            let group = iam_client.create_system_identity_group(
+                &args.name,
+                args.description.as_deref(),
+                &iam_auth_credential,
+            )?;
+
+            match args.output_format {
+                OutputFormat::Table => print!("{}", groups_table(&[group])),
+                OutputFormat::Json => println!("{}", serde_json::to_string(&group)?),
+            }
+        }
+        GroupCommand::List(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+
+            let groups = iam_client
+                .list_system_identity_groups(args.name.as_deref(), &iam_auth_credential)?;
+
+            match args.output_format {
+                OutputFormat::Table => print!("{}", groups_table(&groups)),
+                OutputFormat::Json => println!("{}", serde_json::to_string(&groups)?),
+            }
+        }
+        GroupCommand::Delete(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+
+            iam_client.delete_system_identity_group(&args.id, &iam_auth_credential)?;
+            println!("System identity group {} deleted", args.id);
+        }
+        GroupCommand::AddMembers(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+
+            iam_client.add_identities_to_groups(
+                &as_strs(&args.identity_ids),
+                &as_strs(&args.group_ids),
+                &iam_auth_credential,
+            )?;
+            println!(
+                "{} identities added to {} groups",
+                args.identity_ids.len(),
+                args.group_ids.len()
+            );
+        }
+        GroupCommand::RemoveMembers(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+
+            iam_client.remove_identities_from_groups(
+                &as_strs(&args.identity_ids),
+                &as_strs(&args.group_ids),
+                &iam_auth_credential,
+            )?;
+            println!(
+                "{} identities removed from {} groups",
+                args.identity_ids.len(),
+                args.group_ids.len()
+            );
+        }
+        GroupCommand::Members(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+
+            let members =
+                iam_client.list_system_identity_group_members(&args.id, &iam_auth_credential)?;
+
+            match args.output_format {
+                OutputFormat::Table => print!("{}", identities_table(&members)),
+                OutputFormat::Json => println!("{}", serde_json::to_string(&members)?),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn as_strs(values: &[String]) -> Vec<&str> {
+    values.iter().map(String::as_str).collect()
+}
+
+fn handle_delete_identity_command(
+    http_client: HttpClient,
+    args: DeleteIdentityArgs,
+) -> Result<(), Box<dyn Error>> {
+    let (iam_client, iam_auth_credential) =
+        organization_iam_client(http_client, &args.organization)?;
+
+    let ids = select_identity_ids_deletion(&args);
+    let identities = if ids.is_empty() {
+        let filter = select_deletion_filter(&args)?;
+        iam_client.find_system_identities_to_delete(&filter, &iam_auth_credential)?
+    } else {
+        ids.iter()
+            .map(|id| {
+                iam_client
+                    .get_system_identity(id, &iam_auth_credential)?
+                    .ok_or_else(|| format!("system identity `{id}` not found").into())
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?
+    };
+
+    if args.dry_run {
+        match args.output_format {
+            OutputFormat::Table => {
+                println!("{} identities would be deleted:", identities.len());
+                print!("{}", identities_table(&identities));
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string(&identities)?),
+        }
+        return Ok(());
+    }
+
+    let report = iam_client.delete_system_identities(identities, &iam_auth_credential);
+    match args.output_format {
+        OutputFormat::Table => {
+            println!("{} identities deleted:", report.deleted.len());
+            print!("{}", identities_table(&report.deleted));
+            for failed in &report.failed {
+                eprintln!("unable to delete {}: {}", failed.identity.id, failed.error);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string(&report)?),
+    }
+    if !report.failed.is_empty() {
+        return Err(format!("{} identities could not be deleted", report.failed.len()).into());
+    }
+    Ok(())
+}
+
+/// IAM client and credential for managing the identities of the organization.
+fn organization_iam_client(
+    http_client: HttpClient,
+    args: &OrganizationArgs,
+) -> Result<(HttpIAMClient<HttpClient>, IAMAuthCredential), Box<dyn Error>> {
+    let iam_auth_credential = match extract_organization_credential(args)? {
+        IdentityCreationCredential::BearerToken(token) => IAMAuthCredential::BearerToken(token),
+        IdentityCreationCredential::ApiKey(api_key) => IAMAuthCredential::ApiKey(api_key),
+    };
+    let meta = create_metadata_for_organization(args);
+    Ok((HttpIAMClient::new(http_client, meta), iam_auth_credential))
+}
+
+fn handle_identity_command(
+    http_client: HttpClient,
+    command: IdentityCommand,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        IdentityCommand::List(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+            let filter = select_identity_filter(&args);
+
+            let page = if args.all {
+                SystemIdentityPage {
+                    items: iam_client.list_all_system_identities(&filter, &iam_auth_credential)?,
+                    next_cursor: None,
+                }
+            } else {
+                iam_client.list_system_identities(
+                    &filter,
+                    args.cursor.as_deref(),
+                    &iam_auth_credential,
+                )?
+            };
+
+            match args.output_format {
+                OutputFormat::Table => {
+                    print!("{}", identities_table(&page.items));
+                    if let Some(next_cursor) = page.next_cursor {
+                        println!("\nNext cursor: {next_cursor}");
+                    }
+                }
+                OutputFormat::Json => println!("{}", serde_json::to_string(&page)?),
+            }
+            Ok(())
+        }
+        IdentityCommand::Get(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+
+            let identity = iam_client
+                .get_system_identity(&args.id, &iam_auth_credential)?
+                .ok_or_else(|| format!("system identity `{}` not found", args.id))?;
+
+            match args.output_format {
+                OutputFormat::Table => print!("{}", identities_table(&[identity])),
+                OutputFormat::Json => println!("{}", serde_json::to_string(&identity)?),
+            }
+            Ok(())
+        }
+        IdentityCommand::Update(args) => {
+            let (iam_client, iam_auth_credential) =
+                organization_iam_client(http_client, &args.organization)?;
+            let update = select_identity_update(&args);
+
+            let identity =
+                iam_client.update_system_identity(&args.id, &update, &iam_auth_credential)?;
+
+            match args.output_format {
+                OutputFormat::Table => print!("{}", identities_table(&[identity])),
+                OutputFormat::Json => println!("{}", serde_json::to_string(&identity)?),
+            }
+            Ok(())
+        }
+    }
+}
+
+fn handle_rotate_key_command(
+    http_client: HttpClient,
+    args: RotateKeyArgs,
+) -> Result<(), Box<dyn Error>> {
+    let iam_auth_credential = match extract_key_rotation_credential(&args)? {
+        IdentityCreationCredential::BearerToken(token) => IAMAuthCredential::BearerToken(token),
+        IdentityCreationCredential::ApiKey(api_key) => IAMAuthCredential::ApiKey(api_key),
+    };
+    let key_type = select_key_type_rotation(&args);
+    let key_passphrase = select_key_passphrase_rotation(&args)?;
+
+    let meta = create_metadata_for_key_rotation(&args);
+    let authenticator = HttpAuthenticator::new(
+        http_client.clone(),
+        meta.environment.token_renewal_endpoint(),
+    );
+    let iam_client = &HttpIAMClient::new(http_client, meta);
+
+    let identity = RotatedIdentity {
+        identity_id: args.identity_id,
+        client_id: args.client_id,
+    };
+    let rotation = RotateKeyCommand::new(iam_client, authenticator)
+        .with_key_type(key_type)
+        .with_key_passphrase(key_passphrase)
+        .with_revoke_old_key(args.revoke_old_key)
+        .rotate(&iam_auth_credential, &identity, &args.private_key_path)?;
+
+    println!("{}", serde_json::to_string(&rotation)?);
+    Ok(())
+}
+
+fn handle_key_command(command: KeyCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        KeyCommand::ExportJwks(args) => {
+            if let Some(document) = export_jwks(&args.key_paths, args.output_path.as_deref())? {
+                println!("{document}");
+            }
+            Ok(())
+        }
+        KeyCommand::Validate(args) => {
+            let passphrase = select_validate_key_passphrase(&args)?;
+            let info = validate_key(&args.key_path, passphrase.as_ref())?;
+            println!("{}", serde_json::to_string(&info)?);
+            Ok(())
+        }
     }
 }
 
@@ -56,19 +340,53 @@ fn handle_create_identity_command(
     };
 
     let meta = create_metadata_for_identity_creation(&identity_type);
+    let if_not_exists = select_if_not_exists(&identity_type);
     let iam_client = &HttpIAMClient::new(http_client, meta);
     let create_command = CreateCommand::new(iam_client);
 
     let system_identity = match identity_type {
-        IdentityType::Secret(_) => {
-            create_command.create_l1_with_credential(&iam_auth_credential)?
+        IdentityType::Secret(_) if if_not_exists => {
+            create_command.ensure_l1_with_credential(&iam_auth_credential)?
         }
+        IdentityType::Secret(_) => EnsuredIdentity::Created(
+            create_command.create_l1_with_credential(&iam_auth_credential)?,
+        ),
         IdentityType::Key(key_args) => {
-            let output_platform = select_output_platform(key_args);
-            create_command.create_l2_with_credential(&output_platform, &iam_auth_credential)?
+            let key_type = select_key_type(&key_args);
+            let key_passphrase = select_key_passphrase(&key_args)?;
+            let create_command = create_command
+                .with_key_type(key_type)
+                .with_key_passphrase(key_passphrase)
+                .with_certificate(select_certificate(&key_args));
+            match (select_key_import(&key_args), if_not_exists) {
+                (Some(key_path), true) => {
+                    create_command.ensure_l2_with_key_file(&key_path, &iam_auth_credential)?
+                }
+                (Some(key_path), false) => EnsuredIdentity::Created(
+                    create_command.create_l2_with_key_file(&key_path, &iam_auth_credential)?,
+                ),
+                (None, true) => {
+                    let output_platform = select_output_platform(key_args);
+                    create_command
+                        .ensure_l2_with_credential(&output_platform, &iam_auth_credential)?
+                }
+                (None, false) => {
+                    let output_platform = select_output_platform(key_args);
+                    EnsuredIdentity::Created(
+                        create_command
+                            .create_l2_with_credential(&output_platform, &iam_auth_credential)?,
+                    )
+                }
+            }
         }
     };
 
+    if let EnsuredIdentity::Existing(identity) = &system_identity {
+        eprintln!(
+            "System identity {} already exists, it was not created",
+            identity.id
+        );
+    }
     println!("{}", serde_json::to_string(&system_identity)?);
     Ok(())
 }
@@ -85,16 +403,27 @@ fn handle_create_bootstrap_identity_command(
 
     let system_identity = match identity_type {
         IdentityTypeBootstrap::Secret(_) => {
-            create_command.create_l1_with_credential(&auth_credential)?
+            BootstrapIdentityCreator::new(create_command).create_l1(&auth_credential)?
         }
         IdentityTypeBootstrap::Key(key_args) => {
-            let output_platform = select_output_platform_bootstrap(key_args);
-            create_command.create_l2_with_credential(&output_platform, &auth_credential)?
+            let key_type = select_key_type_bootstrap(&key_args);
+            let key_passphrase = select_key_passphrase_bootstrap(&key_args)?;
+            let creator = BootstrapIdentityCreator::new(
+                create_command
+                    .with_key_type(key_type)
+                    .with_key_passphrase(key_passphrase)
+                    .with_certificate(select_certificate_bootstrap(&key_args)),
+            );
+            match select_key_import_bootstrap(&key_args) {
+                Some(key_path) => creator.create_l2_with_key_file(&key_path, &auth_credential)?,
+                None => {
+                    let output_platform = select_output_platform_bootstrap(key_args);
+                    creator.create_l2(&output_platform, &auth_credential)?
+                }
+            }
         }
     };
 
-    iam_client.add_identity_to_nr_control_group_by_id(&system_identity.id, &auth_credential)?;
-
     println!("{}", serde_json::to_string(&system_identity)?);
     Ok(())
 }
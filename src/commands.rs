@@ -1,2 +1,8 @@
+pub mod bootstrap;
 pub mod create;
+pub mod group;
+pub mod identity;
+pub mod key;
 pub mod retrieve_token;
+pub mod rotate_key;
+mod table;
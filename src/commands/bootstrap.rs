@@ -0,0 +1,346 @@
+use std::path::Path;
+
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::commands::create::{CreateCommand, GeneratedKey};
+use crate::http_client::HttpClient;
+use crate::system_identity::SystemIdentity;
+use crate::system_identity::iam_client::http::{IAMAuthCredential, NR_CONTROL_GROUP_NAME};
+use crate::system_identity::input_data::output_platform::OutputPlatform;
+
+#[derive(Error, Debug)]
+pub enum BootstrapError {
+    #[error("generating the key: `{0}`")]
+    KeyGeneration(String),
+    #[error("creating the identity: `{0}`")]
+    Creation(String),
+    #[error("adding the identity to the {NR_CONTROL_GROUP_NAME}: `{0}`")]
+    GroupMembership(String),
+    #[error("{0}; rolling back the bootstrap identity creation failed: `{1}`")]
+    Rollback(Box<BootstrapError>, String),
+}
+
+/// Creates bootstrap System Identities: identities added to the [`NR_CONTROL_GROUP_NAME`], which
+/// grants them the capability of creating other identities.
+///
+/// Key generation, identity creation and group membership are performed as a single operation.
+/// If a step fails, the completed ones are undone: the identity is deleted and the generated key
+/// (and certificate) removed, so no identity without the expected capability nor orphan key is
+/// left behind.
+///
+/// The key type, passphrase and certificate of L2 identities are the ones configured in the
+/// wrapped [`CreateCommand`].
+pub struct BootstrapIdentityCreator<'a, C>
+where
+    C: HttpClient,
+{
+    create_command: CreateCommand<'a, C>,
+}
+
+impl<'a, C> BootstrapIdentityCreator<'a, C>
+where
+    C: HttpClient,
+{
+    pub fn new(create_command: CreateCommand<'a, C>) -> Self {
+        Self { create_command }
+    }
+
+    /// Creates a bootstrap L1 identity.
+    pub fn create_l1(
+        self,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentity, BootstrapError> {
+        let identity = self
+            .create_command
+            .create_l1_with_credential(auth_credentials)
+            .map_err(|e| BootstrapError::Creation(e.to_string()))?;
+        self.join_control_group(identity, None, auth_credentials)
+    }
+
+    /// Creates a bootstrap L2 identity, generating its key in the output platform.
+    pub fn create_l2(
+        self,
+        output_platform: &OutputPlatform,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentity, BootstrapError> {
+        let (pub_key, generated_key) = self
+            .create_command
+            .generate_key(output_platform)
+            .map_err(|e| BootstrapError::KeyGeneration(e.to_string()))?;
+        let identity = match self
+            .create_command
+            .create_l2_with_public_key(&pub_key, auth_credentials)
+        {
+            Ok(identity) => identity,
+            Err(e) => {
+                let error = BootstrapError::Creation(e.to_string());
+                return Err(self.rollback(error, None, Some(&generated_key), auth_credentials));
+            }
+        };
+        self.join_control_group(identity, Some(&generated_key), auth_credentials)
+    }
+
+    /// Creates a bootstrap L2 identity for the existing PEM key at `key_path` (private or
+    /// public). The key is never removed, even if the creation fails.
+    pub fn create_l2_with_key_file(
+        self,
+        key_path: &Path,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentity, BootstrapError> {
+        let identity = self
+            .create_command
+            .create_l2_with_key_file(key_path, auth_credentials)
+            .map_err(|e| BootstrapError::Creation(e.to_string()))?;
+        self.join_control_group(identity, None, auth_credentials)
+    }
+
+    /// Adds the new identity to the NR Control Group, rolling back its creation on failure.
+    fn join_control_group(
+        &self,
+        identity: SystemIdentity,
+        generated_key: Option<&GeneratedKey>,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentity, BootstrapError> {
+        debug!("adding identity {} to {NR_CONTROL_GROUP_NAME}", identity.id);
+        match self
+            .create_command
+            .iam_client()
+            .add_identity_to_nr_control_group_by_id(&identity.id, auth_credentials)
+        {
+            Ok(()) => Ok(identity),
+            Err(e) => Err(self.rollback(
+                BootstrapError::GroupMembership(e.to_string()),
+                Some(&identity.id),
+                generated_key,
+                auth_credentials,
+            )),
+        }
+    }
+
+    /// Deletes the created identity and removes the generated key, reporting the steps that
+    /// could not be undone along with the original error.
+    fn rollback(
+        &self,
+        error: BootstrapError,
+        identity_id: Option<&str>,
+        generated_key: Option<&GeneratedKey>,
+        auth_credentials: &IAMAuthCredential,
+    ) -> BootstrapError {
+        let mut failures = Vec::new();
+        if let Some(identity_id) = identity_id {
+            debug!("deleting identity {identity_id}");
+            if let Err(e) = self
+                .create_command
+                .iam_client()
+                .delete_system_identity(identity_id, auth_credentials)
+            {
+                failures.push(format!("deleting identity {identity_id}: {e}"));
+            }
+        }
+        if let Some(generated_key) = generated_key
+            && let Err(e) = generated_key.remove()
+        {
+            failures.push(e);
+        }
+        if failures.is_empty() {
+            return error;
+        }
+        for failure in &failures {
+            warn!("unable to roll back the bootstrap identity creation: {failure}");
+        }
+        BootstrapError::Rollback(Box::new(error), failures.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use http::Request;
+    use serde_json::{Value, json};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::http_client::tests::MockHttpClient;
+    use crate::key::certificate::{CertificateConfig, CertificateSubject, certificate_path};
+    use crate::key::generation::KeyType;
+    use crate::system_identity::iam_client::graphql::{
+        SYSTEM_IDENTITY_ADD_TO_GROUPS, SYSTEM_IDENTITY_CREATE, SYSTEM_IDENTITY_DELETE,
+        SYSTEM_IDENTITY_GROUPS_BY_NAME,
+    };
+    use crate::system_identity::iam_client::http::HttpIAMClient;
+    use crate::system_identity::iam_client::http::tests::json_response;
+    use crate::system_identity::input_data::SystemIdentityCreationMetadata;
+    use crate::system_identity::input_data::environment::NewRelicEnvironment;
+
+    fn metadata() -> SystemIdentityCreationMetadata {
+        SystemIdentityCreationMetadata {
+            organization_id: "org-id".to_string(),
+            name: Some("bootstrap".to_string()),
+            environment: NewRelicEnvironment::Staging,
+        }
+    }
+
+    fn auth_credential() -> IAMAuthCredential {
+        IAMAuthCredential::ApiKey("NRAK-XXXX".to_string())
+    }
+
+    fn is_query(req: &Request<Vec<u8>>, query: &str) -> bool {
+        let body: Value = serde_json::from_slice(req.body()).unwrap();
+        body["query"] == query
+    }
+
+    fn expect_creation(mock_http_client: &mut MockHttpClient, credential: Value) {
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| is_query(req, SYSTEM_IDENTITY_CREATE))
+            .returning(move |_| {
+                let mut identity = json!({
+                    "id": "identity-1",
+                    "clientId": "client-1",
+                    "organizationId": "org-id",
+                });
+                identity
+                    .as_object_mut()
+                    .unwrap()
+                    .extend(credential.as_object().unwrap().clone());
+                json_response(json!({"data": {"systemIdentityCreate": identity}}))
+            });
+    }
+
+    fn l1_credential() -> Value {
+        json!({"clientSecret": "secret", "credentialExpiration": "2026-12-31T00:00:00Z"})
+    }
+
+    fn l2_credential() -> Value {
+        json!({"publicKey": "cHVibGljS2V5"})
+    }
+
+    fn expect_group_lookup(mock_http_client: &mut MockHttpClient, group_ids: &'static [&str]) {
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| is_query(req, SYSTEM_IDENTITY_GROUPS_BY_NAME))
+            .returning(move |_| {
+                let items = group_ids
+                    .iter()
+                    .map(|id| json!({"id": id}))
+                    .collect::<Vec<_>>();
+                json_response(json!({"data": {"customerAdministration": {
+                    "systemIdentityGroups": {"items": items}
+                }}}))
+            });
+    }
+
+    fn expect_deletion(mock_http_client: &mut MockHttpClient, success: bool) {
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| is_query(req, SYSTEM_IDENTITY_DELETE))
+            .returning(move |_| match success {
+                true => {
+                    json_response(json!({"data": {"systemIdentityDelete": {"id": "identity-1"}}}))
+                }
+                false => json_response(json!({"errors": [{"message": "not allowed"}]})),
+            });
+    }
+
+    #[test]
+    fn creates_l2_bootstrap_identity() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        let mut mock_http_client = MockHttpClient::default();
+        expect_creation(&mut mock_http_client, l2_credential());
+        expect_group_lookup(&mut mock_http_client, &["group-1"]);
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| is_query(req, SYSTEM_IDENTITY_ADD_TO_GROUPS))
+            .returning(|_| {
+                json_response(json!({"data": {"systemIdentityAddToGroups": {
+                    "systemIdentityGroups": [{"id": "group-1"}]
+                }}}))
+            });
+        let iam_client = HttpIAMClient::new(mock_http_client, metadata());
+
+        let identity = BootstrapIdentityCreator::new(
+            CreateCommand::new(&iam_client).with_key_type(KeyType::Ed25519),
+        )
+        .create_l2(
+            &OutputPlatform::LocalPrivateKeyPath(key_path.clone()),
+            &auth_credential(),
+        )
+        .unwrap();
+
+        assert_eq!(identity.id, "identity-1");
+        assert!(key_path.is_file());
+    }
+
+    #[test]
+    fn group_failure_deletes_identity_and_removes_key() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        let mut mock_http_client = MockHttpClient::default();
+        expect_creation(&mut mock_http_client, l2_credential());
+        // The NR Control Group is not found
+        expect_group_lookup(&mut mock_http_client, &[]);
+        expect_deletion(&mut mock_http_client, true);
+        let iam_client = HttpIAMClient::new(mock_http_client, metadata());
+
+        let create_command = CreateCommand::new(&iam_client)
+            .with_key_type(KeyType::Ed25519)
+            .with_certificate(Some(CertificateConfig::new(
+                CertificateSubject::common_name("bootstrap"),
+            )));
+        let result = BootstrapIdentityCreator::new(create_command).create_l2(
+            &OutputPlatform::LocalPrivateKeyPath(key_path.clone()),
+            &auth_credential(),
+        );
+
+        assert_matches!(result, Err(BootstrapError::GroupMembership(_)));
+        assert!(!key_path.exists());
+        assert!(!certificate_path(&key_path).exists());
+    }
+
+    #[test]
+    fn creation_failure_removes_key() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| is_query(req, SYSTEM_IDENTITY_CREATE))
+            .returning(|_| json_response(json!({"errors": [{"message": "forbidden"}]})));
+        let iam_client = HttpIAMClient::new(mock_http_client, metadata());
+
+        let result = BootstrapIdentityCreator::new(
+            CreateCommand::new(&iam_client).with_key_type(KeyType::Ed25519),
+        )
+        .create_l2(
+            &OutputPlatform::LocalPrivateKeyPath(key_path.clone()),
+            &auth_credential(),
+        );
+
+        assert_matches!(result, Err(BootstrapError::Creation(_)));
+        assert!(!key_path.exists());
+    }
+
+    #[test]
+    fn failed_rollback_is_reported() {
+        let mut mock_http_client = MockHttpClient::default();
+        expect_creation(&mut mock_http_client, l1_credential());
+        expect_group_lookup(&mut mock_http_client, &[]);
+        expect_deletion(&mut mock_http_client, false);
+        let iam_client = HttpIAMClient::new(mock_http_client, metadata());
+
+        let result = BootstrapIdentityCreator::new(CreateCommand::new(&iam_client))
+            .create_l1(&auth_credential());
+
+        assert_matches!(result, Err(BootstrapError::Rollback(error, failure)) => {
+            assert_matches!(*error, BootstrapError::GroupMembership(_));
+            assert!(failure.contains("identity-1"));
+        });
+    }
+}
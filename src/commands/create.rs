@@ -1,12 +1,22 @@
 use crate::http_client::HttpClient;
-use crate::key::generation::KeyType;
+use crate::key::certificate::{CertificateConfig, certificate_path};
+use crate::key::generation::{KeyType, PublicKeyPem};
+use crate::key::import::{import_key_file, import_key_pem};
 use crate::key::local::{LocalKeyPairGenerator, LocalKeyPairGeneratorConfig};
+#[cfg(feature = "pkcs11")]
+use crate::key::pkcs11::Pkcs11KeyPairGenerator;
+use crate::key::secret::SecretSource;
 use crate::system_identity::SystemIdentity;
 use crate::system_identity::iam_client::http::HttpIAMClient;
 use crate::system_identity::iam_client::http::IAMAuthCredential;
 use crate::system_identity::identity_creator::{L1IdentityCreator, L2IdentityCreator};
 use crate::system_identity::input_data::output_platform::OutputPlatform;
+use crate::system_identity::query::{ExistingSystemIdentity, SystemIdentityInfo};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
+use tracing::{debug, warn};
 
 #[derive(Error, Debug)]
 pub enum CreateError {
@@ -14,11 +24,24 @@ pub enum CreateError {
     CreateError(String),
 }
 
+/// Outcome of the `ensure_*` methods of [`CreateCommand`], serialized as the identity itself.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum EnsuredIdentity {
+    /// No identity with the name and type existed, so it was created.
+    Created(SystemIdentity),
+    /// The identity already existed. Its credentials cannot be read back.
+    Existing(SystemIdentityInfo),
+}
+
 pub struct CreateCommand<'a, C>
 where
     C: HttpClient,
 {
     iam_client: &'a HttpIAMClient<C>,
+    key_type: KeyType,
+    key_passphrase: Option<SecretSource>,
+    certificate: Option<CertificateConfig>,
 }
 
 impl<'a, C> CreateCommand<'a, C>
@@ -26,12 +49,39 @@ where
     C: HttpClient,
 {
     pub fn new(iam_client: &'a HttpIAMClient<C>) -> Self {
-        Self { iam_client }
+        Self {
+            iam_client,
+            key_type: KeyType::default(),
+            key_passphrase: None,
+            certificate: None,
+        }
+    }
+
+    /// Sets the type of the key generated for L2 identities (RSA-4096 by default).
+    pub fn with_key_type(self, key_type: KeyType) -> Self {
+        Self { key_type, ..self }
+    }
+
+    /// Encrypts the private keys stored in local files with the given passphrase.
+    pub fn with_key_passphrase(self, key_passphrase: Option<SecretSource>) -> Self {
+        Self {
+            key_passphrase,
+            ..self
+        }
+    }
+
+    /// Writes a self-signed certificate of the L2 key next to the private key. Only supported for
+    /// keys stored in local files.
+    pub fn with_certificate(self, certificate: Option<CertificateConfig>) -> Self {
+        Self {
+            certificate,
+            ..self
+        }
     }
 
     /// Create L1 identity using IAMAuthCredential (supports both Bearer token and API key)
     pub fn create_l1_with_credential(
-        self,
+        &self,
         auth_credentials: &IAMAuthCredential,
     ) -> Result<SystemIdentity, CreateError> {
         self.iam_client
@@ -39,29 +89,255 @@ where
             .map_err(|e| CreateError::CreateError(e.to_string()))
     }
 
-    /// Create L2 identity using IAMAuthCredential (supports both Bearer token and API key)
+    /// Create L2 identity using IAMAuthCredential (supports both Bearer token and API key).
+    /// The generated key is removed if the identity cannot be created, so that creating it can be
+    /// retried with the same output platform.
     pub fn create_l2_with_credential(
-        self,
+        &self,
         output_platform: &OutputPlatform,
         auth_credentials: &IAMAuthCredential,
     ) -> Result<SystemIdentity, CreateError> {
-        let output_key_path = match output_platform {
-            OutputPlatform::LocalPrivateKeyPath(path) => path,
+        let (pub_key, generated_key) = self.generate_key(output_platform)?;
+        self.create_l2_with_public_key(&pub_key, auth_credentials)
+            .map_err(
+                |CreateError::CreateError(error)| match generated_key.remove() {
+                    Ok(()) => CreateError::CreateError(error),
+                    Err(e) => {
+                        warn!("unable to remove the key generated for the identity: {e}");
+                        CreateError::CreateError(format!(
+                            "{error}; removing the generated key: {e}"
+                        ))
+                    }
+                },
+            )
+    }
+
+    /// Generates the key of a new L2 identity in the output platform, returning its public key
+    /// and where it was stored.
+    pub(crate) fn generate_key(
+        &self,
+        output_platform: &OutputPlatform,
+    ) -> Result<(PublicKeyPem, GeneratedKey), CreateError> {
+        match output_platform {
+            OutputPlatform::LocalPrivateKeyPath(path) => {
+                let generator = LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
+                    key_type: self.key_type,
+                    file_path: path.to_path_buf(),
+                });
+                let generator = match &self.key_passphrase {
+                    Some(passphrase) => generator.with_passphrase(passphrase.clone()),
+                    None => generator,
+                };
+                let pub_key = match &self.certificate {
+                    Some(certificate) => generator.with_certificate(certificate.clone()),
+                    None => generator,
+                }
+                .generate()
+                .map_err(|e| CreateError::CreateError(e.to_string()))?;
+                let generated_key = GeneratedKey::Local {
+                    key_path: path.to_path_buf(),
+                    certificate_path: self.certificate.as_ref().map(|_| certificate_path(path)),
+                };
+                Ok((pub_key, generated_key))
+            }
+            #[cfg(feature = "pkcs11")]
+            OutputPlatform::Pkcs11Token(_) if self.certificate.is_some() => {
+                Err(CreateError::CreateError(
+                    "certificates are only supported for local private keys".to_string(),
+                ))
+            }
+            #[cfg(feature = "pkcs11")]
+            OutputPlatform::Pkcs11Token(config) => {
+                let generator = Pkcs11KeyPairGenerator::new(self.key_type, config.clone());
+                let pub_key = generator
+                    .generate()
+                    .map_err(|e| CreateError::CreateError(e.to_string()))?;
+                Ok((pub_key, GeneratedKey::Pkcs11(generator)))
+            }
         }
-        .to_path_buf();
+    }
 
-        let key_creator = LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
-            key_type: KeyType::Rsa4096,
-            file_path: output_key_path,
-        });
+    pub(crate) fn iam_client(&self) -> &'a HttpIAMClient<C> {
+        self.iam_client
+    }
 
-        let pub_key = key_creator
-            .generate()
+    /// Create L2 identity for the existing PEM key at `key_path` (private or public). Encrypted
+    /// private keys are decrypted with the key passphrase.
+    pub fn create_l2_with_key_file(
+        &self,
+        key_path: &Path,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentity, CreateError> {
+        let imported = import_key_file(key_path, self.key_passphrase.as_ref())
             .map_err(|e| CreateError::CreateError(e.to_string()))?;
+        self.create_l2_with_public_key(&imported.public_key, auth_credentials)
+    }
+
+    /// Create L2 identity for an existing key, given its public key in PEM format (see
+    /// [`import_key_file`](crate::key::import::import_key_file) to derive it from a private key).
+    pub fn create_l2_with_public_key(
+        &self,
+        pub_key: &[u8],
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentity, CreateError> {
         self.iam_client
-            .create_l2_system_identity(auth_credentials, pub_key.as_slice())
+            .create_l2_system_identity(auth_credentials, pub_key)
             .map_err(|e| CreateError::CreateError(e.to_string()))
     }
+
+    /// Returns the L1 identity with the configured name if it exists, creating it otherwise.
+    pub fn ensure_l1_with_credential(
+        &self,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<EnsuredIdentity, CreateError> {
+        match self.find_existing(false, auth_credentials)? {
+            Some(existing) => Ok(EnsuredIdentity::Existing(existing.identity)),
+            None => self
+                .create_l1_with_credential(auth_credentials)
+                .map(EnsuredIdentity::Created),
+        }
+    }
+
+    /// Returns the L2 identity with the configured name if it exists, creating it (and its key)
+    /// otherwise. The public key of an existing identity must match the key already stored in the
+    /// output platform.
+    pub fn ensure_l2_with_credential(
+        &self,
+        output_platform: &OutputPlatform,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<EnsuredIdentity, CreateError> {
+        let Some(existing) = self.find_existing(true, auth_credentials)? else {
+            return self
+                .create_l2_with_credential(output_platform, auth_credentials)
+                .map(EnsuredIdentity::Created);
+        };
+        let pub_key = match output_platform {
+            OutputPlatform::LocalPrivateKeyPath(path) => {
+                import_key_file(path, self.key_passphrase.as_ref())
+                    .map_err(|e| {
+                        CreateError::CreateError(format!(
+                            "system identity already exists, but its key `{}` cannot be read: {e}",
+                            path.display()
+                        ))
+                    })?
+                    .public_key
+            }
+            #[cfg(feature = "pkcs11")]
+            OutputPlatform::Pkcs11Token(_) => {
+                return Err(CreateError::CreateError(
+                    "existing identities can only be verified against local private keys"
+                        .to_string(),
+                ));
+            }
+        };
+        verify_public_key(existing, &pub_key)
+    }
+
+    /// Returns the L2 identity with the configured name if it exists, creating it for the
+    /// existing PEM key at `key_path` otherwise. The public key of an existing identity must match
+    /// the key.
+    pub fn ensure_l2_with_key_file(
+        &self,
+        key_path: &Path,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<EnsuredIdentity, CreateError> {
+        let imported = import_key_file(key_path, self.key_passphrase.as_ref())
+            .map_err(|e| CreateError::CreateError(e.to_string()))?;
+        match self.find_existing(true, auth_credentials)? {
+            Some(existing) => verify_public_key(existing, &imported.public_key),
+            None => self
+                .create_l2_with_public_key(&imported.public_key, auth_credentials)
+                .map(EnsuredIdentity::Created),
+        }
+    }
+
+    /// Finds the identity with the configured name and type (L1 or L2), failing if there are
+    /// several, as it would be unclear which one to keep.
+    fn find_existing(
+        &self,
+        l2: bool,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<Option<ExistingSystemIdentity>, CreateError> {
+        let name = self.iam_client.metadata().name.as_deref().ok_or_else(|| {
+            CreateError::CreateError(
+                "a name is required to look up an existing identity".to_string(),
+            )
+        })?;
+        let mut identities = self
+            .iam_client
+            .find_system_identities_by_name(name, auth_credentials)
+            .map_err(|e| CreateError::CreateError(e.to_string()))?;
+        identities.retain(|identity| identity.is_l2() == l2);
+        match identities.len() {
+            0 | 1 => Ok(identities.pop()),
+            found => Err(CreateError::CreateError(format!(
+                "{found} system identities named `{name}` already exist"
+            ))),
+        }
+    }
+}
+
+/// Checks the public key is one of the keys registered for the existing L2 identity.
+fn verify_public_key(
+    existing: ExistingSystemIdentity,
+    pub_key: &[u8],
+) -> Result<EnsuredIdentity, CreateError> {
+    let fingerprint = |key: &[u8]| import_key_pem(key, None).map(|key| key.info.fingerprint);
+    let local_fingerprint =
+        fingerprint(pub_key).map_err(|e| CreateError::CreateError(e.to_string()))?;
+    let matches = existing
+        .public_keys
+        .iter()
+        .any(|key| fingerprint(key).is_ok_and(|registered| registered == local_fingerprint));
+    if !matches {
+        return Err(CreateError::CreateError(format!(
+            "system identity `{}` already exists with a different public key than the local one",
+            existing.identity.id
+        )));
+    }
+    Ok(EnsuredIdentity::Existing(existing.identity))
+}
+
+/// Key generated for a new L2 identity, which can be removed if the identity cannot be created.
+pub(crate) enum GeneratedKey {
+    Local {
+        key_path: PathBuf,
+        certificate_path: Option<PathBuf>,
+    },
+    #[cfg(feature = "pkcs11")]
+    Pkcs11(Pkcs11KeyPairGenerator),
+}
+
+impl GeneratedKey {
+    /// Removes the key, reporting every part that could not be removed.
+    pub(crate) fn remove(&self) -> Result<(), String> {
+        match self {
+            Self::Local {
+                key_path,
+                certificate_path,
+            } => {
+                // Every file is removed even if a previous one could not be.
+                let failures = std::iter::once(key_path)
+                    .chain(certificate_path)
+                    .filter_map(|path| {
+                        debug!("removing {}", path.display());
+                        fs::remove_file(path)
+                            .err()
+                            .map(|e| format!("removing {}: {e}", path.display()))
+                    })
+                    .collect::<Vec<_>>();
+                if failures.is_empty() {
+                    Ok(())
+                } else {
+                    Err(failures.join("; "))
+                }
+            }
+            #[cfg(feature = "pkcs11")]
+            Self::Pkcs11(generator) => generator
+                .remove()
+                .map_err(|e| format!("removing the key pair from the PKCS#11 token: {e}")),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -69,12 +345,18 @@ mod tests {
     use super::*;
     use crate::commands::create::CreateError::CreateError;
     use crate::http_client::tests::MockHttpClient;
+    use crate::jwt::signer::local::LocalPrivateKeySigner;
+    use crate::jwt::signer::local::test::RS256_PRIVATE_KEY;
+    use crate::key::generation::generate_key_pair;
     use crate::system_identity::iam_client::http::IAMAuthCredential;
     use crate::system_identity::input_data::SystemIdentityCreationMetadata;
     use crate::system_identity::input_data::environment::NewRelicEnvironment;
     use crate::system_identity::input_data::output_platform::OutputPlatform;
     use crate::system_identity::{ClientSecret, SystemIdentityType};
+    use assert_matches::assert_matches;
+    use base64::{Engine, engine::general_purpose};
     use http::Response;
+    use serde_json::json;
     use tempfile::tempdir;
 
     fn create_test_metadata(name: Option<String>) -> SystemIdentityCreationMetadata {
@@ -193,6 +475,65 @@ mod tests {
         assert_eq!(result.unwrap(), expected_identity);
     }
     #[test]
+    fn test_create_l2_system_identity_with_key_type() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("test-key");
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let output_platform = OutputPlatform::LocalPrivateKeyPath(key_path.clone());
+
+        let response = r#"
+        {
+          "data": {
+            "systemIdentityCreate": {
+              "clientId": "client-abc-789",
+              "publicKey": "cHVibGljS2V5QmFzZTY0RW5jb2RlZFN0cmluZw==",
+              "id": "identity-123",
+              "name": "test-identity",
+              "organizationId": "org-xyz-456"
+            }
+          }
+        }
+        "#;
+        let mock_http_client = setup_mock_http_client(response);
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+        let command = CreateCommand::new(iam_client).with_key_type(KeyType::Ed25519);
+
+        let result =
+            command.create_l2_with_credential(&output_platform, &dummy_bearer_credential());
+        assert!(result.is_ok());
+        // The generated key is immediately usable for signing
+        assert!(LocalPrivateKeySigner::try_from(key_path.as_path()).is_ok());
+    }
+
+    #[test]
+    fn test_create_l2_system_identity_with_imported_key() {
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let imported = import_key_pem(RS256_PRIVATE_KEY.as_bytes(), None).unwrap();
+        let expected_pub_key = general_purpose::STANDARD.encode(&imported.public_key);
+
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(move |req| {
+                String::from_utf8_lossy(req.body()).contains(&expected_pub_key)
+            })
+            .returning(|_| {
+                Ok(Response::builder()
+                    .status(200)
+                    .body(
+                        br#"{"data":{"systemIdentityCreate":{"clientId":"client-abc-789","publicKey":"cHVibGljS2V5","id":"identity-123","organizationId":"org-xyz-456"}}}"#
+                            .to_vec(),
+                    )
+                    .unwrap())
+            });
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+
+        let result = CreateCommand::new(iam_client)
+            .create_l2_with_public_key(&imported.public_key, &dummy_bearer_credential());
+        assert!(result.is_ok());
+    }
+    #[test]
     fn test_create_l2_system_identity_malformed_response() {
         let tmp_dir = tempdir().unwrap();
         let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
@@ -313,4 +654,261 @@ mod tests {
 
         assert_eq!(result.unwrap(), expected_identity);
     }
+
+    /// Response listing the identities named `l2_identity_test`. L2 ones have a public key, which
+    /// is only registered if not empty.
+    fn existing_identities_response(identities: &[(&str, Option<&[u8]>)]) -> Vec<u8> {
+        let items = identities
+            .iter()
+            .map(|(id, public_key)| {
+                let public_keys = public_key
+                    .filter(|key| !key.is_empty())
+                    .map(|key| vec![json!({"publicKey": general_purpose::STANDARD.encode(key)})])
+                    .unwrap_or_default();
+                let credential_type = match public_key {
+                    Some(_) => "PUBLIC_KEY",
+                    None => "CLIENT_SECRET",
+                };
+                json!({
+                    "id": id,
+                    "name": "l2_identity_test",
+                    "clientId": format!("client-{id}"),
+                    "organizationId": "org-id",
+                    "credentialType": credential_type,
+                    "publicKeys": public_keys,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_vec(
+            &json!({"data": {"customerAdministration": {"systemIdentities": {
+                "items": items,
+                "nextCursor": null,
+            }}}}),
+        )
+        .unwrap()
+    }
+
+    fn mock_existing_identities(identities: &[(&str, Option<&[u8]>)]) -> MockHttpClient {
+        let response = existing_identities_response(identities);
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client.expect_send().once().returning(move |_| {
+            Ok(Response::builder()
+                .status(200)
+                .body(response.clone())
+                .unwrap())
+        });
+        mock_http_client
+    }
+
+    #[test]
+    fn test_ensure_existing_l1_identity() {
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let public_key = import_key_pem(RS256_PRIVATE_KEY.as_bytes(), None)
+            .unwrap()
+            .public_key;
+        // Only the L1 identity has the requested type.
+        let mock_http_client = mock_existing_identities(&[
+            ("identity-l2", Some(public_key.as_slice())),
+            ("identity-l1", None),
+        ]);
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+
+        let result = CreateCommand::new(iam_client)
+            .ensure_l1_with_credential(&dummy_bearer_credential())
+            .unwrap();
+        assert_matches!(result, EnsuredIdentity::Existing(identity) => {
+            assert_eq!(identity.id, "identity-l1");
+        });
+    }
+
+    #[test]
+    fn test_ensure_l1_identity_creates_missing_identity() {
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let public_key = import_key_pem(RS256_PRIVATE_KEY.as_bytes(), None)
+            .unwrap()
+            .public_key;
+        let mut mock_http_client =
+            mock_existing_identities(&[("identity-l2", Some(public_key.as_slice()))]);
+        mock_http_client.expect_send().once().returning(|_| {
+            Ok(Response::builder()
+                .status(200)
+                .body(
+                    br#"{"data":{"systemIdentityCreate":{"clientId":"client-1","id":"identity-1","organizationId":"org-id","clientSecret":"secret","credentialExpiration":"2026-12-31T00:00:00Z"}}}"#
+                        .to_vec(),
+                )
+                .unwrap())
+        });
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+
+        let result = CreateCommand::new(iam_client)
+            .ensure_l1_with_credential(&dummy_bearer_credential())
+            .unwrap();
+        assert_matches!(result, EnsuredIdentity::Created(identity) => {
+            assert_eq!(identity.id, "identity-1");
+        });
+    }
+
+    #[test]
+    fn test_ensure_existing_l2_identity_with_key_file() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        std::fs::write(&key_path, RS256_PRIVATE_KEY).unwrap();
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let public_key = import_key_pem(RS256_PRIVATE_KEY.as_bytes(), None)
+            .unwrap()
+            .public_key;
+        let mock_http_client =
+            mock_existing_identities(&[("identity-l2", Some(public_key.as_slice()))]);
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+
+        let result = CreateCommand::new(iam_client)
+            .ensure_l2_with_key_file(&key_path, &dummy_bearer_credential())
+            .unwrap();
+        assert_matches!(result, EnsuredIdentity::Existing(identity) => {
+            assert_eq!(identity.id, "identity-l2");
+        });
+    }
+
+    #[test]
+    fn test_ensure_existing_l2_identity_with_other_key() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        std::fs::write(&key_path, RS256_PRIVATE_KEY).unwrap();
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let other_key = generate_key_pair(&KeyType::Ed25519).unwrap().public_key;
+        let mock_http_client =
+            mock_existing_identities(&[("identity-l2", Some(other_key.as_slice()))]);
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+
+        let result = CreateCommand::new(iam_client)
+            .ensure_l2_with_key_file(&key_path, &dummy_bearer_credential());
+        assert_matches!(result, Err(CreateError(e)) if e.contains("different public key"));
+    }
+
+    #[test]
+    fn test_ensure_existing_l2_identity_without_registered_keys() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        std::fs::write(&key_path, RS256_PRIVATE_KEY).unwrap();
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        // The identity is still L2, so no duplicate is created for it.
+        let mock_http_client = mock_existing_identities(&[("identity-l2", Some(&[]))]);
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+
+        let result = CreateCommand::new(iam_client)
+            .ensure_l2_with_key_file(&key_path, &dummy_bearer_credential());
+        assert_matches!(result, Err(CreateError(e)) if e.contains("different public key"));
+    }
+
+    #[test]
+    fn test_ensure_l2_identity_keeps_local_key_of_existing_identity() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        std::fs::write(&key_path, RS256_PRIVATE_KEY).unwrap();
+        let output_platform = OutputPlatform::LocalPrivateKeyPath(key_path.clone());
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let public_key = import_key_pem(RS256_PRIVATE_KEY.as_bytes(), None)
+            .unwrap()
+            .public_key;
+        let mock_http_client =
+            mock_existing_identities(&[("identity-l2", Some(public_key.as_slice()))]);
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+
+        let result = CreateCommand::new(iam_client)
+            .ensure_l2_with_credential(&output_platform, &dummy_bearer_credential())
+            .unwrap();
+        assert_matches!(result, EnsuredIdentity::Existing(_));
+        assert_eq!(
+            std::fs::read_to_string(&key_path).unwrap(),
+            RS256_PRIVATE_KEY
+        );
+    }
+
+    #[test]
+    fn test_ensure_l2_identity_after_failed_creation() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        let output_platform = OutputPlatform::LocalPrivateKeyPath(key_path.clone());
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let no_identities = existing_identities_response(&[]);
+        let created = br#"{"data":{"systemIdentityCreate":{"clientId":"client-1","publicKey":"cHVibGljS2V5","id":"identity-1","organizationId":"org-id"}}}"#;
+
+        let mut mock_http_client = MockHttpClient::default();
+        let mut sequence = mockall::Sequence::new();
+        for creation in [None, Some(created.to_vec())] {
+            let no_identities = no_identities.clone();
+            mock_http_client
+                .expect_send()
+                .once()
+                .in_sequence(&mut sequence)
+                .returning(move |_| {
+                    Ok(Response::builder()
+                        .status(200)
+                        .body(no_identities.clone())
+                        .unwrap())
+                });
+            mock_http_client
+                .expect_send()
+                .once()
+                .in_sequence(&mut sequence)
+                .returning(move |_| {
+                    Ok(match &creation {
+                        Some(body) => Response::builder().status(200).body(body.clone()),
+                        None => Response::builder().status(500).body(b"error".to_vec()),
+                    }
+                    .unwrap())
+                });
+        }
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+        let command = CreateCommand::new(iam_client).with_key_type(KeyType::EcdsaP256);
+
+        let result =
+            command.ensure_l2_with_credential(&output_platform, &dummy_bearer_credential());
+        assert_matches!(result, Err(CreateError(_)));
+        assert!(!key_path.exists());
+
+        // Re-running the same creation succeeds, as the failed attempt left no key behind.
+        let result = command
+            .ensure_l2_with_credential(&output_platform, &dummy_bearer_credential())
+            .unwrap();
+        assert_matches!(result, EnsuredIdentity::Created(identity) => {
+            assert_eq!(identity.id, "identity-1");
+        });
+        assert!(key_path.is_file());
+    }
+
+    #[test]
+    fn test_ensure_identity_with_duplicates() {
+        let metadata = create_test_metadata(Some("l2_identity_test".to_string()));
+        let mock_http_client =
+            mock_existing_identities(&[("identity-1", None), ("identity-2", None)]);
+        let iam_client = &HttpIAMClient::new(mock_http_client, metadata);
+
+        let result =
+            CreateCommand::new(iam_client).ensure_l1_with_credential(&dummy_bearer_credential());
+        assert_matches!(result, Err(CreateError(e)) if e.contains("2 system identities"));
+    }
+
+    #[test]
+    fn test_generated_key_removal_continues_after_failures() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("private_key.pem");
+        let certificate_path = tmp_dir.path().join("private_key.crt");
+        std::fs::write(&certificate_path, "certificate").unwrap();
+        // The key is already gone, but the certificate is still removed.
+        let generated_key = GeneratedKey::Local {
+            key_path: key_path.clone(),
+            certificate_path: Some(certificate_path.clone()),
+        };
+
+        let result = generated_key.remove();
+        assert_matches!(result, Err(e) if e.contains(&key_path.display().to_string()));
+        assert!(!certificate_path.exists());
+
+        let generated_key = GeneratedKey::Local {
+            key_path: key_path.clone(),
+            certificate_path: Some(tmp_dir.path().join("missing.crt")),
+        };
+        assert_matches!(generated_key.remove(), Err(e) if e.split("; ").count() == 2);
+    }
 }
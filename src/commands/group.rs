@@ -0,0 +1,48 @@
+use crate::commands::table::render_table;
+use crate::system_identity::group::SystemIdentityGroup;
+
+/// Renders the groups as a table with their id, name and description.
+pub fn groups_table(groups: &[SystemIdentityGroup]) -> String {
+    let rows = groups
+        .iter()
+        .map(|group| {
+            [
+                group.id.clone(),
+                group.name.clone(),
+                group.description.clone().unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    render_table(["ID", "NAME", "DESCRIPTION"], &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_as_table() {
+        let groups = [
+            SystemIdentityGroup {
+                id: "group-1".to_string(),
+                name: "NR Control Group".to_string(),
+                organization_id: "org".to_string(),
+                description: Some("Creates identities".to_string()),
+            },
+            SystemIdentityGroup {
+                id: "group-22".to_string(),
+                name: "hosts".to_string(),
+                organization_id: "org".to_string(),
+                description: None,
+            },
+        ];
+        assert_eq!(
+            groups_table(&groups),
+            "\
+ID        NAME              DESCRIPTION
+group-1   NR Control Group  Creates identities
+group-22  hosts
+"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+
+use crate::commands::table::render_table;
+use crate::system_identity::query::SystemIdentityInfo;
+
+/// Renders the identities as a table with their id, name, description, client id, creation and
+/// last use time.
+pub fn identities_table(identities: &[SystemIdentityInfo]) -> String {
+    let rows = identities
+        .iter()
+        .map(|identity| {
+            [
+                identity.id.clone(),
+                identity.name.clone().unwrap_or_default(),
+                identity.description.clone().unwrap_or_default(),
+                identity.client_id.clone(),
+                format_time(identity.created_at),
+                format_time(identity.last_used_at),
+            ]
+        })
+        .collect::<Vec<_>>();
+    render_table(
+        [
+            "ID",
+            "NAME",
+            "DESCRIPTION",
+            "CLIENT ID",
+            "CREATED AT",
+            "LAST USED AT",
+        ],
+        &rows,
+    )
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.to_rfc3339()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identities_as_table() {
+        let identities = [
+            SystemIdentityInfo {
+                id: "identity-1".to_string(),
+                name: Some("host-a".to_string()),
+                client_id: "client-1".to_string(),
+                organization_id: "org".to_string(),
+                created_at: Some("2026-03-01T10:00:00Z".parse().unwrap()),
+                last_used_at: None,
+                description: Some("edge host".to_string()),
+                tags: Vec::new(),
+            },
+            SystemIdentityInfo {
+                id: "identity-22".to_string(),
+                name: None,
+                client_id: "client-22".to_string(),
+                organization_id: "org".to_string(),
+                created_at: None,
+                last_used_at: Some("2026-04-01T08:30:00Z".parse().unwrap()),
+                description: None,
+                tags: Vec::new(),
+            },
+        ];
+        assert_eq!(
+            identities_table(&identities),
+            "\
+ID           NAME    DESCRIPTION  CLIENT ID  CREATED AT                 LAST USED AT
+identity-1   host-a  edge host    client-1   2026-03-01T10:00:00+00:00
+identity-22                       client-22                             2026-04-01T08:30:00+00:00
+"
+        );
+    }
+}
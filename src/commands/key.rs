@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::key::import::{KeyImportError, KeyInfo, import_key_file};
+use crate::key::jwk::{JwkExportError, jwk_from_pem_file, jwks};
+use crate::key::secret::SecretSource;
+
+#[derive(Error, Debug)]
+pub enum KeyCommandError {
+    #[error("exporting key `{0}`: `{1}`")]
+    Export(PathBuf, JwkExportError),
+    #[error("validating key `{0}`: `{1}`")]
+    Validate(PathBuf, KeyImportError),
+    #[error("serializing JWKS: `{0}`")]
+    Serialize(#[from] serde_json::Error),
+    #[error("writing output: `{0}`")]
+    Write(#[from] std::io::Error),
+}
+
+/// Exports the public part of the PEM keys as a JWKS document. The document is written to
+/// `output_path` when set and returned otherwise.
+pub fn export_jwks(
+    key_paths: &[PathBuf],
+    output_path: Option<&Path>,
+) -> Result<Option<String>, KeyCommandError> {
+    let keys = key_paths
+        .iter()
+        .map(|path| jwk_from_pem_file(path).map_err(|e| KeyCommandError::Export(path.clone(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let document = serde_json::to_string_pretty(&jwks(keys))?;
+
+    match output_path {
+        Some(path) => {
+            fs::write(path, document)?;
+            Ok(None)
+        }
+        None => Ok(Some(document)),
+    }
+}
+
+/// Validates the PEM key at `key_path` (private or public) and reports its algorithm, size and
+/// fingerprint.
+pub fn validate_key(
+    key_path: &Path,
+    passphrase: Option<&SecretSource>,
+) -> Result<KeyInfo, KeyCommandError> {
+    import_key_file(key_path, passphrase)
+        .map(|imported| imported.info)
+        .map_err(|e| KeyCommandError::Validate(key_path.to_path_buf(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::jwk::JwkSet;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::jwt::signer::local::test::{RS256_PRIVATE_KEY, RS256_PUBLIC_KEY};
+
+    #[test]
+    fn export_jwks_of_several_keys() {
+        let dir = tempdir().unwrap();
+        let public_key_path = dir.path().join("key.pub");
+        fs::write(&public_key_path, RS256_PUBLIC_KEY).unwrap();
+        let ec_key = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let private_key_path = dir.path().join("ec-key");
+        fs::write(&private_key_path, ec_key.serialize_pem()).unwrap();
+
+        let document = export_jwks(&[public_key_path.clone(), private_key_path], None)
+            .unwrap()
+            .unwrap();
+        let jwks: JwkSet = serde_json::from_str(&document).unwrap();
+        assert_eq!(jwks.keys.len(), 2);
+        assert!(!document.contains(RS256_PRIVATE_KEY));
+
+        let output_path = dir.path().join("jwks.json");
+        assert!(
+            export_jwks(&[public_key_path], Some(&output_path))
+                .unwrap()
+                .is_none()
+        );
+        let jwks: JwkSet = serde_json::from_slice(&fs::read(output_path).unwrap()).unwrap();
+        assert_eq!(jwks.keys.len(), 1);
+    }
+
+    #[test]
+    fn export_jwks_missing_file() {
+        let result = export_jwks(&[PathBuf::from("/non/existing/key")], None);
+        assert!(matches!(result, Err(KeyCommandError::Export(_, _))));
+    }
+
+    #[test]
+    fn validate_keys() {
+        let dir = tempdir().unwrap();
+        let key_path = dir.path().join("key");
+        fs::write(&key_path, RS256_PRIVATE_KEY).unwrap();
+
+        let info = validate_key(&key_path, None).unwrap();
+        assert_eq!(info.size, 2048);
+        assert!(info.private);
+
+        fs::write(&key_path, "not a key").unwrap();
+        assert!(matches!(
+            validate_key(&key_path, None),
+            Err(KeyCommandError::Validate(_, _))
+        ));
+    }
+}
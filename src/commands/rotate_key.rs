@@ -0,0 +1,636 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, engine::general_purpose};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::authenticator::Authenticator;
+use crate::jwt::signer::JwtSignerImpl;
+use crate::jwt::signer::local::LocalPrivateKeySigner;
+use crate::key::certificate::certificate_path;
+use crate::key::generation::KeyType;
+use crate::key::import::import_key_file;
+use crate::key::local::{LocalKeyPairGenerator, LocalKeyPairGeneratorConfig};
+use crate::key::secret::SecretSource;
+use crate::system_identity::iam_client::http::IAMAuthCredential;
+use crate::system_identity::key_manager::L2KeyManager;
+use crate::token_retriever::TokenRetrieverWithCache;
+use crate::{ClientID, TokenRetriever};
+
+#[derive(Error, Debug)]
+pub enum RotateKeyError {
+    #[error("generating the new key: `{0}`")]
+    KeyGeneration(String),
+    #[error("registering the new public key: `{0}`")]
+    Registration(String),
+    #[error("retrieving a token with the new key: `{0}`")]
+    Verification(String),
+    #[error("replacing the private key file: `{0}`")]
+    Archive(String),
+    #[error("{0}; rolling back the rotation failed: `{1}`")]
+    Rollback(Box<RotateKeyError>, String),
+    #[error("revoking the previous key: `{0}`")]
+    Revocation(String),
+}
+
+/// Times a token is requested with the new key before rolling back the rotation.
+const DEFAULT_VERIFICATION_ATTEMPTS: u8 = 5;
+/// Wait before the second token request, doubled after each failed one.
+const DEFAULT_VERIFICATION_BACKOFF: Duration = Duration::from_secs(1);
+
+/// L2 System Identity whose key is rotated.
+#[derive(Debug, Clone)]
+pub struct RotatedIdentity {
+    pub identity_id: String,
+    pub client_id: ClientID,
+}
+
+/// Result of a successful key rotation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyRotation {
+    pub identity_id: String,
+    pub client_id: ClientID,
+    /// New public key in Base64 format, as registered for the identity.
+    pub pub_key: String,
+    /// Path the previous private key was moved to.
+    pub archived_key_path: PathBuf,
+    /// Path the certificate of the previous key was moved to, if there was one next to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived_certificate_path: Option<PathBuf>,
+    /// Whether the previous public key was removed from the identity.
+    pub old_key_revoked: bool,
+}
+
+/// Rotates the locally stored private key of an existing L2 System Identity.
+///
+/// The new key pair is generated next to the current key and its public key is registered for
+/// the identity. The private key file is only replaced, archiving the previous one, once a token
+/// has been obtained with the new key, retrying with backoff while the new public key propagates.
+/// A certificate of the previous key (see [`certificate_path`]) is archived along with it, as it
+/// does not certify the new key. Any failure rolls back the completed steps, leaving the previous
+/// key in place.
+///
+/// The previous public key stays registered unless [`RotateKeyCommand::with_revoke_old_key`] is
+/// set, so that processes still signing with the previous key keep working until they load the
+/// new one. Keeping it registered means the archived private key remains a valid credential until
+/// its public key is removed.
+pub struct RotateKeyCommand<'a, K, A>
+where
+    K: L2KeyManager,
+    A: Authenticator,
+{
+    key_manager: &'a K,
+    authenticator: A,
+    key_type: KeyType,
+    key_passphrase: Option<SecretSource>,
+    verification_attempts: u8,
+    verification_backoff: Duration,
+    revoke_old_key: bool,
+}
+
+impl<'a, K, A> RotateKeyCommand<'a, K, A>
+where
+    K: L2KeyManager,
+    A: Authenticator,
+{
+    pub fn new(key_manager: &'a K, authenticator: A) -> Self {
+        Self {
+            key_manager,
+            authenticator,
+            key_type: KeyType::default(),
+            key_passphrase: None,
+            verification_attempts: DEFAULT_VERIFICATION_ATTEMPTS,
+            verification_backoff: DEFAULT_VERIFICATION_BACKOFF,
+            revoke_old_key: false,
+        }
+    }
+
+    /// Sets the type of the new key (RSA-4096 by default).
+    pub fn with_key_type(self, key_type: KeyType) -> Self {
+        Self { key_type, ..self }
+    }
+
+    /// Passphrase the current private key is encrypted with. The new key is encrypted with it too.
+    pub fn with_key_passphrase(self, key_passphrase: Option<SecretSource>) -> Self {
+        Self {
+            key_passphrase,
+            ..self
+        }
+    }
+
+    /// Sets how many times a token is requested with the new key (at least once) and the wait
+    /// before the second request, doubled after each failed one.
+    pub fn with_verification_retries(self, attempts: u8, backoff: Duration) -> Self {
+        Self {
+            verification_attempts: attempts.max(1),
+            verification_backoff: backoff,
+            ..self
+        }
+    }
+
+    /// Removes the previous public key from the identity once the rotation succeeds, so the
+    /// archived private key can no longer be used to obtain tokens.
+    pub fn with_revoke_old_key(self, revoke_old_key: bool) -> Self {
+        Self {
+            revoke_old_key,
+            ..self
+        }
+    }
+
+    /// Rotates the private key stored at `key_path` for the given identity.
+    pub fn rotate(
+        self,
+        auth_credentials: &IAMAuthCredential,
+        identity: &RotatedIdentity,
+        key_path: &Path,
+    ) -> Result<KeyRotation, RotateKeyError> {
+        if !key_path.is_file() {
+            return Err(RotateKeyError::KeyGeneration(format!(
+                "current private key not found at {}",
+                key_path.display()
+            )));
+        }
+        let Self {
+            key_manager,
+            authenticator,
+            key_type,
+            key_passphrase,
+            verification_attempts,
+            verification_backoff,
+            revoke_old_key,
+        } = self;
+        // Derived before rotating, so an unreadable current key fails without changing anything.
+        let old_pub_key = if revoke_old_key {
+            let imported = import_key_file(key_path, key_passphrase.as_ref())
+                .map_err(|e| RotateKeyError::Revocation(e.to_string()))?;
+            Some(imported.public_key)
+        } else {
+            None
+        };
+        let new_key_path = sibling_path(key_path, "new");
+        let pub_key = generate_key(key_type, key_passphrase.as_ref(), &new_key_path)?;
+
+        debug!(
+            "registering new public key for identity {}",
+            identity.identity_id
+        );
+        if let Err(e) =
+            key_manager.add_l2_public_key(auth_credentials, &identity.identity_id, &pub_key)
+        {
+            remove_new_key(&new_key_path);
+            return Err(RotateKeyError::Registration(e.to_string()));
+        }
+
+        let rollback = |error: RotateKeyError| {
+            remove_new_key(&new_key_path);
+            match key_manager.remove_l2_public_key(
+                auth_credentials,
+                &identity.identity_id,
+                &pub_key,
+            ) {
+                Ok(()) => error,
+                Err(e) => RotateKeyError::Rollback(Box::new(error), e.to_string()),
+            }
+        };
+
+        let verification = verify(
+            authenticator,
+            key_passphrase.as_ref(),
+            &identity.client_id,
+            &new_key_path,
+            verification_attempts,
+            verification_backoff,
+        );
+        if let Err(error) = verification {
+            return Err(rollback(error));
+        }
+
+        let timestamp = unix_timestamp();
+        let archived_key_path = sibling_path(key_path, &format!("{timestamp}.old"));
+        debug!(
+            "archiving previous private key to {}",
+            archived_key_path.display()
+        );
+        if let Err(e) = fs::rename(key_path, &archived_key_path) {
+            return Err(rollback(RotateKeyError::Archive(e.to_string())));
+        }
+        let mut archived = vec![(key_path.to_path_buf(), archived_key_path.clone())];
+
+        // A certificate next to the key certifies the previous public key, so it is archived
+        // along with it.
+        let cert_path = certificate_path(key_path);
+        let archived_certificate_path = if cert_path.is_file() {
+            let archived_cert_path = sibling_path(&cert_path, &format!("{timestamp}.old"));
+            debug!(
+                "archiving previous certificate to {}",
+                archived_cert_path.display()
+            );
+            if let Err(e) = fs::rename(&cert_path, &archived_cert_path) {
+                return Err(restore(
+                    &archived,
+                    rollback(RotateKeyError::Archive(e.to_string())),
+                ));
+            }
+            archived.push((cert_path, archived_cert_path.clone()));
+            Some(archived_cert_path)
+        } else {
+            None
+        };
+
+        if let Err(e) = fs::rename(&new_key_path, key_path) {
+            return Err(restore(
+                &archived,
+                rollback(RotateKeyError::Archive(e.to_string())),
+            ));
+        }
+
+        if let Some(old_pub_key) = &old_pub_key {
+            debug!(
+                "removing previous public key from identity {}",
+                identity.identity_id
+            );
+            key_manager
+                .remove_l2_public_key(auth_credentials, &identity.identity_id, old_pub_key)
+                .map_err(|e| {
+                    RotateKeyError::Revocation(format!(
+                        "the key was rotated and the previous one archived at {}, but its public \
+                         key is still registered: {e}",
+                        archived_key_path.display()
+                    ))
+                })?;
+        }
+
+        Ok(KeyRotation {
+            identity_id: identity.identity_id.clone(),
+            client_id: identity.client_id.clone(),
+            pub_key: general_purpose::STANDARD.encode(&pub_key),
+            archived_key_path,
+            archived_certificate_path,
+            old_key_revoked: old_pub_key.is_some(),
+        })
+    }
+}
+
+fn generate_key(
+    key_type: KeyType,
+    passphrase: Option<&SecretSource>,
+    path: &Path,
+) -> Result<Vec<u8>, RotateKeyError> {
+    let generator = LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
+        key_type,
+        file_path: path.to_path_buf(),
+    });
+    match passphrase {
+        Some(passphrase) => generator.with_passphrase(passphrase.clone()),
+        None => generator,
+    }
+    .generate()
+    .map_err(|e| RotateKeyError::KeyGeneration(e.to_string()))
+}
+
+/// Obtains a token signing the client assertion with the new key. The token request is retried
+/// up to `attempts` times, as the server may not accept a just registered key right away.
+fn verify<A: Authenticator>(
+    authenticator: A,
+    passphrase: Option<&SecretSource>,
+    client_id: &ClientID,
+    new_key_path: &Path,
+    attempts: u8,
+    backoff: Duration,
+) -> Result<(), RotateKeyError> {
+    let signer = match passphrase {
+        Some(passphrase) => {
+            LocalPrivateKeySigner::from_file_with_passphrase(new_key_path, passphrase)
+        }
+        None => LocalPrivateKeySigner::try_from(new_key_path),
+    }
+    .map_err(|e| RotateKeyError::Verification(e.to_string()))?;
+
+    let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer(
+        client_id.clone(),
+        authenticator,
+        JwtSignerImpl::Local(signer),
+    );
+    let mut backoff = backoff;
+    let mut attempt = 1;
+    loop {
+        match token_retriever.retrieve() {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt >= attempts => {
+                return Err(RotateKeyError::Verification(e.to_string()));
+            }
+            Err(e) => {
+                debug!(
+                    "retrieving a token with the new key failed (attempt {attempt}/{attempts}), \
+                     retrying in {backoff:?}: {e}"
+                );
+                thread::sleep(backoff);
+                backoff = backoff.saturating_mul(2);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Moves the archived files back to their original paths, adding any failure to `error`.
+fn restore(archived: &[(PathBuf, PathBuf)], error: RotateKeyError) -> RotateKeyError {
+    let failures = archived
+        .iter()
+        .filter_map(|(path, archived_path)| {
+            fs::rename(archived_path, path).err().map(|e| {
+                format!(
+                    "restoring {} from {}: {e}",
+                    path.display(),
+                    archived_path.display()
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    if failures.is_empty() {
+        error
+    } else {
+        RotateKeyError::Rollback(Box::new(error), failures.join(", "))
+    }
+}
+
+/// Path in the same directory as `path`, with `suffix` appended to the file name.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{suffix}"));
+    path.with_file_name(file_name)
+}
+
+fn remove_new_key(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("unable to remove new private key {}: {e}", path.display());
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use tempfile::{TempDir, tempdir};
+
+    use super::*;
+    use crate::authenticator::test::MockAuthenticatorMock;
+    use crate::authenticator::{AuthenticateError, TokenRetrievalResponse};
+    use crate::key::pkcs8::is_encrypted_pem;
+    use crate::system_identity::identity_creator::tests::MockIAMClientError;
+    use crate::system_identity::key_manager::tests::MockL2KeyManager;
+
+    const OLD_KEY: &str = "old private key";
+
+    fn identity() -> RotatedIdentity {
+        RotatedIdentity {
+            identity_id: "identity-123".to_string(),
+            client_id: "client-id".to_string(),
+        }
+    }
+
+    fn credential() -> IAMAuthCredential {
+        IAMAuthCredential::BearerToken("token".to_string())
+    }
+
+    fn key_dir() -> (TempDir, PathBuf) {
+        let dir = tempdir().unwrap();
+        let key_path = dir.path().join("key");
+        fs::write(&key_path, OLD_KEY).unwrap();
+        (dir, key_path)
+    }
+
+    /// Authenticator answering each token request with the next of `accepted`.
+    fn authenticator(accepted: &[bool]) -> MockAuthenticatorMock {
+        let mut authenticator = MockAuthenticatorMock::default();
+        let mut sequence = mockall::Sequence::new();
+        for &succeeds in accepted {
+            authenticator
+                .expect_authenticate()
+                .once()
+                .in_sequence(&mut sequence)
+                .returning(move |_| {
+                    if succeeds {
+                        Ok(TokenRetrievalResponse {
+                            access_token: "access-token".to_string(),
+                            expires_in: 3600,
+                            token_type: "Bearer".to_string(),
+                        })
+                    } else {
+                        Err(AuthenticateError::HttpResponseError(
+                            401,
+                            "invalid_client".to_string(),
+                        ))
+                    }
+                });
+        }
+        authenticator
+    }
+
+    fn assert_not_rotated(dir: &TempDir, key_path: &Path) {
+        assert_eq!(fs::read_to_string(key_path).unwrap(), OLD_KEY);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn rotate_key() {
+        let (dir, key_path) = key_dir();
+        let mut key_manager = MockL2KeyManager::default();
+        key_manager
+            .expect_add_l2_public_key()
+            .once()
+            .withf(|_, identity_id, _| identity_id == "identity-123")
+            .returning(|_, _, _| Ok(()));
+        key_manager.expect_remove_l2_public_key().never();
+
+        let rotation = RotateKeyCommand::new(&key_manager, authenticator(&[true]))
+            .with_key_type(KeyType::EcdsaP256)
+            .with_key_passphrase(Some(SecretSource::Value("passphrase".to_string())))
+            .rotate(&credential(), &identity(), &key_path)
+            .unwrap();
+
+        assert_eq!(rotation.identity_id, "identity-123");
+        assert_eq!(
+            fs::read_to_string(&rotation.archived_key_path).unwrap(),
+            OLD_KEY
+        );
+        assert!(is_encrypted_pem(&fs::read(&key_path).unwrap()));
+        assert!(!sibling_path(&key_path, "new").exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn rotate_key_archives_the_certificate() {
+        let (dir, key_path) = key_dir();
+        let cert_path = certificate_path(&key_path);
+        fs::write(&cert_path, "old certificate").unwrap();
+        let mut key_manager = MockL2KeyManager::default();
+        key_manager
+            .expect_add_l2_public_key()
+            .once()
+            .returning(|_, _, _| Ok(()));
+
+        let rotation = RotateKeyCommand::new(&key_manager, authenticator(&[true]))
+            .with_key_type(KeyType::EcdsaP256)
+            .rotate(&credential(), &identity(), &key_path)
+            .unwrap();
+
+        let archived_cert_path = rotation.archived_certificate_path.unwrap();
+        assert_eq!(
+            fs::read_to_string(archived_cert_path).unwrap(),
+            "old certificate"
+        );
+        assert!(!cert_path.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn rotate_key_retries_verification_while_the_key_propagates() {
+        let (dir, key_path) = key_dir();
+        let mut key_manager = MockL2KeyManager::default();
+        key_manager
+            .expect_add_l2_public_key()
+            .once()
+            .returning(|_, _, _| Ok(()));
+        key_manager.expect_remove_l2_public_key().never();
+
+        let rotation = RotateKeyCommand::new(&key_manager, authenticator(&[false, false, true]))
+            .with_key_type(KeyType::EcdsaP256)
+            .with_verification_retries(3, Duration::from_millis(1))
+            .rotate(&credential(), &identity(), &key_path)
+            .unwrap();
+
+        assert!(!rotation.old_key_revoked);
+        assert_eq!(
+            fs::read_to_string(&rotation.archived_key_path).unwrap(),
+            OLD_KEY
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn rotate_key_revoking_the_old_key() {
+        let dir = tempdir().unwrap();
+        let key_path = dir.path().join("key");
+        let old_pub_key = LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
+            key_type: KeyType::EcdsaP256,
+            file_path: key_path.clone(),
+        })
+        .generate()
+        .unwrap();
+
+        let mut key_manager = MockL2KeyManager::default();
+        let mut sequence = mockall::Sequence::new();
+        key_manager
+            .expect_add_l2_public_key()
+            .once()
+            .in_sequence(&mut sequence)
+            .returning(|_, _, _| Ok(()));
+        key_manager
+            .expect_remove_l2_public_key()
+            .once()
+            .in_sequence(&mut sequence)
+            .withf(move |_, identity_id, pub_key| {
+                identity_id == "identity-123" && pub_key == old_pub_key.as_slice()
+            })
+            .returning(|_, _, _| Ok(()));
+
+        let rotation = RotateKeyCommand::new(&key_manager, authenticator(&[true]))
+            .with_key_type(KeyType::EcdsaP256)
+            .with_revoke_old_key(true)
+            .rotate(&credential(), &identity(), &key_path)
+            .unwrap();
+
+        assert!(rotation.old_key_revoked);
+        assert!(rotation.archived_key_path.is_file());
+    }
+
+    #[test]
+    fn rotate_key_registration_fails() {
+        let (dir, key_path) = key_dir();
+        let mut key_manager = MockL2KeyManager::default();
+        key_manager
+            .expect_add_l2_public_key()
+            .once()
+            .returning(|_, _, _| Err(MockIAMClientError));
+        key_manager.expect_remove_l2_public_key().never();
+
+        let result = RotateKeyCommand::new(&key_manager, MockAuthenticatorMock::default())
+            .with_key_type(KeyType::EcdsaP256)
+            .rotate(&credential(), &identity(), &key_path);
+
+        assert_matches!(result, Err(RotateKeyError::Registration(_)));
+        assert_not_rotated(&dir, &key_path);
+    }
+
+    #[test]
+    fn rotate_key_verification_fails() {
+        let (dir, key_path) = key_dir();
+        let mut key_manager = MockL2KeyManager::default();
+        key_manager
+            .expect_add_l2_public_key()
+            .once()
+            .returning(|_, _, _| Ok(()));
+        key_manager
+            .expect_remove_l2_public_key()
+            .once()
+            .withf(|_, identity_id, _| identity_id == "identity-123")
+            .returning(|_, _, _| Ok(()));
+
+        let result = RotateKeyCommand::new(&key_manager, authenticator(&[false, false]))
+            .with_key_type(KeyType::EcdsaP256)
+            .with_verification_retries(2, Duration::ZERO)
+            .rotate(&credential(), &identity(), &key_path);
+
+        assert_matches!(result, Err(RotateKeyError::Verification(_)));
+        assert_not_rotated(&dir, &key_path);
+    }
+
+    #[test]
+    fn rotate_key_rollback_fails() {
+        let (dir, key_path) = key_dir();
+        let mut key_manager = MockL2KeyManager::default();
+        key_manager
+            .expect_add_l2_public_key()
+            .once()
+            .returning(|_, _, _| Ok(()));
+        key_manager
+            .expect_remove_l2_public_key()
+            .once()
+            .returning(|_, _, _| Err(MockIAMClientError));
+
+        let result = RotateKeyCommand::new(&key_manager, authenticator(&[false, false]))
+            .with_key_type(KeyType::EcdsaP256)
+            .with_verification_retries(2, Duration::ZERO)
+            .rotate(&credential(), &identity(), &key_path);
+
+        assert_matches!(result, Err(RotateKeyError::Rollback(error, _)) => {
+            assert_matches!(*error, RotateKeyError::Verification(_));
+        });
+        assert_not_rotated(&dir, &key_path);
+    }
+
+    #[test]
+    fn rotate_key_without_current_key() {
+        let dir = tempdir().unwrap();
+        let key_manager = MockL2KeyManager::default();
+
+        let result = RotateKeyCommand::new(&key_manager, MockAuthenticatorMock::default()).rotate(
+            &credential(),
+            &identity(),
+            &dir.path().join("key"),
+        );
+
+        assert_matches!(result, Err(RotateKeyError::KeyGeneration(_)));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
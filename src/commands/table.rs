@@ -0,0 +1,42 @@
+//! Plain text tables for the CLI output.
+
+/// Renders `rows` as left-aligned columns under `headers`, separated by two spaces.
+pub(crate) fn render_table<const N: usize>(headers: [&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = headers.map(|header| header.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    let header_row = headers.map(String::from);
+    for row in std::iter::once(&header_row).chain(rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_aligned_columns() {
+        let table = render_table(
+            ["ID", "NAME"],
+            &[
+                ["1".to_string(), "agent ñ".to_string()],
+                ["1234".to_string(), String::new()],
+            ],
+        );
+        assert_eq!(table, "ID    NAME\n1     agent ñ\n1234\n");
+    }
+}
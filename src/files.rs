@@ -0,0 +1,195 @@
+//! Filesystem helpers for files holding secrets, such as private keys.
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+/// Owner and group applied to persisted private files. Unset ids are left unchanged.
+///
+/// Only supported on Unix platforms.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileOwner {
+    /// User id the file is assigned to.
+    pub uid: Option<u32>,
+    /// Group id the file is assigned to.
+    pub gid: Option<u32>,
+}
+
+/// Creates `dir` and any missing ancestors, restricting the new directories to the owner.
+pub(crate) fn create_private_dir_all(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(dir)
+}
+
+/// Atomically writes `content` to `path` with owner-only (`0600`) permissions.
+///
+/// The content is written and synced to a temporary file in the same directory, which is then
+/// renamed over `path`, so readers never observe a partially written file. On unix the parent
+/// directory is synced afterwards so the rename survives a crash.
+pub(crate) fn write_private_file(
+    path: &Path,
+    content: &[u8],
+    owner: Option<&FileOwner>,
+) -> io::Result<()> {
+    let (parent_dir, tmp_path) = temporary_path(path)?;
+    write_synced(&tmp_path, content, owner)
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&tmp_path);
+        })?;
+    sync_dir(parent_dir)
+}
+
+/// Same as [`write_private_file`], but fails with [`io::ErrorKind::AlreadyExists`] instead of
+/// replacing an existing file at `path`, even one created while the content is being written.
+///
+/// The temporary file is published with a hard link, which unlike a rename never replaces the
+/// target, and then removed.
+pub(crate) fn write_new_private_file(
+    path: &Path,
+    content: &[u8],
+    owner: Option<&FileOwner>,
+) -> io::Result<()> {
+    let (parent_dir, tmp_path) = temporary_path(path)?;
+    let published =
+        write_synced(&tmp_path, content, owner).and_then(|_| fs::hard_link(&tmp_path, path));
+    let removed = fs::remove_file(&tmp_path);
+    published?;
+    removed?;
+    sync_dir(parent_dir)
+}
+
+/// Returns the directory of `path` and a unique temporary path in it.
+fn temporary_path(path: &Path) -> io::Result<(&Path, PathBuf)> {
+    let parent_dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let tmp_path = parent_dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        Uuid::now_v7()
+    ));
+    Ok((parent_dir, tmp_path))
+}
+
+/// Syncs the directory entry so a rename within `dir` is durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened as files on non-unix platforms, where the rename is already
+/// durable once it returns.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes `content` to the new file at `path`, failing if it already exists.
+pub(crate) fn write_new_file(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+fn write_synced(path: &Path, content: &[u8], owner: Option<&FileOwner>) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options.open(path)?;
+    file.write_all(content)?;
+    #[cfg(unix)]
+    if let Some(owner) = owner {
+        std::os::unix::fs::fchown(&file, owner.uid, owner.gid)
+            .map_err(|e| io::Error::new(e.kind(), format!("unable to set file owner: {e}")))?;
+    }
+    #[cfg(not(unix))]
+    if owner.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "setting the file owner is only supported on unix",
+        ));
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_write_private_file_replaces_existing_file() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("key");
+
+        write_private_file(&path, b"first", None).unwrap();
+        write_private_file(&path, b"second", None).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        // Only the target remains, no temporary file is left behind
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_write_new_private_file_keeps_existing_file() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("key");
+
+        write_new_private_file(&path, b"first", None).unwrap();
+        let result = write_new_private_file(&path, b"second", None);
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        // Only the target remains, no temporary file is left behind
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod permission_tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn test_existing_parent_dir_permissions_are_kept() {
+        let tmp_dir = tempdir().unwrap();
+        fs::set_permissions(tmp_dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        let path = tmp_dir.path().join("key");
+
+        write_private_file(&path, b"content", None).unwrap();
+
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(tmp_dir.path()), 0o755);
+        assert_eq!(fs::read(&path).unwrap(), b"content");
+    }
+
+    #[test]
+    fn test_failed_write_leaves_no_file() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("missing-dir").join("key");
+
+        assert!(write_private_file(&path, b"content", None).is_err());
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 0);
+    }
+}
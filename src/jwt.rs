@@ -1,4 +1,6 @@
 pub mod claims;
+pub mod encryption;
 pub mod error;
 pub mod signed;
 pub mod signer;
+pub mod verifier;
@@ -1,7 +1,16 @@
+use chrono::{DateTime, TimeDelta, Utc};
 use http::Uri;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
 use uuid::Uuid;
 
+/// A signed JWT should live enough for the System Identity Service to consume it.
+pub const DEFAULT_JWT_CLAIM_EXP: TimeDelta = TimeDelta::seconds(180);
+
+/// Claims defined by the service or RFC 7519 that cannot be set as private claims.
+const REGISTERED_CLAIMS: &[&str] = &["iss", "sub", "aud", "jti", "exp", "iat", "nbf"];
+
 /// JWT Claims supported by the service.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Claims {
@@ -15,6 +24,15 @@ pub struct Claims {
     pub(crate) jti: Uuid,
     /// Expiration time (as UTC timestamp).
     pub(crate) exp: u64,
+    /// Issued at (as UTC timestamp).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) iat: Option<u64>,
+    /// Not before (as UTC timestamp).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) nbf: Option<u64>,
+    /// Additional private claims.
+    #[serde(flatten)]
+    pub(crate) private_claims: Map<String, Value>,
 }
 
 impl Clone for Claims {
@@ -26,6 +44,9 @@ impl Clone for Claims {
             aud: self.aud.clone(),
             jti: Uuid::now_v7(),
             exp: self.exp,
+            iat: self.iat,
+            nbf: self.nbf,
+            private_claims: self.private_claims.clone(),
         }
     }
 }
@@ -39,6 +60,255 @@ impl Claims {
             aud: aud.to_string(),
             jti: Uuid::now_v7(), // Non-reusable JWT ID
             exp,
+            iat: None,
+            nbf: None,
+            private_claims: Map::new(),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ClaimsBuilderError {
+    #[error("private claim `{0}` collides with a registered claim")]
+    RegisteredClaim(String),
+    #[error("invalid claims lifetime: `{0}`")]
+    InvalidLifetime(String),
+    #[error("invalid clock skew: `{0}`")]
+    InvalidClockSkew(String),
+    #[error("converting timestamp: `{0}`")]
+    Timestamp(String),
+}
+
+/// Builds the [`Claims`] of the JWTs a client signs to authenticate.
+///
+/// Timestamps are computed when calling [`ClaimsBuilder::build`], so the same builder can be
+/// reused for every token request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimsBuilder {
+    aud: Uri,
+    lifetime: TimeDelta,
+    issued_at: bool,
+    not_before: bool,
+    clock_skew: TimeDelta,
+    private_claims: Map<String, Value>,
+}
+
+impl ClaimsBuilder {
+    /// Creates a builder for the given audience, with the default lifetime and no `iat`, `nbf`
+    /// nor private claims.
+    pub fn new(aud: Uri) -> Self {
+        Self {
+            aud,
+            lifetime: DEFAULT_JWT_CLAIM_EXP,
+            issued_at: false,
+            not_before: false,
+            clock_skew: TimeDelta::zero(),
+            private_claims: Map::new(),
+        }
+    }
+
+    pub fn with_audience(self, aud: Uri) -> Self {
+        Self { aud, ..self }
+    }
+
+    /// Sets how long the signed JWT is valid for, which determines the `exp` claim.
+    pub fn with_lifetime(self, lifetime: TimeDelta) -> Self {
+        Self { lifetime, ..self }
+    }
+
+    pub(crate) fn lifetime(&self) -> TimeDelta {
+        self.lifetime
+    }
+
+    /// Includes the `iat` (issued at) claim.
+    pub fn with_issued_at(self, issued_at: bool) -> Self {
+        Self { issued_at, ..self }
+    }
+
+    /// Includes the `nbf` (not before) claim.
+    pub fn with_not_before(self, not_before: bool) -> Self {
+        Self { not_before, ..self }
+    }
+
+    /// Backdates the `iat` and `nbf` claims so that the JWT is not rejected by a server whose
+    /// clock is behind ours.
+    pub fn with_clock_skew(self, clock_skew: TimeDelta) -> Self {
+        Self { clock_skew, ..self }
+    }
+
+    /// Adds a private claim. Registered claims (`iss`, `sub`, `aud`, `jti`, `exp`, `iat`, `nbf`)
+    /// are rejected when building.
+    pub fn with_private_claim(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.private_claims.insert(name.into(), value);
+        self
+    }
+
+    /// Adds all the given private claims.
+    pub fn with_private_claims(mut self, claims: Map<String, Value>) -> Self {
+        self.private_claims.extend(claims);
+        self
+    }
+
+    /// Builds the claims for `client_id`, relative to the current time.
+    pub fn build(&self, client_id: String) -> Result<Claims, ClaimsBuilderError> {
+        self.build_at(client_id, Utc::now())
+    }
+
+    fn build_at(
+        &self,
+        client_id: String,
+        now: DateTime<Utc>,
+    ) -> Result<Claims, ClaimsBuilderError> {
+        if let Some(name) = self
+            .private_claims
+            .keys()
+            .find(|name| REGISTERED_CLAIMS.contains(&name.as_str()))
+        {
+            return Err(ClaimsBuilderError::RegisteredClaim(name.to_owned()));
         }
+        if self.lifetime <= TimeDelta::zero() {
+            return Err(ClaimsBuilderError::InvalidLifetime(format!(
+                "{}s",
+                self.lifetime.num_seconds()
+            )));
+        }
+        if self.clock_skew < TimeDelta::zero() {
+            return Err(ClaimsBuilderError::InvalidClockSkew(format!(
+                "negative clock skew {}s",
+                self.clock_skew.num_seconds()
+            )));
+        }
+
+        let timestamp = |time: DateTime<Utc>| {
+            u64::try_from(time.timestamp())
+                .map_err(|e| ClaimsBuilderError::Timestamp(e.to_string()))
+        };
+        let skewed_now = now.checked_sub_signed(self.clock_skew).ok_or_else(|| {
+            ClaimsBuilderError::InvalidClockSkew(format!(
+                "{}s is out of the date range",
+                self.clock_skew.num_seconds()
+            ))
+        })?;
+        let expiration = now.checked_add_signed(self.lifetime).ok_or_else(|| {
+            ClaimsBuilderError::InvalidLifetime(format!(
+                "{}s is out of the date range",
+                self.lifetime.num_seconds()
+            ))
+        })?;
+        let skewed_now = timestamp(skewed_now)?;
+
+        Ok(Claims {
+            iat: self.issued_at.then_some(skewed_now),
+            nbf: self.not_before.then_some(skewed_now),
+            private_claims: self.private_claims.clone(),
+            ..Claims::new(client_id, self.aud.to_owned(), timestamp(expiration)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn default_builder_matches_claims_new() {
+        let now = Utc::now();
+        let aud = Uri::from_static("https://www.newrelic.com/");
+        let claims = ClaimsBuilder::new(aud.clone())
+            .build_at("client-id".to_string(), now)
+            .unwrap();
+
+        let expected = Claims::new(
+            "client-id".to_string(),
+            aud,
+            (now + DEFAULT_JWT_CLAIM_EXP).timestamp() as u64,
+        );
+        assert_eq!(
+            Claims {
+                jti: expected.jti,
+                ..claims
+            },
+            expected
+        );
+        assert_eq!(
+            serde_json::to_value(&expected)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["aud", "exp", "iss", "jti", "sub"]
+        );
+    }
+
+    #[test]
+    fn timestamps_and_private_claims() {
+        let now = Utc::now();
+        let claims = ClaimsBuilder::new(Uri::from_static("https://staging.newrelic.com/"))
+            .with_lifetime(TimeDelta::seconds(60))
+            .with_issued_at(true)
+            .with_not_before(true)
+            .with_clock_skew(TimeDelta::seconds(5))
+            .with_private_claim("tenant", json!("edge"))
+            .build_at("client-id".to_string(), now)
+            .unwrap();
+
+        let value = serde_json::to_value(&claims).unwrap();
+        let now = now.timestamp() as u64;
+        assert_eq!(value["aud"], "https://staging.newrelic.com/");
+        assert_eq!(value["exp"], now + 60);
+        assert_eq!(value["iat"], now - 5);
+        assert_eq!(value["nbf"], now - 5);
+        assert_eq!(value["tenant"], "edge");
+
+        // Private claims survive a serialization round trip
+        let decoded: Claims = serde_json::from_value(value).unwrap();
+        assert_eq!(decoded, claims);
+    }
+
+    #[test]
+    fn invalid_builders() {
+        let builder = ClaimsBuilder::new(Uri::from_static("https://www.newrelic.com/"));
+        assert_eq!(
+            builder
+                .clone()
+                .with_private_claim("exp", json!(0))
+                .build("client-id".to_string()),
+            Err(ClaimsBuilderError::RegisteredClaim("exp".to_string()))
+        );
+        assert!(matches!(
+            builder
+                .clone()
+                .with_lifetime(TimeDelta::zero())
+                .build("client-id".to_string()),
+            Err(ClaimsBuilderError::InvalidLifetime(_))
+        ));
+        assert!(matches!(
+            builder
+                .clone()
+                .with_clock_skew(TimeDelta::seconds(-1))
+                .build("client-id".to_string()),
+            Err(ClaimsBuilderError::InvalidClockSkew(_))
+        ));
+    }
+
+    #[test]
+    fn out_of_range_durations_do_not_panic() {
+        let builder = ClaimsBuilder::new(Uri::from_static("https://www.newrelic.com/"));
+        assert!(matches!(
+            builder
+                .clone()
+                .with_lifetime(TimeDelta::MAX)
+                .build("client-id".to_string()),
+            Err(ClaimsBuilderError::InvalidLifetime(_))
+        ));
+        assert!(matches!(
+            builder
+                .with_clock_skew(TimeDelta::MAX)
+                .build("client-id".to_string()),
+            Err(ClaimsBuilderError::InvalidClockSkew(_))
+        ));
     }
 }
@@ -0,0 +1,421 @@
+//! JWE encryption of signed JWTs.
+//! Some token endpoints require the client assertion to be encrypted to the server's key, on top
+//! of being signed. The signed JWT is wrapped in a nested JWT (RFC 7519 §5.2) using the compact
+//! JWE serialization (RFC 7516), with either RSA-OAEP-256 or ECDH-ES key management and A256GCM
+//! content encryption.
+use std::fmt::{self, Debug, Formatter};
+
+use aws_lc_rs::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use aws_lc_rs::agreement::{
+    self, Algorithm as AgreementAlgorithm, ECDH_P256, ECDH_P384, EphemeralPrivateKey,
+    UnparsedPublicKey,
+};
+use aws_lc_rs::digest::{self, SHA256};
+use aws_lc_rs::rand::{self, SystemRandom};
+use aws_lc_rs::rsa::{OAEP_SHA256_MGF1SHA256, OaepPublicEncryptingKey, PublicEncryptingKey};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve};
+use serde_json::{Map, Value, json};
+
+use crate::key::jwk::jwk_from_spki_der;
+
+use super::{error::JwtEncoderError, signed::SignedJwt};
+
+/// Content encryption algorithm, the only one supported.
+const CONTENT_ENCRYPTION: &str = "A256GCM";
+/// Length in bytes of the A256GCM content encryption key.
+const CONTENT_KEY_LEN: usize = 32;
+
+/// Key management algorithm, determined by the type of the server's key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JweAlgorithm {
+    /// RSAES OAEP using SHA-256 and MGF1 with SHA-256, for RSA keys.
+    RsaOaep256,
+    /// Ephemeral-static ECDH with Concat KDF, for EC P-256 and P-384 keys.
+    EcdhEs,
+}
+
+impl JweAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            JweAlgorithm::RsaOaep256 => "RSA-OAEP-256",
+            JweAlgorithm::EcdhEs => "ECDH-ES",
+        }
+    }
+}
+
+enum RecipientKey {
+    Rsa(OaepPublicEncryptingKey),
+    Ec {
+        algorithm: &'static AgreementAlgorithm,
+        spki: Vec<u8>,
+    },
+}
+
+/// Encrypts signed JWTs to the public key of the server.
+pub struct JweEncrypter {
+    key: RecipientKey,
+    key_id: Option<String>,
+}
+
+impl Debug for JweEncrypter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JweEncrypter")
+            .field("algorithm", &self.algorithm())
+            .field("key_id", &self.key_id)
+            .finish()
+    }
+}
+
+impl JweEncrypter {
+    /// Builds an encrypter for the server's PEM-encoded public key (`PUBLIC KEY`). RSA keys use
+    /// RSA-OAEP-256 and EC P-256 / P-384 keys use ECDH-ES.
+    pub fn from_public_key_pem(pem: &[u8]) -> Result<Self, JwtEncoderError> {
+        let parsed = pem::parse(pem).map_err(|e| JwtEncoderError::EncryptionKey(e.to_string()))?;
+        if parsed.tag() != "PUBLIC KEY" {
+            return Err(JwtEncoderError::EncryptionKey(format!(
+                "unexpected PEM tag `{}`",
+                parsed.tag()
+            )));
+        }
+        Self::from_spki_der(parsed.contents())
+    }
+
+    /// Builds an encrypter for the server's DER-encoded `SubjectPublicKeyInfo`.
+    pub fn from_spki_der(der: &[u8]) -> Result<Self, JwtEncoderError> {
+        let jwk =
+            jwk_from_spki_der(der).map_err(|e| JwtEncoderError::EncryptionKey(e.to_string()))?;
+        let key = match jwk.algorithm {
+            AlgorithmParameters::RSA(_) => {
+                let public_key = PublicEncryptingKey::from_der(der)
+                    .map_err(|e| JwtEncoderError::EncryptionKey(e.to_string()))?;
+                RecipientKey::Rsa(
+                    OaepPublicEncryptingKey::new(public_key)
+                        .map_err(|e| JwtEncoderError::EncryptionKey(e.to_string()))?,
+                )
+            }
+            AlgorithmParameters::EllipticCurve(ref params) => RecipientKey::Ec {
+                algorithm: match params.curve {
+                    EllipticCurve::P256 => &ECDH_P256,
+                    EllipticCurve::P384 => &ECDH_P384,
+                    ref curve => {
+                        return Err(JwtEncoderError::EncryptionKey(format!(
+                            "unsupported curve {curve:?}"
+                        )));
+                    }
+                },
+                spki: der.to_vec(),
+            },
+            _ => {
+                return Err(JwtEncoderError::EncryptionKey(
+                    "only RSA and EC keys are supported".to_string(),
+                ));
+            }
+        };
+        Ok(Self { key, key_id: None })
+    }
+
+    /// Sets the `kid` header, identifying the server's key the JWE is encrypted to.
+    pub fn with_key_id(self, key_id: impl Into<String>) -> Self {
+        Self {
+            key_id: Some(key_id.into()),
+            ..self
+        }
+    }
+
+    pub fn algorithm(&self) -> JweAlgorithm {
+        match self.key {
+            RecipientKey::Rsa(_) => JweAlgorithm::RsaOaep256,
+            RecipientKey::Ec { .. } => JweAlgorithm::EcdhEs,
+        }
+    }
+
+    /// Wraps the signed JWT in a JWE, returned in compact serialization.
+    pub fn encrypt(&self, jwt: &SignedJwt) -> Result<SignedJwt, JwtEncoderError> {
+        let mut header = Map::new();
+        header.insert("alg".into(), self.algorithm().name().into());
+        header.insert("enc".into(), CONTENT_ENCRYPTION.into());
+        header.insert("cty".into(), "JWT".into());
+        if let Some(key_id) = &self.key_id {
+            header.insert("kid".into(), key_id.as_str().into());
+        }
+
+        let (content_key, encrypted_key) = match &self.key {
+            RecipientKey::Rsa(public_key) => {
+                let mut content_key = vec![0u8; CONTENT_KEY_LEN];
+                rand::fill(&mut content_key).map_err(encryption_error)?;
+                let mut encrypted_key = vec![0u8; public_key.ciphertext_size()];
+                let len = public_key
+                    .encrypt(
+                        &OAEP_SHA256_MGF1SHA256,
+                        &content_key,
+                        &mut encrypted_key,
+                        None,
+                    )
+                    .map_err(encryption_error)?
+                    .len();
+                encrypted_key.truncate(len);
+                (content_key, encrypted_key)
+            }
+            RecipientKey::Ec { algorithm, spki } => {
+                let (content_key, epk) = ecdh_es_content_key(algorithm, spki)?;
+                header.insert("epk".into(), epk);
+                // With direct key agreement the JWE Encrypted Key is empty.
+                (content_key, Vec::new())
+            }
+        };
+
+        let header = BASE64_URL_SAFE_NO_PAD.encode(Value::Object(header).to_string());
+
+        let mut iv = [0u8; NONCE_LEN];
+        rand::fill(&mut iv).map_err(encryption_error)?;
+        let key = LessSafeKey::new(
+            UnboundKey::new(&AES_256_GCM, &content_key).map_err(encryption_error)?,
+        );
+        let mut ciphertext = jwt.value().as_bytes().to_vec();
+        let tag = key
+            .seal_in_place_separate_tag(
+                Nonce::assume_unique_for_key(iv),
+                Aad::from(header.as_bytes()),
+                &mut ciphertext,
+            )
+            .map_err(encryption_error)?;
+
+        Ok(SignedJwt {
+            value: [
+                header,
+                BASE64_URL_SAFE_NO_PAD.encode(encrypted_key),
+                BASE64_URL_SAFE_NO_PAD.encode(iv),
+                BASE64_URL_SAFE_NO_PAD.encode(ciphertext),
+                BASE64_URL_SAFE_NO_PAD.encode(tag.as_ref()),
+            ]
+            .join("."),
+        })
+    }
+}
+
+impl SignedJwt {
+    /// Wraps this JWT in a JWE encrypted to the server's key.
+    pub fn encrypt(&self, encrypter: &JweEncrypter) -> Result<SignedJwt, JwtEncoderError> {
+        encrypter.encrypt(self)
+    }
+}
+
+/// Agrees on the content encryption key with an ephemeral key pair, returning the key and the
+/// ephemeral public key as a JWK for the `epk` header.
+fn ecdh_es_content_key(
+    algorithm: &'static AgreementAlgorithm,
+    spki: &[u8],
+) -> Result<(Vec<u8>, Value), JwtEncoderError> {
+    let private_key =
+        EphemeralPrivateKey::generate(algorithm, &SystemRandom::new()).map_err(encryption_error)?;
+    let public_key = private_key.compute_public_key().map_err(encryption_error)?;
+
+    // Uncompressed point: 0x04 || x || y
+    let point = public_key.as_ref();
+    let len = (point.len() - 1) / 2;
+    let epk = json!({
+        "kty": "EC",
+        "crv": if algorithm == &ECDH_P256 { "P-256" } else { "P-384" },
+        "x": BASE64_URL_SAFE_NO_PAD.encode(&point[1..=len]),
+        "y": BASE64_URL_SAFE_NO_PAD.encode(&point[len + 1..]),
+    });
+
+    let content_key = agreement::agree_ephemeral(
+        private_key,
+        UnparsedPublicKey::new(algorithm, spki),
+        JwtEncoderError::Encryption("ECDH key agreement failed".to_string()),
+        |shared_secret| Ok(content_key_from_shared_secret(shared_secret)),
+    )?;
+    Ok((content_key, epk))
+}
+
+/// Concat KDF (NIST SP 800-56A) with SHA-256, as specified for ECDH-ES (RFC 7518 §4.6.2).
+/// A single round is computed, which is enough for keys up to 256 bits.
+fn concat_kdf(
+    shared_secret: &[u8],
+    algorithm_id: &str,
+    party_u_info: &[u8],
+    party_v_info: &[u8],
+    key_len: usize,
+) -> Vec<u8> {
+    let mut input = Vec::new();
+    input.extend_from_slice(&1u32.to_be_bytes());
+    input.extend_from_slice(shared_secret);
+    for data in [algorithm_id.as_bytes(), party_u_info, party_v_info] {
+        input.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        input.extend_from_slice(data);
+    }
+    // SuppPubInfo: key length in bits.
+    input.extend_from_slice(&((key_len * 8) as u32).to_be_bytes());
+
+    digest::digest(&SHA256, &input).as_ref()[..key_len].to_vec()
+}
+
+/// Derives the A256GCM key in direct key agreement mode, where the AlgorithmID is the `enc`
+/// value. No `apu` / `apv` headers are set.
+fn content_key_from_shared_secret(shared_secret: &[u8]) -> Vec<u8> {
+    concat_kdf(shared_secret, CONTENT_ENCRYPTION, &[], &[], CONTENT_KEY_LEN)
+}
+
+fn encryption_error<E: Debug>(err: E) -> JwtEncoderError {
+    JwtEncoderError::Encryption(format!("{err:?}"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use assert_matches::assert_matches;
+    use aws_lc_rs::agreement::PrivateKey;
+    use aws_lc_rs::encoding::AsDer;
+    use aws_lc_rs::rsa::{OaepPrivateDecryptingKey, PrivateDecryptingKey};
+
+    use super::*;
+    use crate::jwt::signer::local::test::{RS256_PRIVATE_KEY, RS256_PUBLIC_KEY};
+
+    /// Private key of the server the JWEs are encrypted to.
+    pub(crate) enum ServerKey {
+        Rsa(OaepPrivateDecryptingKey),
+        Ec(PrivateKey),
+    }
+
+    /// Decrypts a compact JWE, returning its protected header and payload.
+    pub(crate) fn decrypt(jwe: &str, server_key: &ServerKey) -> (Value, String) {
+        let parts = jwe
+            .split('.')
+            .map(|part| BASE64_URL_SAFE_NO_PAD.decode(part).unwrap())
+            .collect::<Vec<_>>();
+        let [header, encrypted_key, iv, ciphertext, tag] = parts.as_slice() else {
+            panic!("JWE compact serialization has 5 parts");
+        };
+        let header_value: Value = serde_json::from_slice(header).unwrap();
+
+        let content_key = match server_key {
+            ServerKey::Rsa(private_key) => {
+                let mut content_key = vec![0u8; private_key.min_output_size()];
+                let len = private_key
+                    .decrypt(
+                        &OAEP_SHA256_MGF1SHA256,
+                        encrypted_key,
+                        &mut content_key,
+                        None,
+                    )
+                    .unwrap()
+                    .len();
+                content_key.truncate(len);
+                content_key
+            }
+            ServerKey::Ec(private_key) => {
+                assert!(encrypted_key.is_empty());
+                let epk = &header_value["epk"];
+                let mut point = vec![0x04];
+                for coordinate in ["x", "y"] {
+                    point.extend(
+                        BASE64_URL_SAFE_NO_PAD
+                            .decode(epk[coordinate].as_str().unwrap())
+                            .unwrap(),
+                    );
+                }
+                agreement::agree(
+                    private_key,
+                    UnparsedPublicKey::new(private_key.algorithm(), point),
+                    (),
+                    |shared_secret| Ok(content_key_from_shared_secret(shared_secret)),
+                )
+                .unwrap()
+            }
+        };
+
+        let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &content_key).unwrap());
+        let mut in_out = [ciphertext.as_slice(), tag.as_slice()].concat();
+        let aad = jwe.split('.').next().unwrap().as_bytes();
+        let plaintext = key
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(iv).unwrap(),
+                Aad::from(aad),
+                &mut in_out,
+            )
+            .unwrap();
+        (header_value, String::from_utf8(plaintext.to_vec()).unwrap())
+    }
+
+    pub(crate) fn rsa_server_key() -> ServerKey {
+        let der = pem::parse(RS256_PRIVATE_KEY).unwrap();
+        ServerKey::Rsa(
+            OaepPrivateDecryptingKey::new(
+                PrivateDecryptingKey::from_pkcs8(der.contents()).unwrap(),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn signed_jwt() -> SignedJwt {
+        SignedJwt::from("header.payload.signature".to_string())
+    }
+
+    #[test]
+    fn rsa_oaep_256() {
+        let encrypter = JweEncrypter::from_public_key_pem(RS256_PUBLIC_KEY.as_bytes())
+            .unwrap()
+            .with_key_id("server-key");
+        assert_eq!(encrypter.algorithm(), JweAlgorithm::RsaOaep256);
+
+        let jwe = signed_jwt().encrypt(&encrypter).unwrap();
+        let (header, payload) = decrypt(jwe.value(), &rsa_server_key());
+        assert_eq!(payload, signed_jwt().value());
+        assert_eq!(header["alg"], "RSA-OAEP-256");
+        assert_eq!(header["enc"], "A256GCM");
+        assert_eq!(header["cty"], "JWT");
+        assert_eq!(header["kid"], "server-key");
+
+        // A fresh content key is used for each JWE
+        assert_ne!(jwe, signed_jwt().encrypt(&encrypter).unwrap());
+    }
+
+    #[test]
+    fn ecdh_es() {
+        for (algorithm, curve) in [(&ECDH_P256, "P-256"), (&ECDH_P384, "P-384")] {
+            let private_key = PrivateKey::generate(algorithm).unwrap();
+            let spki = private_key.compute_public_key().unwrap().as_der().unwrap();
+            let encrypter = JweEncrypter::from_spki_der(spki.as_ref()).unwrap();
+            assert_eq!(encrypter.algorithm(), JweAlgorithm::EcdhEs);
+
+            let jwe = signed_jwt().encrypt(&encrypter).unwrap();
+            let (header, payload) = decrypt(jwe.value(), &ServerKey::Ec(private_key));
+            assert_eq!(payload, signed_jwt().value());
+            assert_eq!(header["alg"], "ECDH-ES");
+            assert_eq!(header["epk"]["crv"], curve);
+            assert!(header.get("kid").is_none());
+        }
+    }
+
+    #[test]
+    fn concat_kdf_rfc7518_appendix_c() {
+        let shared_secret = [
+            158, 86, 217, 29, 129, 113, 53, 211, 114, 131, 66, 131, 191, 132, 38, 156, 251, 49,
+            110, 163, 218, 128, 106, 72, 246, 218, 167, 121, 140, 254, 144, 196,
+        ];
+        assert_eq!(
+            BASE64_URL_SAFE_NO_PAD.encode(concat_kdf(
+                &shared_secret,
+                "A128GCM",
+                b"Alice",
+                b"Bob",
+                16
+            )),
+            "VqqN6vgjbSBcIijNcacQGg"
+        );
+    }
+
+    #[test]
+    fn unsupported_keys() {
+        assert_matches!(
+            JweEncrypter::from_public_key_pem(RS256_PRIVATE_KEY.as_bytes()),
+            Err(JwtEncoderError::EncryptionKey(_))
+        );
+        let ed25519 = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+        assert_matches!(
+            JweEncrypter::from_public_key_pem(ed25519.public_key_pem().as_bytes()),
+            Err(JwtEncoderError::EncryptionKey(_))
+        );
+    }
+}
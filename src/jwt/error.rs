@@ -5,4 +5,24 @@ pub enum JwtEncoderError {
     // Generic error for each implementation to use
     #[error("unable to encode token: `{0}`")]
     TokenEncoding(String),
+    #[error("invalid encryption key: `{0}`")]
+    EncryptionKey(String),
+    #[error("unable to encrypt token: `{0}`")]
+    Encryption(String),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum JwtDecoderError {
+    #[error("unable to load verification key: `{0}`")]
+    VerificationKey(String),
+    #[error("no verification key found for the token: `{0}`")]
+    UnknownKey(String),
+    #[error("invalid token: `{0}`")]
+    InvalidToken(String),
+    #[error("invalid claims: `{0}`")]
+    InvalidClaims(String),
+    #[error("token already used: `{0}`")]
+    Replayed(String),
+    #[error("recording token id: `{0}`")]
+    JtiStore(String),
 }
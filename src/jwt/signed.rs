@@ -10,3 +10,9 @@ impl SignedJwt {
         &self.value
     }
 }
+
+impl From<String> for SignedJwt {
+    fn from(value: String) -> Self {
+        Self { value }
+    }
+}
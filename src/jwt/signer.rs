@@ -1,26 +1,82 @@
 use super::{claims::Claims, error::JwtEncoderError, signed::SignedJwt};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use jsonwebtoken::Header;
 use local::{LocalPrivateKeySigner, LocalPrivateKeySignerError};
+#[cfg(feature = "pkcs11")]
+use pkcs11::{Pkcs11Signer, Pkcs11SignerError};
+use reloading::ReloadingPrivateKeySigner;
+use remote::{RemoteDigestJwtSigner, RemoteDigestSignerError};
 use thiserror::Error;
+#[cfg(feature = "vault")]
+use vault::{VaultTransitSigner, VaultTransitSignerError};
 
+pub mod fallback;
 pub mod local;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+pub mod reloading;
+pub mod remote;
+#[cfg(feature = "vault")]
+pub mod vault;
 
 /// A JWT signer.
 pub trait JwtSigner {
     // should Claims be single-use? Local implementation only needs references but
     // perhaps consuming it is useful from the security/safety perspective?
     fn sign(&self, claims: Claims) -> Result<SignedJwt, JwtEncoderError>;
+
+    /// Notifies that the server rejected the last assertion as an invalid client. Returns whether
+    /// signing again may succeed, for signers able to switch to another key.
+    fn assertion_rejected(&self) -> bool {
+        false
+    }
+
+    /// Notifies that the server accepted the last assertion.
+    fn assertion_accepted(&self) {}
+
+    /// Notifies that getting a token with the last assertion failed for another reason than the
+    /// assertion being rejected (e.g. a transport error). Returns whether the signer switched to
+    /// another key.
+    fn assertion_failed(&self) -> bool {
+        false
+    }
+
+    /// Identifies the key currently used. It changes whenever the signer switches to another key
+    /// on its own (e.g. reloading it), so assertions signed ahead of time can be discarded.
+    fn key_generation(&self) -> u64 {
+        0
+    }
 }
 
 /// Enumerates all implementations for `JwtSigner` for static dispatching reasons.
 #[derive(Debug)]
 pub enum JwtSignerImpl {
     Local(LocalPrivateKeySigner),
+    Reloading(Box<ReloadingPrivateKeySigner>),
+    RemoteDigest(Box<RemoteDigestJwtSigner>),
+    #[cfg(feature = "vault")]
+    VaultTransit(Box<VaultTransitSigner>),
+    #[cfg(feature = "pkcs11")]
+    Pkcs11(Box<Pkcs11Signer>),
 }
 
 impl JwtSigner for JwtSignerImpl {
     fn sign(&self, claims: Claims) -> Result<SignedJwt, JwtEncoderError> {
         match self {
             Self::Local(local_signer) => local_signer.sign(claims),
+            Self::Reloading(reloading_signer) => reloading_signer.sign(claims),
+            Self::RemoteDigest(remote_signer) => remote_signer.sign(claims),
+            #[cfg(feature = "vault")]
+            Self::VaultTransit(vault_signer) => vault_signer.sign(claims),
+            #[cfg(feature = "pkcs11")]
+            Self::Pkcs11(pkcs11_signer) => pkcs11_signer.sign(claims),
+        }
+    }
+
+    fn key_generation(&self) -> u64 {
+        match self {
+            Self::Reloading(reloading_signer) => reloading_signer.key_generation(),
+            _ => 0,
         }
     }
 }
@@ -29,6 +85,28 @@ impl JwtSigner for JwtSignerImpl {
 pub enum JwtSignerImplError {
     #[error("building local private key JWT signer: `{0}`")]
     LocalPrivateKeySignerError(#[from] LocalPrivateKeySignerError),
+    #[error("building remote digest JWT signer: `{0}`")]
+    RemoteDigestSignerError(#[from] RemoteDigestSignerError),
+    #[cfg(feature = "vault")]
+    #[error("building vault transit JWT signer: `{0}`")]
+    VaultTransitSignerError(#[from] VaultTransitSignerError),
+    #[cfg(feature = "pkcs11")]
+    #[error("building PKCS#11 JWT signer: `{0}`")]
+    Pkcs11SignerError(#[from] Pkcs11SignerError),
+}
+
+/// Builds the JWS signing input (`BASE64URL(header).BASE64URL(claims)`) for signers that
+/// compute the signature themselves instead of relying on `jsonwebtoken::encode`.
+pub(crate) fn signing_input(header: &Header, claims: &Claims) -> Result<String, JwtEncoderError> {
+    let header = serde_json::to_vec(header)
+        .map_err(|e| JwtEncoderError::TokenEncoding(format!("serializing header: {e}")))?;
+    let claims = serde_json::to_vec(claims)
+        .map_err(|e| JwtEncoderError::TokenEncoding(format!("serializing claims: {e}")))?;
+    Ok(format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(header),
+        BASE64_URL_SAFE_NO_PAD.encode(claims)
+    ))
 }
 
 #[cfg(test)]
@@ -44,4 +122,25 @@ pub mod tests {
             fn sign(&self, claims: Claims) -> Result<SignedJwt, JwtEncoderError>;
         }
     }
+
+    #[test]
+    fn signing_input_is_base64url_encoded() {
+        use http::Uri;
+        use jsonwebtoken::Algorithm;
+
+        let claims = Claims::new(
+            "client-id".to_string(),
+            Uri::from_static("https://www.newrelic.com/"),
+            0,
+        );
+        let input = signing_input(&Header::new(Algorithm::RS256), &claims).unwrap();
+        let (header, payload) = input.split_once('.').unwrap();
+
+        let header: Header =
+            serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(header).unwrap()).unwrap();
+        let payload: Claims =
+            serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+        assert_eq!(header.alg, Algorithm::RS256);
+        assert_eq!(payload, claims);
+    }
 }
@@ -0,0 +1,243 @@
+//! Signer switching between several keys while a key rotation propagates.
+//! During an L2 key rotation the identity server may only know the previous or only the new
+//! public key for a while. The [`FallbackSigner`] signs with the key that last got a token and
+//! moves to the next one whenever an assertion is rejected as an invalid client.
+use std::sync::{Mutex, MutexGuard};
+
+use tracing::debug;
+
+use crate::jwt::{claims::Claims, error::JwtEncoderError, signed::SignedJwt};
+use crate::sync::lock_recovering_poison;
+
+use super::JwtSigner;
+
+#[derive(Debug, Default)]
+struct FallbackState {
+    /// Signer that last got an assertion accepted.
+    preferred: usize,
+    /// Signer currently in use.
+    current: usize,
+    /// Consecutive rejections since the last accepted assertion.
+    rejections: usize,
+    /// Number of times `current` changed.
+    switches: u64,
+}
+
+impl FallbackState {
+    fn switch_to(&mut self, signer: usize) {
+        if self.current != signer {
+            self.current = signer;
+            self.switches = self.switches.wrapping_add(1);
+        }
+    }
+}
+
+/// Signs with the first of an ordered list of signers accepted by the server.
+///
+/// Signers are tried in order, starting from the one that last got an assertion accepted. Once
+/// every signer has been rejected, or getting a token fails for another reason, the next refresh
+/// starts over from the preferred signer.
+#[derive(Debug)]
+pub struct FallbackSigner<S: JwtSigner> {
+    signers: Vec<S>,
+    state: Mutex<FallbackState>,
+}
+
+impl<S: JwtSigner> FallbackSigner<S> {
+    /// Creates a signer using `primary` until it is rejected.
+    pub fn new(primary: S) -> Self {
+        Self {
+            signers: vec![primary],
+            state: Mutex::new(FallbackState::default()),
+        }
+    }
+
+    /// Adds a signer to try after the ones already added.
+    pub fn with_fallback(mut self, signer: S) -> Self {
+        self.signers.push(signer);
+        self
+    }
+
+    /// Position of the signer used for the next assertion.
+    pub fn current(&self) -> usize {
+        self.lock().current
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FallbackState> {
+        // The wrapped signers' `assertion_rejected` and `assertion_accepted` run with the guard
+        // held, but the state is only updated after they return, so a panic in them leaves it as
+        // it was before the call.
+        lock_recovering_poison(&self.state)
+    }
+}
+
+impl<S: JwtSigner> JwtSigner for FallbackSigner<S> {
+    fn sign(&self, claims: Claims) -> Result<SignedJwt, JwtEncoderError> {
+        let current = self.lock().current;
+        self.signers[current].sign(claims)
+    }
+
+    fn assertion_rejected(&self) -> bool {
+        let mut state = self.lock();
+        // A wrapped signer may itself have another key to try.
+        if self.signers[state.current].assertion_rejected() {
+            return true;
+        }
+        state.rejections += 1;
+        if state.rejections >= self.signers.len() {
+            debug!("client assertion rejected with every signer");
+            let preferred = state.preferred;
+            state.switch_to(preferred);
+            state.rejections = 0;
+            return false;
+        }
+        let next = (state.current + 1) % self.signers.len();
+        state.switch_to(next);
+        debug!(
+            "client assertion rejected, falling back to signer {}",
+            state.current
+        );
+        true
+    }
+
+    fn assertion_accepted(&self) {
+        let mut state = self.lock();
+        self.signers[state.current].assertion_accepted();
+        state.preferred = state.current;
+        state.rejections = 0;
+    }
+
+    fn assertion_failed(&self) -> bool {
+        let mut state = self.lock();
+        let switched = self.signers[state.current].assertion_failed();
+        // Otherwise the next refresh would resume the fallback with fewer signers left to try.
+        let reset = state.current != state.preferred;
+        if reset {
+            debug!(
+                "getting a token failed, starting over from signer {}",
+                state.preferred
+            );
+        }
+        let preferred = state.preferred;
+        state.switch_to(preferred);
+        state.rejections = 0;
+        switched || reset
+    }
+
+    fn key_generation(&self) -> u64 {
+        // Every term only grows, so the sum changes whenever any signer switches keys, including
+        // when starting over from the preferred signer without asking for a retry.
+        let switches = self.lock().switches;
+        self.signers.iter().fold(switches, |sum, signer| {
+            sum.wrapping_add(signer.key_generation())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Uri;
+
+    use super::*;
+    use crate::jwt::signer::tests::MockJwtSigner;
+
+    fn claims() -> Claims {
+        Claims::new(
+            "client-id".to_string(),
+            Uri::from_static("https://www.newrelic.com/"),
+            0,
+        )
+    }
+
+    fn signer(value: &'static str) -> MockJwtSigner {
+        let mut signer = MockJwtSigner::new();
+        signer
+            .expect_sign()
+            .returning(move |_| Ok(SignedJwt::from(value.to_string())));
+        signer
+    }
+
+    #[test]
+    fn falls_back_and_remembers_accepted_signer() {
+        let fallback = FallbackSigner::new(signer("old"))
+            .with_fallback(signer("new"))
+            .with_fallback(signer("newest"));
+        assert_eq!(fallback.sign(claims()).unwrap().value(), "old");
+
+        assert!(fallback.assertion_rejected());
+        assert_eq!(fallback.sign(claims()).unwrap().value(), "new");
+        fallback.assertion_accepted();
+        assert_eq!(fallback.current(), 1);
+
+        // Later refreshes start with the accepted signer and wrap around the list.
+        assert!(fallback.assertion_rejected());
+        assert_eq!(fallback.sign(claims()).unwrap().value(), "newest");
+        assert!(fallback.assertion_rejected());
+        assert_eq!(fallback.sign(claims()).unwrap().value(), "old");
+        fallback.assertion_accepted();
+        assert_eq!(fallback.current(), 0);
+    }
+
+    #[test]
+    fn every_signer_rejected() {
+        let fallback = FallbackSigner::new(signer("old")).with_fallback(signer("new"));
+        fallback.assertion_rejected();
+        fallback.assertion_accepted();
+
+        assert!(fallback.assertion_rejected());
+        assert!(!fallback.assertion_rejected());
+        // The next refresh starts over from the signer last accepted.
+        assert_eq!(fallback.current(), 1);
+        assert!(fallback.assertion_rejected());
+        assert_eq!(fallback.current(), 0);
+    }
+
+    #[test]
+    fn key_generation_changes_with_current_signer() {
+        let fallback = FallbackSigner::new(signer("old")).with_fallback(signer("new"));
+        let initial = fallback.key_generation();
+
+        assert!(fallback.assertion_rejected());
+        let fallen_back = fallback.key_generation();
+        assert_ne!(fallen_back, initial);
+
+        // Starting over after every signer was rejected is a switch too.
+        assert!(!fallback.assertion_rejected());
+        assert_eq!(fallback.current(), 0);
+        assert_ne!(fallback.key_generation(), fallen_back);
+
+        // Staying on the same signer keeps the generation.
+        let generation = fallback.key_generation();
+        fallback.assertion_accepted();
+        assert!(!fallback.assertion_failed());
+        assert_eq!(fallback.key_generation(), generation);
+    }
+
+    #[test]
+    fn failed_refresh_starts_over_from_preferred_signer() {
+        let fallback = FallbackSigner::new(signer("old"))
+            .with_fallback(signer("new"))
+            .with_fallback(signer("newest"));
+        assert!(fallback.assertion_rejected());
+        assert_eq!(fallback.current(), 1);
+
+        // A transport error after falling back resets the rejections too.
+        assert!(fallback.assertion_failed());
+        assert_eq!(fallback.current(), 0);
+        assert!(!fallback.assertion_failed());
+
+        // Every signer is still tried on the next refresh.
+        assert!(fallback.assertion_rejected());
+        assert!(fallback.assertion_rejected());
+        assert_eq!(fallback.sign(claims()).unwrap().value(), "newest");
+        assert!(!fallback.assertion_rejected());
+        assert_eq!(fallback.current(), 0);
+    }
+
+    #[test]
+    fn single_signer_is_not_retried() {
+        let fallback = FallbackSigner::new(signer("only"));
+        assert!(!fallback.assertion_rejected());
+        assert_eq!(fallback.current(), 0);
+    }
+}
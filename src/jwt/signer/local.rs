@@ -1,8 +1,18 @@
+use aws_lc_rs::digest::{SHA256, digest};
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+};
+use jsonwebtoken::jwk::{CommonParameters, Jwk, KeyAlgorithm, ThumbprintHash};
 use jsonwebtoken::{Algorithm, EncodingKey, Header};
 use std::{fmt::Debug, io, path::Path};
 use thiserror::Error;
 
 use crate::jwt::{claims::Claims, error::JwtEncoderError, signed::SignedJwt};
+use crate::key::certificate::certificate_spki_der;
+use crate::key::jwk::{jwk_from_pem, jwk_from_spki_der, with_thumbprint_kid};
+use crate::key::pkcs8::{EncryptedKeyError, decrypt_private_key_pem, is_encrypted_pem};
+use crate::key::secret::SecretSource;
 
 use super::JwtSigner;
 
@@ -13,12 +23,24 @@ pub enum LocalPrivateKeySignerError {
     Encoding(#[from] jsonwebtoken::errors::Error),
     #[error("filesystem i/o error: `{0}`")]
     IO(#[from] io::Error),
+    #[error("unable to load certificate: `{0}`")]
+    Certificate(String),
+    #[error("unable to decrypt private key: `{0}`")]
+    Decryption(#[from] EncryptedKeyError),
 }
 
 /// Signer structure that uses a local private key to sign JWTs.
 pub struct LocalPrivateKeySigner {
     encoding_key: EncodingKey,
     algorithm: Algorithm,
+    /// RFC 7638 thumbprint of the public key, to check certificates against.
+    public_key_thumbprint: String,
+    /// Value of the `kid` header, so the server can tell which registered key to verify against.
+    key_id: Option<String>,
+    /// Value of the `x5t#S256` header, the SHA-256 thumbprint of the key certificate.
+    x5t_s256: Option<String>,
+    /// Value of the `x5c` header, the Base64 DER certificate chain starting with the key one.
+    x5c: Option<Vec<String>>,
 }
 
 impl Debug for LocalPrivateKeySigner {
@@ -26,6 +48,9 @@ impl Debug for LocalPrivateKeySigner {
         f.debug_struct("LocalPrivateKeySigner")
             .field("algorithm", &self.algorithm)
             .field("encoding_key", &"REDACTED") // Avoid printing the key
+            .field("key_id", &self.key_id)
+            .field("x5t_s256", &self.x5t_s256)
+            .field("x5c", &self.x5c)
             .finish()
     }
 }
@@ -35,16 +60,47 @@ impl TryFrom<&[u8]> for LocalPrivateKeySigner {
     type Error = LocalPrivateKeySignerError;
 
     fn try_from(pem: &[u8]) -> Result<Self, Self::Error> {
-        // Algorithm is hardcoded to RS256, so decoding key is also fixed. Here we load it from
-        // a PEM file.
+        let pkcs8_jwk = pem::parse(pem)
+            .ok()
+            .filter(|parsed| parsed.tag() == "PRIVATE KEY")
+            .and_then(|_| jwk_from_pem(pem).ok());
+        // The algorithm follows the key type: RS256 for RSA keys, ES256/ES384 for ECDSA keys on
+        // the P-256/P-384 curves and EdDSA for Ed25519 keys.
+        let algorithm = key_algorithm(pkcs8_jwk.as_ref());
+        let encoding_key = match algorithm {
+            Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(pem)?,
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(pem)?,
+            _ => EncodingKey::from_rsa_pem(pem)?,
+        };
+        // Keys that are not PKCS#8 are RSA ones, whose JWK can be derived from the encoding key.
+        let public_key_thumbprint = match pkcs8_jwk {
+            Some(jwk) => jwk,
+            None => Jwk::from_encoding_key(&encoding_key, algorithm)?,
+        }
+        .thumbprint(ThumbprintHash::SHA256);
         Ok(Self {
-            // This will call pem::parse
-            encoding_key: EncodingKey::from_rsa_pem(pem)?,
-            algorithm: Algorithm::RS256,
+            encoding_key,
+            algorithm,
+            public_key_thumbprint,
+            key_id: None,
+            x5t_s256: None,
+            x5c: None,
         })
     }
 }
 
+/// Detects the signing algorithm matching the JWK of a PKCS#8 private key. Anything that is not
+/// a PKCS#8 ECDSA or Ed25519 key is treated as RSA, so invalid keys get reported when loading
+/// them.
+fn key_algorithm(pkcs8_jwk: Option<&Jwk>) -> Algorithm {
+    match pkcs8_jwk.and_then(|jwk| jwk.common.key_algorithm) {
+        Some(KeyAlgorithm::ES256) => Algorithm::ES256,
+        Some(KeyAlgorithm::ES384) => Algorithm::ES384,
+        Some(KeyAlgorithm::EdDSA) => Algorithm::EdDSA,
+        _ => Algorithm::RS256,
+    }
+}
+
 // Attempt to create a LocalPrivateKeySigner from a file path, which must be a valid PEM file.
 impl TryFrom<&Path> for LocalPrivateKeySigner {
     type Error = LocalPrivateKeySignerError;
@@ -55,12 +111,114 @@ impl TryFrom<&Path> for LocalPrivateKeySigner {
     }
 }
 
+impl LocalPrivateKeySigner {
+    /// Creates a signer from a PEM private key that may be passphrase-encrypted
+    /// (`ENCRYPTED PRIVATE KEY`). Unencrypted keys are loaded as with [`TryFrom`], without
+    /// reading the passphrase.
+    pub fn from_pem_with_passphrase(
+        pem: &[u8],
+        passphrase: &SecretSource,
+    ) -> Result<Self, LocalPrivateKeySignerError> {
+        if !is_encrypted_pem(pem) {
+            return Self::try_from(pem);
+        }
+        let decrypted =
+            decrypt_private_key_pem(pem, &passphrase.read().map_err(EncryptedKeyError::from)?)?;
+        Self::try_from(decrypted.as_slice())
+    }
+
+    /// Creates a signer from a PEM file whose private key may be passphrase-encrypted.
+    pub fn from_file_with_passphrase(
+        path: &Path,
+        passphrase: &SecretSource,
+    ) -> Result<Self, LocalPrivateKeySignerError> {
+        Self::from_pem_with_passphrase(&std::fs::read(path)?, passphrase)
+    }
+
+    /// Sets the `kid` header of the signed JWTs to the given key identifier.
+    pub fn with_key_id(self, key_id: impl Into<String>) -> Self {
+        Self {
+            key_id: Some(key_id.into()),
+            ..self
+        }
+    }
+
+    /// Sets the `kid` header of the signed JWTs to the RFC 7638 JWK thumbprint (SHA-256) of the
+    /// public key, which identifies the key without any extra configuration.
+    pub fn with_thumbprint_key_id(self) -> Result<Self, LocalPrivateKeySignerError> {
+        let key_id = Jwk::from_encoding_key(&self.encoding_key, self.algorithm)?
+            .thumbprint(ThumbprintHash::SHA256);
+        Ok(self.with_key_id(key_id))
+    }
+
+    /// Sets the `x5c` and `x5t#S256` headers of the signed JWTs from the given PEM-encoded X.509
+    /// certificate chain, which starts with the certificate of the key. `x5t#S256` is the
+    /// thumbprint of that first certificate, whose public key must be the signer one.
+    pub fn with_certificate_pem(self, cert_pem: &[u8]) -> Result<Self, LocalPrivateKeySignerError> {
+        let chain = pem::parse_many(cert_pem)
+            .map_err(|e| LocalPrivateKeySignerError::Certificate(e.to_string()))?;
+        if let Some(other) = chain.iter().find(|cert| cert.tag() != "CERTIFICATE") {
+            return Err(LocalPrivateKeySignerError::Certificate(format!(
+                "unexpected PEM tag `{}`",
+                other.tag()
+            )));
+        }
+        let cert = chain.first().ok_or_else(|| {
+            LocalPrivateKeySignerError::Certificate("no certificate found".to_string())
+        })?;
+        let cert_key = certificate_spki_der(cert.contents())
+            .map_err(|e| LocalPrivateKeySignerError::Certificate(e.to_string()))
+            .and_then(|spki| {
+                jwk_from_spki_der(&spki)
+                    .map_err(|e| LocalPrivateKeySignerError::Certificate(e.to_string()))
+            })?;
+        if cert_key.thumbprint(ThumbprintHash::SHA256) != self.public_key_thumbprint {
+            return Err(LocalPrivateKeySignerError::Certificate(
+                "the certificate public key does not match the private key".to_string(),
+            ));
+        }
+        Ok(Self {
+            x5t_s256: Some(BASE64_URL_SAFE_NO_PAD.encode(digest(&SHA256, cert.contents()))),
+            x5c: Some(
+                chain
+                    .iter()
+                    .map(|cert| BASE64_STANDARD.encode(cert.contents()))
+                    .collect(),
+            ),
+            ..self
+        })
+    }
+
+    /// Returns the JWK of the public key, identified by the configured key id or, if none, by its
+    /// RFC 7638 thumbprint.
+    pub fn public_jwk(&self) -> Result<Jwk, LocalPrivateKeySignerError> {
+        let jwk = with_thumbprint_kid(Jwk::from_encoding_key(&self.encoding_key, self.algorithm)?);
+        Ok(match &self.key_id {
+            Some(key_id) => Jwk {
+                common: CommonParameters {
+                    key_id: Some(key_id.to_owned()),
+                    ..jwk.common
+                },
+                ..jwk
+            },
+            None => jwk,
+        })
+    }
+
+    fn header(&self) -> Header {
+        Header {
+            kid: self.key_id.clone(),
+            x5t_s256: self.x5t_s256.clone(),
+            x5c: self.x5c.clone(),
+            ..Header::new(self.algorithm)
+        }
+    }
+}
+
 /// Sign a JWT using a local private key.
 impl JwtSigner for LocalPrivateKeySigner {
-    // Algorithm-agnostic, though we only support RS256.
-    // Change the algorithm and encoding key in LocalPrivateKeySigner to support other.
     fn sign(&self, claims: Claims) -> Result<SignedJwt, JwtEncoderError> {
-        let value = jsonwebtoken::encode(&Header::new(self.algorithm), &claims, &self.encoding_key)
+        let value = jsonwebtoken::encode(&self.header(), &claims, &self.encoding_key)
             .map_err(|e| JwtEncoderError::TokenEncoding(e.to_string()))?;
         Ok(SignedJwt { value })
     }
@@ -69,6 +227,8 @@ impl JwtSigner for LocalPrivateKeySigner {
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::key::generation::{KeyType, generate_key_pair};
+    use crate::key::pkcs8::encrypt_private_key_pem;
     use http::Uri;
     use jsonwebtoken::{DecodingKey, Validation, get_current_timestamp};
 
@@ -102,7 +262,7 @@ USEqOAWZjvEBorOcB1tTO3vBgZOBz41i/x9xlYw2fmt+fTBUNAN6ABFcrEEaAIFQ
 -----END PRIVATE KEY-----
 "#;
 
-    const RS256_PUBLIC_KEY: &str = r#"-----BEGIN PUBLIC KEY-----
+    pub const RS256_PUBLIC_KEY: &str = r#"-----BEGIN PUBLIC KEY-----
 MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAtj2oIV5g+0nLcBx5JBd8
 g5AZXpm9alireiKPBluzpWEietBwybSV60N8KCHCoK9WppmlxNWjJYlB1i3JS74e
 cdNHunjaU65yhKV64Xtd/VTNkK4BsKdoJTR7bUxG0xMSwR0hHGOrceoOGQ96SJSi
@@ -155,6 +315,181 @@ BrPkB8yrZjJU/fpF2D6HzGfinKRboBTcmo7mUF2wuYARj/IsuEklh0gz7rseIf7G
         assert_eq!(decoded_claims.aud, audience.to_string());
     }
 
+    #[test]
+    fn key_id_header() {
+        let claims = Claims::new(
+            "test".to_owned(),
+            Uri::from_static("http://127.0.0.1/"),
+            get_current_timestamp(),
+        );
+        let signer = LocalPrivateKeySigner::try_from(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+
+        let token = signer.sign(claims.clone()).unwrap();
+        assert_eq!(jsonwebtoken::decode_header(&token.value).unwrap().kid, None);
+
+        let token = signer.with_key_id("my-key").sign(claims.clone()).unwrap();
+        assert_eq!(
+            jsonwebtoken::decode_header(&token.value).unwrap().kid,
+            Some("my-key".to_string())
+        );
+    }
+
+    #[test]
+    fn thumbprint_key_id_header() {
+        let claims = Claims::new(
+            "test".to_owned(),
+            Uri::from_static("http://127.0.0.1/"),
+            get_current_timestamp(),
+        );
+        let signer = LocalPrivateKeySigner::try_from(RS256_PRIVATE_KEY.as_bytes())
+            .unwrap()
+            .with_thumbprint_key_id()
+            .unwrap();
+        let token = signer.sign(claims).unwrap();
+
+        // The thumbprint must match the one computed from the public key alone (RFC 7638 §3)
+        let spki = pem::parse(RS256_PUBLIC_KEY).unwrap();
+        let (n, e) = yasna::parse_der(spki.contents(), |reader| {
+            reader.read_sequence(|reader| {
+                reader.next().read_der()?;
+                let (key, _) = reader.next().read_bitvec_bytes()?;
+                yasna::parse_der(&key, |reader| {
+                    reader.read_sequence(|reader| {
+                        let (n, _) = reader.next().read_bigint_bytes()?;
+                        let (e, _) = reader.next().read_bigint_bytes()?;
+                        Ok((n, e))
+                    })
+                })
+            })
+        })
+        .unwrap();
+        let n = n.strip_prefix(&[0]).unwrap_or(&n);
+        let canonical_jwk = format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            BASE64_URL_SAFE_NO_PAD.encode(e),
+            BASE64_URL_SAFE_NO_PAD.encode(n)
+        );
+        assert_eq!(
+            jsonwebtoken::decode_header(&token.value).unwrap().kid,
+            Some(BASE64_URL_SAFE_NO_PAD.encode(digest(&SHA256, canonical_jwk.as_bytes())))
+        );
+    }
+
+    #[test]
+    fn certificate_headers() {
+        let claims = Claims::new(
+            "test".to_owned(),
+            Uri::from_static("http://127.0.0.1/"),
+            get_current_timestamp(),
+        );
+        let key_pair = rcgen::KeyPair::from_pem(RS256_PRIVATE_KEY).unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .self_signed(&key_pair)
+            .unwrap();
+
+        let signer = LocalPrivateKeySigner::try_from(RS256_PRIVATE_KEY.as_bytes())
+            .unwrap()
+            .with_certificate_pem(cert.pem().as_bytes())
+            .unwrap();
+        let token = signer.sign(claims).unwrap();
+        let header = jsonwebtoken::decode_header(&token.value).unwrap();
+        assert_eq!(
+            header.x5t_s256,
+            Some(BASE64_URL_SAFE_NO_PAD.encode(digest(&SHA256, cert.der())))
+        );
+        assert_eq!(header.x5c, Some(vec![BASE64_STANDARD.encode(cert.der())]));
+
+        let signer = LocalPrivateKeySigner::try_from(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        assert!(matches!(
+            signer.with_certificate_pem(RS256_PUBLIC_KEY.as_bytes()),
+            Err(LocalPrivateKeySignerError::Certificate(_))
+        ));
+    }
+
+    #[test]
+    fn certificate_of_another_key_is_rejected() {
+        let other_key = generate_key_pair(&KeyType::Rsa2048).unwrap();
+        let other_key =
+            rcgen::KeyPair::from_pem(std::str::from_utf8(&other_key.private_key).unwrap()).unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .self_signed(&other_key)
+            .unwrap();
+
+        let signer = LocalPrivateKeySigner::try_from(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        assert!(matches!(
+            signer.with_certificate_pem(cert.pem().as_bytes()),
+            Err(LocalPrivateKeySignerError::Certificate(e)) if e.contains("does not match")
+        ));
+    }
+
+    #[test]
+    fn public_jwk() {
+        let signer = LocalPrivateKeySigner::try_from(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        let jwk = signer.public_jwk().unwrap();
+        assert_eq!(
+            jwk,
+            crate::key::jwk::jwk_from_pem(RS256_PUBLIC_KEY.as_bytes()).unwrap()
+        );
+
+        let jwk = signer.with_key_id("my-key").public_jwk().unwrap();
+        assert_eq!(jwk.common.key_id, Some("my-key".to_string()));
+    }
+
+    #[rstest::rstest]
+    #[case::rsa2048(KeyType::Rsa2048)]
+    #[case::p256(KeyType::EcdsaP256)]
+    #[case::p384(KeyType::EcdsaP384)]
+    #[case::ed25519(KeyType::Ed25519)]
+    fn generated_keys_sign(#[case] key_type: KeyType) {
+        let audience = Uri::from_static("http://127.0.0.1/");
+        let claims = Claims::new("test".to_owned(), audience.clone(), get_current_timestamp());
+        let key_pair = generate_key_pair(&key_type).unwrap();
+
+        let signer = LocalPrivateKeySigner::try_from(key_pair.private_key.as_slice()).unwrap();
+        assert_eq!(signer.algorithm, key_type.algorithm());
+        let token = signer.sign(claims).unwrap();
+
+        let decoding_key = match key_type {
+            KeyType::EcdsaP256 | KeyType::EcdsaP384 => {
+                DecodingKey::from_ec_pem(&key_pair.public_key).unwrap()
+            }
+            KeyType::Ed25519 => DecodingKey::from_ed_pem(&key_pair.public_key).unwrap(),
+            _ => DecodingKey::from_rsa_pem(&key_pair.public_key).unwrap(),
+        };
+        let mut validation = Validation::new(key_type.algorithm());
+        validation.set_audience(std::slice::from_ref(&audience));
+        assert!(jsonwebtoken::decode::<Claims>(&token.value, &decoding_key, &validation).is_ok());
+    }
+
+    #[test]
+    fn encrypted_private_key() {
+        let passphrase = SecretSource::Value("passphrase".to_string());
+        let encrypted =
+            encrypt_private_key_pem(RS256_PRIVATE_KEY.as_bytes(), "passphrase").unwrap();
+
+        let signer = LocalPrivateKeySigner::from_pem_with_passphrase(&encrypted, &passphrase);
+        assert!(signer.is_ok());
+        // Unencrypted keys are still accepted
+        assert!(
+            LocalPrivateKeySigner::from_pem_with_passphrase(
+                RS256_PRIVATE_KEY.as_bytes(),
+                &passphrase
+            )
+            .is_ok()
+        );
+        assert!(matches!(
+            LocalPrivateKeySigner::from_pem_with_passphrase(
+                &encrypted,
+                &SecretSource::Value("wrong".to_string())
+            ),
+            Err(LocalPrivateKeySignerError::Decryption(
+                EncryptedKeyError::Decryption
+            ))
+        ));
+    }
+
     #[test]
     fn bad_pem_file() {
         let signer = LocalPrivateKeySigner::try_from("WRONG".as_bytes());
@@ -0,0 +1,217 @@
+use std::{fmt::Debug, sync::Mutex};
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use cryptoki::context::Pkcs11;
+use cryptoki::mechanism::eddsa::{EddsaParams, EddsaSignatureScheme};
+use cryptoki::mechanism::rsa::{PkcsMgfType, PkcsPssParams};
+use cryptoki::mechanism::{Mechanism, MechanismType};
+use cryptoki::object::{ObjectClass, ObjectHandle};
+use cryptoki::session::Session;
+use jsonwebtoken::{Algorithm, Header};
+use thiserror::Error;
+
+use crate::jwt::{claims::Claims, error::JwtEncoderError, signed::SignedJwt};
+use crate::key::pkcs11::{Pkcs11Error, Pkcs11KeyConfig};
+
+use super::{JwtSigner, signing_input};
+
+/// Errors that can occur when creating or using a Pkcs11Signer.
+#[derive(Debug, Error)]
+pub enum Pkcs11SignerError {
+    #[error("unsupported signing algorithm: `{0:?}`")]
+    UnsupportedAlgorithm(Algorithm),
+    #[error("PKCS#11 token: `{0}`")]
+    Token(#[from] Pkcs11Error),
+    #[error("signing with PKCS#11 token: `{0}`")]
+    Sign(String),
+    #[error("PKCS#11 session lock poisoned: `{0}`")]
+    PoisonError(String),
+}
+
+impl From<Pkcs11SignerError> for JwtEncoderError {
+    fn from(err: Pkcs11SignerError) -> Self {
+        JwtEncoderError::TokenEncoding(err.to_string())
+    }
+}
+
+/// Signer structure that signs JWTs with a private key that never leaves a PKCS#11 token.
+pub struct Pkcs11Signer {
+    /// Loaded module, kept alive as long as the session is in use.
+    _pkcs11: Pkcs11,
+    /// PKCS#11 sessions cannot be used concurrently.
+    session: Mutex<Session>,
+    private_key: ObjectHandle,
+    algorithm: Algorithm,
+    key_label: String,
+}
+
+impl Debug for Pkcs11Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pkcs11Signer")
+            .field("algorithm", &self.algorithm)
+            .field("key_label", &self.key_label)
+            .finish()
+    }
+}
+
+impl Pkcs11Signer {
+    /// Opens a session in the configured token and looks up the private key by its label.
+    pub fn new(config: &Pkcs11KeyConfig, algorithm: Algorithm) -> Result<Self, Pkcs11SignerError> {
+        // Fail early instead of on the first signature.
+        mechanism(algorithm)?;
+
+        let (pkcs11, session) = config.open_session()?;
+        let private_key = match config
+            .find_objects(&session, ObjectClass::PRIVATE_KEY)?
+            .as_slice()
+        {
+            [handle] => *handle,
+            [] => Err(Pkcs11Error::KeyNotFound(config.key_label.clone()))?,
+            _ => Err(Pkcs11Error::Operation(format!(
+                "multiple private keys with label `{}`",
+                config.key_label
+            )))?,
+        };
+
+        Ok(Self {
+            _pkcs11: pkcs11,
+            session: Mutex::new(session),
+            private_key,
+            algorithm,
+            key_label: config.key_label.clone(),
+        })
+    }
+
+    fn sign_input(&self, input: &str) -> Result<String, Pkcs11SignerError> {
+        let session = self
+            .session
+            .lock()
+            .map_err(|e| Pkcs11SignerError::PoisonError(e.to_string()))?;
+        // ECDSA mechanisms already return the raw `r || s` form expected by JWS.
+        let signature = session
+            .sign(
+                &mechanism(self.algorithm)?,
+                self.private_key,
+                input.as_bytes(),
+            )
+            .map_err(|e| Pkcs11SignerError::Sign(e.to_string()))?;
+        Ok(BASE64_URL_SAFE_NO_PAD.encode(signature))
+    }
+}
+
+/// Sign a JWT using a private key stored in a PKCS#11 token.
+impl JwtSigner for Pkcs11Signer {
+    fn sign(&self, claims: Claims) -> Result<SignedJwt, JwtEncoderError> {
+        let input = signing_input(&Header::new(self.algorithm), &claims)?;
+        let signature = self.sign_input(&input)?;
+        Ok(SignedJwt {
+            value: format!("{input}.{signature}"),
+        })
+    }
+}
+
+/// PKCS#11 mechanism producing the signature expected for a JWS algorithm.
+fn mechanism(algorithm: Algorithm) -> Result<Mechanism<'static>, Pkcs11SignerError> {
+    let pss = |hash_alg, mgf, s_len: u64| PkcsPssParams {
+        hash_alg,
+        mgf,
+        s_len: s_len.into(),
+    };
+    Ok(match algorithm {
+        Algorithm::RS256 => Mechanism::Sha256RsaPkcs,
+        Algorithm::RS384 => Mechanism::Sha384RsaPkcs,
+        Algorithm::RS512 => Mechanism::Sha512RsaPkcs,
+        Algorithm::PS256 => {
+            Mechanism::Sha256RsaPkcsPss(pss(MechanismType::SHA256, PkcsMgfType::MGF1_SHA256, 32))
+        }
+        Algorithm::PS384 => {
+            Mechanism::Sha384RsaPkcsPss(pss(MechanismType::SHA384, PkcsMgfType::MGF1_SHA384, 48))
+        }
+        Algorithm::PS512 => {
+            Mechanism::Sha512RsaPkcsPss(pss(MechanismType::SHA512, PkcsMgfType::MGF1_SHA512, 64))
+        }
+        Algorithm::ES256 => Mechanism::EcdsaSha256,
+        Algorithm::ES384 => Mechanism::EcdsaSha384,
+        Algorithm::EdDSA => Mechanism::Eddsa(EddsaParams::new(EddsaSignatureScheme::Pure)),
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            return Err(Pkcs11SignerError::UnsupportedAlgorithm(algorithm));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Uri;
+    use jsonwebtoken::{DecodingKey, Validation, get_current_timestamp};
+
+    use super::*;
+    use crate::key::generation::KeyType;
+    use crate::key::pkcs11::Pkcs11KeyPairGenerator;
+    use crate::key::pkcs11::tests::softhsm_config;
+
+    #[test]
+    fn hmac_algorithms_are_rejected() {
+        for algorithm in [Algorithm::HS256, Algorithm::HS384, Algorithm::HS512] {
+            assert!(matches!(
+                mechanism(algorithm),
+                Err(Pkcs11SignerError::UnsupportedAlgorithm(rejected)) if rejected == algorithm
+            ));
+        }
+    }
+
+    #[test]
+    fn pss_algorithms_use_hash_length_salts() {
+        assert!(matches!(
+            mechanism(Algorithm::PS256),
+            Ok(Mechanism::Sha256RsaPkcsPss(params)) if params.s_len == 32.into()
+        ));
+        assert!(matches!(
+            mechanism(Algorithm::PS384),
+            Ok(Mechanism::Sha384RsaPkcsPss(params)) if params.s_len == 48.into()
+        ));
+        assert!(matches!(
+            mechanism(Algorithm::PS512),
+            Ok(Mechanism::Sha512RsaPkcsPss(params)) if params.s_len == 64.into()
+        ));
+    }
+
+    #[test]
+    #[ignore = "requires a SoftHSM token, see softhsm_config"]
+    fn sign_with_generated_key() {
+        let key_label = format!("nr-auth-test-{}", uuid::Uuid::now_v7());
+        let config = softhsm_config(&key_label);
+        let public_key = Pkcs11KeyPairGenerator::new(KeyType::Rsa4096, config.clone())
+            .generate()
+            .unwrap();
+
+        for algorithm in [Algorithm::RS256, Algorithm::PS256] {
+            let signer = Pkcs11Signer::new(&config, algorithm).unwrap();
+            let claims = Claims::new(
+                "client-id".to_string(),
+                Uri::from_static("https://www.newrelic.com/"),
+                get_current_timestamp() + 60,
+            );
+            let signed = signer.sign(claims.clone()).unwrap();
+
+            let mut validation = Validation::new(algorithm);
+            validation.set_audience(&["https://www.newrelic.com/"]);
+            let decoded = jsonwebtoken::decode::<Claims>(
+                &signed.value,
+                &DecodingKey::from_rsa_pem(&public_key).unwrap(),
+                &validation,
+            )
+            .unwrap();
+            assert_eq!(decoded.claims.iss, "client-id");
+        }
+    }
+
+    #[test]
+    #[ignore = "requires a SoftHSM token, see softhsm_config"]
+    fn missing_key_label() {
+        let config = softhsm_config("nr-auth-test-missing-key");
+        assert!(matches!(
+            Pkcs11Signer::new(&config, Algorithm::RS256),
+            Err(Pkcs11SignerError::Token(Pkcs11Error::KeyNotFound(_)))
+        ));
+    }
+}
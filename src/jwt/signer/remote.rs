@@ -0,0 +1,340 @@
+use std::fmt::Debug;
+
+use aws_lc_rs::digest::{self, SHA256, SHA384, SHA512};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, Header};
+use thiserror::Error;
+
+use crate::jwt::{claims::Claims, error::JwtEncoderError, signed::SignedJwt};
+
+use super::{JwtSigner, signing_input};
+
+/// Errors that can occur when signing through a remote digest signer.
+#[derive(Debug, Error)]
+pub enum RemoteDigestSignerError {
+    #[error("unsupported signing algorithm: `{0:?}`")]
+    UnsupportedAlgorithm(Algorithm),
+    #[error("remote signer: `{0}`")]
+    Backend(String),
+    #[error("invalid signature returned by the remote signer: `{0}`")]
+    InvalidSignature(String),
+}
+
+impl From<RemoteDigestSignerError> for JwtEncoderError {
+    fn from(err: RemoteDigestSignerError) -> Self {
+        JwtEncoderError::TokenEncoding(err.to_string())
+    }
+}
+
+/// Format of the ECDSA signatures returned by a [`RemoteDigestSigner`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EcdsaSignatureFormat {
+    /// ASN.1 DER `SEQUENCE { r INTEGER, s INTEGER }`, as returned by AWS KMS and GCP KMS.
+    Der,
+    /// Fixed-size `r || s` concatenation, as returned by Azure Key Vault and expected by JWS.
+    Raw,
+}
+
+/// A signing backend (cloud KMS, remote HSM...) that signs a pre-computed digest with a key it
+/// holds. Backends only need to implement the signing call; [`RemoteDigestJwtSigner`] takes care
+/// of building the JWS signing input, hashing it and converting the signature.
+pub trait RemoteDigestSigner {
+    /// Signs the `digest` of the signing input, computed with the hash function of `algorithm`,
+    /// and returns the raw signature bytes.
+    fn sign_digest(
+        &self,
+        digest: &[u8],
+        algorithm: Algorithm,
+    ) -> Result<Vec<u8>, RemoteDigestSignerError>;
+
+    /// Format of the ECDSA signatures returned by `sign_digest`. RSA signatures need no conversion.
+    fn ecdsa_signature_format(&self) -> EcdsaSignatureFormat {
+        EcdsaSignatureFormat::Der
+    }
+}
+
+impl<T: RemoteDigestSigner + ?Sized> RemoteDigestSigner for Box<T> {
+    fn sign_digest(
+        &self,
+        digest: &[u8],
+        algorithm: Algorithm,
+    ) -> Result<Vec<u8>, RemoteDigestSignerError> {
+        (**self).sign_digest(digest, algorithm)
+    }
+
+    fn ecdsa_signature_format(&self) -> EcdsaSignatureFormat {
+        (**self).ecdsa_signature_format()
+    }
+}
+
+/// Adapter implementing `JwtSigner` on top of any [`RemoteDigestSigner`].
+pub struct RemoteDigestJwtSigner<S = Box<dyn RemoteDigestSigner + Send + Sync>>
+where
+    S: RemoteDigestSigner,
+{
+    signer: S,
+    algorithm: Algorithm,
+}
+
+impl<S: RemoteDigestSigner> Debug for RemoteDigestJwtSigner<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteDigestJwtSigner")
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl<S: RemoteDigestSigner> RemoteDigestJwtSigner<S> {
+    /// Builds the adapter for a key of the given JWS algorithm. Only RSA (RS*, PS*) and ECDSA
+    /// (ES*) algorithms sign digests; HMAC and EdDSA are rejected.
+    pub fn new(signer: S, algorithm: Algorithm) -> Result<Self, RemoteDigestSignerError> {
+        digest_algorithm(algorithm)?;
+        Ok(Self { signer, algorithm })
+    }
+
+    fn sign_input(&self, input: &str) -> Result<String, RemoteDigestSignerError> {
+        let digest = digest::digest(digest_algorithm(self.algorithm)?, input.as_bytes());
+        let signature = self.signer.sign_digest(digest.as_ref(), self.algorithm)?;
+
+        let signature = match (
+            ecdsa_component_len(self.algorithm),
+            self.signer.ecdsa_signature_format(),
+        ) {
+            (Some(len), EcdsaSignatureFormat::Der) => ecdsa_der_to_raw(&signature, len)?,
+            (Some(len), EcdsaSignatureFormat::Raw) if signature.len() != 2 * len => {
+                return Err(RemoteDigestSignerError::InvalidSignature(format!(
+                    "expected {} bytes, got {}",
+                    2 * len,
+                    signature.len()
+                )));
+            }
+            _ => signature,
+        };
+        Ok(BASE64_URL_SAFE_NO_PAD.encode(signature))
+    }
+}
+
+/// Sign a JWT delegating the signature of its digest to a remote backend.
+impl<S: RemoteDigestSigner> JwtSigner for RemoteDigestJwtSigner<S> {
+    fn sign(&self, claims: Claims) -> Result<SignedJwt, JwtEncoderError> {
+        let input = signing_input(&Header::new(self.algorithm), &claims)?;
+        let signature = self.sign_input(&input)?;
+        Ok(SignedJwt {
+            value: format!("{input}.{signature}"),
+        })
+    }
+}
+
+fn digest_algorithm(
+    algorithm: Algorithm,
+) -> Result<&'static digest::Algorithm, RemoteDigestSignerError> {
+    match algorithm {
+        Algorithm::RS256 | Algorithm::PS256 | Algorithm::ES256 => Ok(&SHA256),
+        Algorithm::RS384 | Algorithm::PS384 | Algorithm::ES384 => Ok(&SHA384),
+        Algorithm::RS512 | Algorithm::PS512 => Ok(&SHA512),
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 | Algorithm::EdDSA => {
+            Err(RemoteDigestSignerError::UnsupportedAlgorithm(algorithm))
+        }
+    }
+}
+
+/// Size in bytes of each of the `r` and `s` components of the JWS ECDSA signatures.
+fn ecdsa_component_len(algorithm: Algorithm) -> Option<usize> {
+    match algorithm {
+        Algorithm::ES256 => Some(32),
+        Algorithm::ES384 => Some(48),
+        _ => None,
+    }
+}
+
+/// Converts a DER-encoded ECDSA signature into the fixed-size `r || s` form (RFC 7518 §3.4).
+fn ecdsa_der_to_raw(der: &[u8], len: usize) -> Result<Vec<u8>, RemoteDigestSignerError> {
+    let (r, s) = yasna::parse_der(der, |reader| {
+        reader.read_sequence(|reader| {
+            let (r, _) = reader.next().read_bigint_bytes()?;
+            let (s, _) = reader.next().read_bigint_bytes()?;
+            Ok((r, s))
+        })
+    })
+    .map_err(|e| RemoteDigestSignerError::InvalidSignature(e.to_string()))?;
+
+    let mut raw = vec![0; 2 * len];
+    let (raw_r, raw_s) = raw.split_at_mut(len);
+    for (component, out) in [(r, raw_r), (s, raw_s)] {
+        // DER integers are minimal and signed, so they may be shorter or carry a leading zero.
+        let first_non_zero = component
+            .iter()
+            .position(|b| *b != 0)
+            .unwrap_or(component.len());
+        let component = &component[first_non_zero..];
+        if component.len() > len {
+            return Err(RemoteDigestSignerError::InvalidSignature(format!(
+                "signature component longer than {len} bytes"
+            )));
+        }
+        out[len - component.len()..].copy_from_slice(component);
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_lc_rs::digest::Digest;
+    use aws_lc_rs::signature::{
+        ECDSA_P256_SHA256_ASN1_SIGNING, ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair,
+        RSA_PKCS1_SHA256, RsaKeyPair,
+    };
+    use http::Uri;
+    use jsonwebtoken::{DecodingKey, Validation, get_current_timestamp};
+    use rcgen::PKCS_ECDSA_P256_SHA256;
+
+    use super::*;
+    use crate::jwt::signer::local::test::{RS256_PRIVATE_KEY, RS256_PUBLIC_KEY};
+
+    /// Test backend signing digests locally, the way a KMS would.
+    enum LocalDigestSigner {
+        Rsa(RsaKeyPair),
+        Ecdsa(EcdsaKeyPair, EcdsaSignatureFormat),
+    }
+
+    impl RemoteDigestSigner for LocalDigestSigner {
+        fn sign_digest(
+            &self,
+            digest: &[u8],
+            _algorithm: Algorithm,
+        ) -> Result<Vec<u8>, RemoteDigestSignerError> {
+            match self {
+                Self::Rsa(key_pair) => {
+                    let digest = Digest::import_less_safe(digest, &SHA256).unwrap();
+                    let mut signature = vec![0; key_pair.public_modulus_len()];
+                    key_pair
+                        .sign_digest(&RSA_PKCS1_SHA256, &digest, &mut signature)
+                        .unwrap();
+                    Ok(signature)
+                }
+                Self::Ecdsa(key_pair, _) => {
+                    let digest = Digest::import_less_safe(digest, &SHA256).unwrap();
+                    Ok(key_pair.sign_digest(&digest).unwrap().as_ref().to_vec())
+                }
+            }
+        }
+
+        fn ecdsa_signature_format(&self) -> EcdsaSignatureFormat {
+            match self {
+                Self::Ecdsa(_, format) => *format,
+                Self::Rsa(_) => EcdsaSignatureFormat::Der,
+            }
+        }
+    }
+
+    fn claims() -> Claims {
+        Claims::new(
+            "client-id".to_string(),
+            Uri::from_static("https://www.newrelic.com/"),
+            get_current_timestamp() + 60,
+        )
+    }
+
+    fn decode(token: &str, key: &DecodingKey, algorithm: Algorithm) -> Claims {
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&["https://www.newrelic.com/"]);
+        jsonwebtoken::decode::<Claims>(token, key, &validation)
+            .unwrap()
+            .claims
+    }
+
+    fn ecdsa_key_pair(der_signature: bool) -> (EcdsaKeyPair, DecodingKey) {
+        let key_pair = rcgen::KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let decoding_key = DecodingKey::from_ec_pem(key_pair.public_key_pem().as_bytes()).unwrap();
+        let signing = if der_signature {
+            &ECDSA_P256_SHA256_ASN1_SIGNING
+        } else {
+            &ECDSA_P256_SHA256_FIXED_SIGNING
+        };
+        let key_pair = EcdsaKeyPair::from_pkcs8(signing, &key_pair.serialize_der()).unwrap();
+        (key_pair, decoding_key)
+    }
+
+    #[test]
+    fn rsa_signature() {
+        let der = pem::parse(RS256_PRIVATE_KEY).unwrap();
+        let key_pair = RsaKeyPair::from_pkcs8(der.contents()).unwrap();
+        let signer =
+            RemoteDigestJwtSigner::new(LocalDigestSigner::Rsa(key_pair), Algorithm::RS256).unwrap();
+
+        let signed = signer.sign(claims()).unwrap();
+        let decoding_key = DecodingKey::from_rsa_pem(RS256_PUBLIC_KEY.as_bytes()).unwrap();
+        assert_eq!(
+            decode(&signed.value, &decoding_key, Algorithm::RS256).iss,
+            "client-id"
+        );
+    }
+
+    #[test]
+    fn ecdsa_der_signature_is_converted() {
+        let (key_pair, decoding_key) = ecdsa_key_pair(true);
+        let signer = RemoteDigestJwtSigner::new(
+            LocalDigestSigner::Ecdsa(key_pair, EcdsaSignatureFormat::Der),
+            Algorithm::ES256,
+        )
+        .unwrap();
+
+        let signed = signer.sign(claims()).unwrap();
+        assert_eq!(
+            decode(&signed.value, &decoding_key, Algorithm::ES256).iss,
+            "client-id"
+        );
+    }
+
+    #[test]
+    fn ecdsa_raw_signature() {
+        let (key_pair, decoding_key) = ecdsa_key_pair(false);
+        let signer = RemoteDigestJwtSigner::new(
+            LocalDigestSigner::Ecdsa(key_pair, EcdsaSignatureFormat::Raw),
+            Algorithm::ES256,
+        )
+        .unwrap();
+
+        let signed = signer.sign(claims()).unwrap();
+        assert_eq!(
+            decode(&signed.value, &decoding_key, Algorithm::ES256).iss,
+            "client-id"
+        );
+    }
+
+    #[test]
+    fn der_to_raw_pads_and_strips_components() {
+        // r has a leading zero (high bit set) and s is shorter than the component size
+        let mut r = vec![0x00, 0x80];
+        r.extend([0x11; 31]);
+        let s = vec![0x01, 0x02];
+        let der = yasna::construct_der(|writer| {
+            writer.write_sequence(|writer| {
+                writer.next().write_bigint_bytes(&r[1..], true);
+                writer.next().write_bigint_bytes(&s, true);
+            })
+        });
+
+        let raw = ecdsa_der_to_raw(&der, 32).unwrap();
+        assert_eq!(&raw[..32], &r[1..]);
+        assert_eq!(&raw[32..62], &[0; 30]);
+        assert_eq!(&raw[62..], &s);
+
+        assert!(matches!(
+            ecdsa_der_to_raw(&[0x30, 0x00], 32),
+            Err(RemoteDigestSignerError::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_algorithms() {
+        let der = pem::parse(RS256_PRIVATE_KEY).unwrap();
+        let key_pair = RsaKeyPair::from_pkcs8(der.contents()).unwrap();
+        assert!(matches!(
+            RemoteDigestJwtSigner::new(LocalDigestSigner::Rsa(key_pair), Algorithm::EdDSA),
+            Err(RemoteDigestSignerError::UnsupportedAlgorithm(
+                Algorithm::EdDSA
+            ))
+        ));
+    }
+}
@@ -0,0 +1,577 @@
+//! JWT signer backed by the HashiCorp Vault [Transit secrets engine](https://developer.hashicorp.com/vault/docs/secrets/transit).
+//!
+//! The private key never leaves Vault: the signer builds the JWS signing input locally and asks
+//! Transit to sign it, requesting a JWS-marshaled signature so it can be appended to the token as is.
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use http::header::CONTENT_TYPE;
+use http::{Method, Request, Uri};
+use jsonwebtoken::{Algorithm, Header};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::http_client::HttpClient;
+use crate::jwt::{claims::Claims, error::JwtEncoderError, signed::SignedJwt};
+
+use super::{JwtSigner, signing_input};
+
+const VAULT_TOKEN_HEADER: &str = "X-Vault-Token";
+const VAULT_NAMESPACE_HEADER: &str = "X-Vault-Namespace";
+/// Default mount path of the Transit secrets engine.
+pub const DEFAULT_TRANSIT_MOUNT: &str = "transit";
+/// Default mount path of the AppRole auth method.
+pub const DEFAULT_APPROLE_MOUNT: &str = "approle";
+/// Tokens obtained through AppRole are renewed this long before their lease expires.
+const TOKEN_RENEWAL_MARGIN: Duration = Duration::from_secs(10);
+
+/// Errors that can occur when signing through Vault Transit.
+#[derive(Debug, Error)]
+pub enum VaultTransitSignerError {
+    #[error("unsupported signing algorithm for Vault Transit: `{0:?}`")]
+    UnsupportedAlgorithm(Algorithm),
+    #[error("building vault request: `{0}`")]
+    Request(String),
+    #[error("vault transport error: `{0}`")]
+    Transport(String),
+    #[error("vault responded with status `{0}`: `{1}`")]
+    Response(u16, String),
+    #[error("decoding vault response: `{0}`")]
+    Decode(String),
+    #[error("acquiring vault token mutex lock")]
+    PoisonError,
+}
+
+impl From<VaultTransitSignerError> for JwtEncoderError {
+    fn from(err: VaultTransitSignerError) -> Self {
+        JwtEncoderError::TokenEncoding(err.to_string())
+    }
+}
+
+/// Authentication method used against Vault.
+#[derive(Clone)]
+pub enum VaultAuth {
+    /// A Vault token used as is.
+    Token(String),
+    /// AppRole credentials, exchanged for a token at `auth/<mount>/login`.
+    AppRole {
+        mount: String,
+        role_id: String,
+        secret_id: String,
+    },
+}
+
+impl VaultAuth {
+    /// AppRole credentials using the default `approle` mount path.
+    pub fn app_role(role_id: String, secret_id: String) -> Self {
+        Self::AppRole {
+            mount: DEFAULT_APPROLE_MOUNT.to_string(),
+            role_id,
+            secret_id,
+        }
+    }
+}
+
+impl Debug for VaultAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Token(_) => write!(f, "VaultAuth::Token(<REDACTED>)"),
+            Self::AppRole { mount, role_id, .. } => f
+                .debug_struct("VaultAuth::AppRole")
+                .field("mount", mount)
+                .field("role_id", role_id)
+                .field("secret_id", &"REDACTED")
+                .finish(),
+        }
+    }
+}
+
+/// Configuration for [`VaultTransitSigner`].
+#[derive(Debug, Clone)]
+pub struct VaultTransitConfig {
+    /// Vault server address, e.g. `http://127.0.0.1:8200`.
+    pub address: Uri,
+    /// Mount path of the Transit secrets engine.
+    pub mount: String,
+    /// Name of the Transit key used to sign.
+    pub key_name: String,
+    /// Version of the Transit key to sign with. The latest one is used if not set.
+    pub key_version: Option<u32>,
+    /// JWS algorithm, which must match the Transit key type.
+    pub algorithm: Algorithm,
+    /// Vault Enterprise namespace.
+    pub namespace: Option<String>,
+    /// Authentication method.
+    pub auth: VaultAuth,
+}
+
+impl VaultTransitConfig {
+    /// Creates a configuration for an RSA Transit key signing with RS256 on the default mount.
+    pub fn new(address: Uri, key_name: String, auth: VaultAuth) -> Self {
+        Self {
+            address,
+            mount: DEFAULT_TRANSIT_MOUNT.to_string(),
+            key_name,
+            key_version: None,
+            algorithm: Algorithm::RS256,
+            namespace: None,
+            auth,
+        }
+    }
+
+    pub fn with_mount(self, mount: String) -> Self {
+        Self { mount, ..self }
+    }
+
+    pub fn with_key_version(self, key_version: u32) -> Self {
+        Self {
+            key_version: Some(key_version),
+            ..self
+        }
+    }
+
+    pub fn with_algorithm(self, algorithm: Algorithm) -> Self {
+        Self { algorithm, ..self }
+    }
+
+    pub fn with_namespace(self, namespace: String) -> Self {
+        Self {
+            namespace: Some(namespace),
+            ..self
+        }
+    }
+}
+
+/// Signer structure that delegates the signature of JWTs to a Vault Transit key.
+pub struct VaultTransitSigner<C = crate::http::client::HttpClient>
+where
+    C: HttpClient,
+{
+    http_client: C,
+    config: VaultTransitConfig,
+    /// Token obtained from the auth method, along with the instant it should be renewed at.
+    token: Mutex<Option<(String, Option<Instant>)>>,
+}
+
+impl<C: HttpClient> Debug for VaultTransitSigner<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultTransitSigner")
+            .field("http_client", &"impl HttpClient")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<C: HttpClient> VaultTransitSigner<C> {
+    /// Creates a new signer. Fails if the configured algorithm cannot be produced by Transit.
+    pub fn new(
+        http_client: C,
+        config: VaultTransitConfig,
+    ) -> Result<Self, VaultTransitSignerError> {
+        TransitAlgorithm::try_from(config.algorithm)?;
+        Ok(Self {
+            http_client,
+            config,
+            token: Mutex::new(None),
+        })
+    }
+
+    fn sign_input(&self, input: &str) -> Result<String, VaultTransitSignerError> {
+        let transit_algorithm = TransitAlgorithm::try_from(self.config.algorithm)?;
+        let body = SignRequest {
+            input: BASE64_STANDARD.encode(input),
+            marshaling_algorithm: "jws",
+            signature_algorithm: transit_algorithm.signature_algorithm,
+            salt_length: transit_algorithm.salt_length,
+            key_version: self.config.key_version,
+        };
+        let path = format!(
+            "v1/{}/sign/{}/{}",
+            self.config.mount, self.config.key_name, transit_algorithm.hash_algorithm
+        );
+
+        let token = self.token()?;
+        let response: VaultResponse<SignResponseData> =
+            self.post(&path, &body, Some(token.as_str()))?;
+
+        // Transit prefixes signatures with the key version, e.g. `vault:v1:<signature>`.
+        response
+            .data
+            .signature
+            .rsplit_once(':')
+            .map(|(_, signature)| signature.to_string())
+            .ok_or_else(|| {
+                VaultTransitSignerError::Decode(format!(
+                    "unexpected signature format: {}",
+                    response.data.signature
+                ))
+            })
+    }
+
+    /// Returns a valid Vault token, logging in through the auth method if needed.
+    fn token(&self) -> Result<String, VaultTransitSignerError> {
+        let (mount, role_id, secret_id) = match &self.config.auth {
+            VaultAuth::Token(token) => return Ok(token.to_owned()),
+            VaultAuth::AppRole {
+                mount,
+                role_id,
+                secret_id,
+            } => (mount, role_id, secret_id),
+        };
+
+        let mut cached_token = self
+            .token
+            .lock()
+            .map_err(|_| VaultTransitSignerError::PoisonError)?;
+
+        if let Some((token, renew_at)) = cached_token.as_ref()
+            && renew_at.is_none_or(|renew_at| Instant::now() < renew_at)
+        {
+            return Ok(token.to_owned());
+        }
+
+        debug!("logging in to vault using approle");
+        let body = AppRoleLoginRequest { role_id, secret_id };
+        let response: AppRoleLoginResponse =
+            self.post(&format!("v1/auth/{mount}/login"), &body, None)?;
+
+        // A lease duration of 0 means the token does not expire.
+        let renew_at = (response.auth.lease_duration > 0).then(|| {
+            Instant::now()
+                + Duration::from_secs(response.auth.lease_duration)
+                    .saturating_sub(TOKEN_RENEWAL_MARGIN)
+        });
+        *cached_token = Some((response.auth.client_token.to_owned(), renew_at));
+
+        Ok(response.auth.client_token)
+    }
+
+    fn post<B: Serialize, R: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        body: &B,
+        token: Option<&str>,
+    ) -> Result<R, VaultTransitSignerError> {
+        let body = serde_json::to_vec(body)
+            .map_err(|e| VaultTransitSignerError::Request(e.to_string()))?;
+
+        let mut request_builder = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "{}/{path}",
+                self.config.address.to_string().trim_end_matches('/')
+            ))
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request_builder = request_builder.header(VAULT_TOKEN_HEADER, token);
+        }
+        if let Some(namespace) = &self.config.namespace {
+            request_builder = request_builder.header(VAULT_NAMESPACE_HEADER, namespace);
+        }
+        let request = request_builder
+            .body(body)
+            .map_err(|e| VaultTransitSignerError::Request(e.to_string()))?;
+
+        let response = self
+            .http_client
+            .send(request)
+            .map_err(|e| VaultTransitSignerError::Transport(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(VaultTransitSignerError::Response(
+                response.status().as_u16(),
+                String::from_utf8_lossy(response.body()).to_string(),
+            ));
+        }
+
+        serde_json::from_slice(response.body())
+            .map_err(|e| VaultTransitSignerError::Decode(e.to_string()))
+    }
+}
+
+/// Sign a JWT using a Vault Transit key.
+impl<C: HttpClient> JwtSigner for VaultTransitSigner<C> {
+    fn sign(&self, claims: Claims) -> Result<SignedJwt, JwtEncoderError> {
+        let input = signing_input(&Header::new(self.config.algorithm), &claims)?;
+        let signature = self.sign_input(&input)?;
+        Ok(SignedJwt {
+            value: format!("{input}.{signature}"),
+        })
+    }
+}
+
+/// Transit parameters producing the signature expected for a JWS algorithm.
+struct TransitAlgorithm {
+    hash_algorithm: &'static str,
+    signature_algorithm: Option<&'static str>,
+    salt_length: Option<&'static str>,
+}
+
+impl TryFrom<Algorithm> for TransitAlgorithm {
+    type Error = VaultTransitSignerError;
+
+    fn try_from(algorithm: Algorithm) -> Result<Self, Self::Error> {
+        // RFC 7518 requires PSS salts as long as the hash, while Transit defaults to the
+        // longest salt the key allows.
+        let pss = (Some("pss"), Some("hash"));
+        let (hash_algorithm, (signature_algorithm, salt_length)) = match algorithm {
+            Algorithm::RS256 => ("sha2-256", (Some("pkcs1v15"), None)),
+            Algorithm::RS384 => ("sha2-384", (Some("pkcs1v15"), None)),
+            Algorithm::RS512 => ("sha2-512", (Some("pkcs1v15"), None)),
+            Algorithm::PS256 => ("sha2-256", pss),
+            Algorithm::PS384 => ("sha2-384", pss),
+            Algorithm::PS512 => ("sha2-512", pss),
+            Algorithm::ES256 => ("sha2-256", (None, None)),
+            Algorithm::ES384 => ("sha2-384", (None, None)),
+            // Ed25519 keys ignore the hash algorithm.
+            Algorithm::EdDSA => ("sha2-512", (None, None)),
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                return Err(VaultTransitSignerError::UnsupportedAlgorithm(algorithm));
+            }
+        };
+        Ok(Self {
+            hash_algorithm,
+            signature_algorithm,
+            salt_length,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    input: String,
+    marshaling_algorithm: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature_algorithm: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    salt_length: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_version: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct VaultResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct SignResponseData {
+    signature: String,
+}
+
+#[derive(Serialize)]
+struct AppRoleLoginRequest<'a> {
+    role_id: &'a str,
+    secret_id: &'a str,
+}
+
+#[derive(Deserialize)]
+struct AppRoleLoginResponse {
+    auth: AppRoleLoginAuth,
+}
+
+#[derive(Deserialize)]
+struct AppRoleLoginAuth {
+    client_token: String,
+    lease_duration: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use httpmock::{Method::POST, MockServer};
+    use jsonwebtoken::{DecodingKey, EncodingKey, Validation, get_current_timestamp};
+    use serde_json::json;
+
+    use super::*;
+    use crate::http::client::HttpClient;
+    use crate::http::config::HttpConfig;
+    use crate::jwt::signer::local::test::{RS256_PRIVATE_KEY, RS256_PUBLIC_KEY};
+    use crate::parameters::DEFAULT_AUTHENTICATOR_TIMEOUT;
+
+    const KEY_NAME: &str = "nr-auth";
+
+    fn http_client() -> HttpClient {
+        HttpClient::new(HttpConfig::new(
+            DEFAULT_AUTHENTICATOR_TIMEOUT,
+            DEFAULT_AUTHENTICATOR_TIMEOUT,
+            Default::default(),
+        ))
+        .unwrap()
+    }
+
+    fn test_claims() -> Claims {
+        Claims::new(
+            "client-id".to_string(),
+            Uri::from_static("https://www.newrelic.com/"),
+            get_current_timestamp() + 60,
+        )
+    }
+
+    /// Signs the input the same way Transit would for an RS256 JWS-marshaled signature.
+    fn transit_signature(input: &str) -> String {
+        let key = EncodingKey::from_rsa_pem(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        let signature =
+            jsonwebtoken::crypto::sign(input.as_bytes(), &key, Algorithm::RS256).unwrap();
+        format!("vault:v1:{signature}")
+    }
+
+    fn decode(jwt: &SignedJwt) -> Claims {
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&["https://www.newrelic.com/"]);
+        jsonwebtoken::decode::<Claims>(
+            jwt.value(),
+            &DecodingKey::from_rsa_pem(RS256_PUBLIC_KEY.as_bytes()).unwrap(),
+            &validation,
+        )
+        .unwrap()
+        .claims
+    }
+
+    #[test]
+    fn vault_transit_signer_with_token() {
+        let claims = test_claims();
+        let input = signing_input(&Header::new(Algorithm::RS256), &claims).unwrap();
+
+        let server = MockServer::start();
+        let sign_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!("/v1/transit/sign/{KEY_NAME}/sha2-256"))
+                .header(VAULT_TOKEN_HEADER, "root")
+                .json_body(json!({
+                    "input": BASE64_STANDARD.encode(&input),
+                    "marshaling_algorithm": "jws",
+                    "signature_algorithm": "pkcs1v15",
+                }));
+            then.status(200)
+                .json_body(json!({"data": {"signature": transit_signature(&input)}}));
+        });
+
+        let config = VaultTransitConfig::new(
+            server.base_url().parse().unwrap(),
+            KEY_NAME.to_string(),
+            VaultAuth::Token("root".to_string()),
+        );
+        let signer = VaultTransitSigner::new(http_client(), config).unwrap();
+
+        let jti = claims.jti;
+        let signed_jwt = signer.sign(claims).unwrap();
+
+        sign_mock.assert();
+        let decoded = decode(&signed_jwt);
+        assert_eq!(decoded.jti, jti);
+        assert_eq!(decoded.sub, "client-id");
+    }
+
+    #[test]
+    fn vault_transit_signer_pss_uses_hash_salt_length() {
+        let claims = test_claims();
+        let input = signing_input(&Header::new(Algorithm::PS256), &claims).unwrap();
+
+        let server = MockServer::start();
+        let sign_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!("/v1/transit/sign/{KEY_NAME}/sha2-256"))
+                .json_body(json!({
+                    "input": BASE64_STANDARD.encode(&input),
+                    "marshaling_algorithm": "jws",
+                    "signature_algorithm": "pss",
+                    "salt_length": "hash",
+                }));
+            // The signature is not verified in this test
+            then.status(200)
+                .json_body(json!({"data": {"signature": "vault:v1:c2lnbmF0dXJl"}}));
+        });
+
+        let config = VaultTransitConfig::new(
+            server.base_url().parse().unwrap(),
+            KEY_NAME.to_string(),
+            VaultAuth::Token("root".to_string()),
+        )
+        .with_algorithm(Algorithm::PS256);
+        let signer = VaultTransitSigner::new(http_client(), config).unwrap();
+
+        signer.sign(claims).unwrap();
+
+        sign_mock.assert();
+    }
+
+    #[test]
+    fn vault_transit_signer_with_approle_reuses_token() {
+        let server = MockServer::start();
+        let login_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/auth/approle/login")
+                .json_body(json!({"role_id": "role", "secret_id": "secret"}));
+            then.status(200).json_body(json!({
+                "auth": {"client_token": "approle-token", "lease_duration": 3600}
+            }));
+        });
+        let sign_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!("/v1/custom-transit/sign/{KEY_NAME}/sha2-256"))
+                .header(VAULT_TOKEN_HEADER, "approle-token")
+                .header(VAULT_NAMESPACE_HEADER, "ns1");
+            // The signature is not verified in this test
+            then.status(200)
+                .json_body(json!({"data": {"signature": "vault:v2:c2lnbmF0dXJl"}}));
+        });
+
+        let config = VaultTransitConfig::new(
+            server.base_url().parse().unwrap(),
+            KEY_NAME.to_string(),
+            VaultAuth::app_role("role".to_string(), "secret".to_string()),
+        )
+        .with_mount("custom-transit".to_string())
+        .with_namespace("ns1".to_string());
+        let signer = VaultTransitSigner::new(http_client(), config).unwrap();
+
+        let first = signer.sign(test_claims()).unwrap();
+        let second = signer.sign(test_claims()).unwrap();
+
+        login_mock.assert_calls(1);
+        sign_mock.assert_calls(2);
+        assert!(first.value().ends_with(".c2lnbmF0dXJl"));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn vault_transit_signer_error_response() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST);
+            then.status(403).body(r#"{"errors":["permission denied"]}"#);
+        });
+
+        let config = VaultTransitConfig::new(
+            server.base_url().parse().unwrap(),
+            KEY_NAME.to_string(),
+            VaultAuth::Token("invalid".to_string()),
+        );
+        let signer = VaultTransitSigner::new(http_client(), config).unwrap();
+
+        let err = signer.sign(test_claims()).unwrap_err();
+        assert!(err.to_string().contains("permission denied"));
+    }
+
+    #[test]
+    fn vault_transit_signer_unsupported_algorithm() {
+        let config = VaultTransitConfig::new(
+            Uri::from_static("http://127.0.0.1:8200"),
+            KEY_NAME.to_string(),
+            VaultAuth::Token("root".to_string()),
+        )
+        .with_algorithm(Algorithm::HS256);
+
+        assert_matches!(
+            VaultTransitSigner::new(http_client(), config),
+            Err(VaultTransitSignerError::UnsupportedAlgorithm(
+                Algorithm::HS256
+            ))
+        );
+    }
+}
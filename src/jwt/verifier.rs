@@ -0,0 +1,28 @@
+use super::{claims::Claims, error::JwtDecoderError, signed::SignedJwt};
+
+pub mod jti;
+pub mod local;
+
+/// Leeway applied when checking `exp` and `nbf`, in seconds.
+pub(crate) const DEFAULT_LEEWAY: u64 = 60;
+
+/// A JWT verifier, the counterpart of [`JwtSigner`](super::signer::JwtSigner).
+pub trait JwtVerifier {
+    /// Verifies the signature and claims of the JWT and returns its decoded claims.
+    fn verify(&self, jwt: &SignedJwt) -> Result<Claims, JwtDecoderError>;
+}
+
+#[cfg(test)]
+pub mod tests {
+    use mockall::mock;
+
+    use super::*;
+
+    mock! {
+        pub JwtVerifier {}
+
+        impl JwtVerifier for JwtVerifier {
+            fn verify(&self, jwt: &SignedJwt) -> Result<Claims, JwtDecoderError>;
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Replay protection for verified JWTs.
+//! The `jti` of every accepted JWT is recorded until the JWT expires, so a JWT can only be used
+//! once, matching the single-use semantics enforced by the System Identity Service.
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use jsonwebtoken::get_current_timestamp;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::files::write_private_file;
+use crate::jwt::{claims::Claims, error::JwtDecoderError, signed::SignedJwt};
+
+use super::{DEFAULT_LEEWAY, JwtVerifier};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum JtiStoreError {
+    #[error("jti `{0}` already used")]
+    Replayed(Uuid),
+    #[error("jti storage: `{0}`")]
+    Storage(String),
+    #[error("acquiring jti store lock")]
+    PoisonError,
+}
+
+impl From<JtiStoreError> for JwtDecoderError {
+    fn from(err: JtiStoreError) -> Self {
+        match err {
+            JtiStoreError::Replayed(jti) => JwtDecoderError::Replayed(jti.to_string()),
+            err => JwtDecoderError::JtiStore(err.to_string()),
+        }
+    }
+}
+
+/// Records the `jti` values already used.
+pub trait JtiStore {
+    /// Records `jti` as used until `expires_at` (UTC timestamp). Fails with
+    /// [`JtiStoreError::Replayed`] if it was already recorded and has not expired yet.
+    fn record(&self, jti: Uuid, expires_at: u64) -> Result<(), JtiStoreError>;
+}
+
+/// Records a `jti` in `seen`, evicting the expired entries first.
+fn record_at(
+    seen: &mut HashMap<Uuid, u64>,
+    jti: Uuid,
+    expires_at: u64,
+    now: u64,
+) -> Result<(), JtiStoreError> {
+    seen.retain(|_, expires_at| *expires_at >= now);
+    if seen.contains_key(&jti) {
+        return Err(JtiStoreError::Replayed(jti));
+    }
+    seen.insert(jti, expires_at);
+    Ok(())
+}
+
+/// In-memory [`JtiStore`]. Entries are evicted once expired.
+#[derive(Debug, Default)]
+pub struct InMemoryJtiStore {
+    seen: Mutex<HashMap<Uuid, u64>>,
+}
+
+impl JtiStore for InMemoryJtiStore {
+    fn record(&self, jti: Uuid, expires_at: u64) -> Result<(), JtiStoreError> {
+        let mut seen = self.seen.lock().map_err(|_| JtiStoreError::PoisonError)?;
+        record_at(&mut seen, jti, expires_at, get_current_timestamp())
+    }
+}
+
+/// File-backed [`JtiStore`], so used `jti` values survive restarts. The file holds a JSON object
+/// mapping each `jti` to its expiration, and is atomically rewritten with owner-only permissions
+/// on each record.
+///
+/// Each record holds an exclusive OS lock on the `<path>.lock` file while reading and rewriting
+/// the store, so several stores on the same path, in this or other processes, never lose each
+/// other's records.
+#[derive(Debug)]
+pub struct FileJtiStore {
+    path: PathBuf,
+    lock_path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileJtiStore {
+    pub fn new(path: PathBuf) -> Self {
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        Self {
+            path,
+            lock_path: lock_path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Takes the exclusive lock on the lock file, released when the returned file is dropped.
+    fn lock_file(&self) -> Result<File, JtiStoreError> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let file = options.open(&self.lock_path).map_err(|e| {
+            JtiStoreError::Storage(format!("opening {}: {e}", self.lock_path.display()))
+        })?;
+        file.lock().map_err(|e| {
+            JtiStoreError::Storage(format!("locking {}: {e}", self.lock_path.display()))
+        })?;
+        Ok(file)
+    }
+
+    fn load(&self) -> Result<HashMap<Uuid, u64>, JtiStoreError> {
+        match fs::read(&self.path) {
+            Ok(content) => serde_json::from_slice(&content).map_err(|e| {
+                JtiStoreError::Storage(format!("parsing {}: {e}", self.path.display()))
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(JtiStoreError::Storage(format!(
+                "reading {}: {e}",
+                self.path.display()
+            ))),
+        }
+    }
+
+    fn save(&self, seen: &HashMap<Uuid, u64>) -> Result<(), JtiStoreError> {
+        let content =
+            serde_json::to_vec(seen).map_err(|e| JtiStoreError::Storage(e.to_string()))?;
+        write_private_file(&self.path, &content, None)
+            .map_err(|e| JtiStoreError::Storage(format!("writing {}: {e}", self.path.display())))
+    }
+}
+
+impl JtiStore for FileJtiStore {
+    fn record(&self, jti: Uuid, expires_at: u64) -> Result<(), JtiStoreError> {
+        // The mutex serializes the records of this instance, the file lock those of any other.
+        let _guard = self.lock.lock().map_err(|_| JtiStoreError::PoisonError)?;
+        let _file_lock = self.lock_file()?;
+        let mut seen = self.load()?;
+        record_at(&mut seen, jti, expires_at, get_current_timestamp())?;
+        self.save(&seen)
+    }
+}
+
+/// Wraps a [`JwtVerifier`] rejecting JWTs whose `jti` was already used.
+#[derive(Debug)]
+pub struct ReplayProtectedVerifier<V, S>
+where
+    V: JwtVerifier,
+    S: JtiStore,
+{
+    verifier: V,
+    store: S,
+    leeway: u64,
+}
+
+impl<V: JwtVerifier, S: JtiStore> ReplayProtectedVerifier<V, S> {
+    pub fn new(verifier: V, store: S) -> Self {
+        Self {
+            verifier,
+            store,
+            leeway: DEFAULT_LEEWAY,
+        }
+    }
+
+    /// Sets how long, in seconds, a `jti` is kept after `exp`. It should match the leeway of the
+    /// wrapped verifier, as JWTs are accepted until `exp` plus that leeway.
+    pub fn with_leeway(self, leeway: u64) -> Self {
+        Self { leeway, ..self }
+    }
+}
+
+impl<V: JwtVerifier, S: JtiStore> JwtVerifier for ReplayProtectedVerifier<V, S> {
+    fn verify(&self, jwt: &SignedJwt) -> Result<Claims, JwtDecoderError> {
+        // Only valid JWTs are recorded, so garbage cannot fill the store.
+        let claims = self.verifier.verify(jwt)?;
+        self.store
+            .record(claims.jti, claims.exp.saturating_add(self.leeway))?;
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use http::Uri;
+    use jsonwebtoken::Algorithm;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::jwt::signer::JwtSigner;
+    use crate::jwt::signer::local::LocalPrivateKeySigner;
+    use crate::jwt::signer::local::test::{RS256_PRIVATE_KEY, RS256_PUBLIC_KEY};
+    use crate::jwt::verifier::local::LocalJwtVerifier;
+
+    #[test]
+    fn record_and_evict() {
+        let mut seen = HashMap::new();
+        let jti = Uuid::now_v7();
+
+        assert_eq!(record_at(&mut seen, jti, 100, 50), Ok(()));
+        assert_eq!(
+            record_at(&mut seen, jti, 100, 100),
+            Err(JtiStoreError::Replayed(jti))
+        );
+        // Evicted once expired
+        assert_eq!(record_at(&mut seen, jti, 200, 101), Ok(()));
+        assert_eq!(record_at(&mut seen, Uuid::now_v7(), 200, 101), Ok(()));
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn in_memory_store_rejects_replays() {
+        let store = InMemoryJtiStore::default();
+        let jti = Uuid::now_v7();
+        let expires_at = get_current_timestamp() + 60;
+
+        assert!(store.record(jti, expires_at).is_ok());
+        assert_eq!(
+            store.record(jti, expires_at),
+            Err(JtiStoreError::Replayed(jti))
+        );
+    }
+
+    #[test]
+    fn file_store_persists_jtis() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("jti.json");
+        let jti = Uuid::now_v7();
+        let expires_at = get_current_timestamp() + 60;
+
+        assert!(
+            FileJtiStore::new(path.clone())
+                .record(jti, expires_at)
+                .is_ok()
+        );
+        // Only the store and lock files remain, no temporary file is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // A new instance, as after a restart, still knows the jti
+        let store = FileJtiStore::new(path.clone());
+        assert_eq!(
+            store.record(jti, expires_at),
+            Err(JtiStoreError::Replayed(jti))
+        );
+
+        fs::write(&path, "not json").unwrap();
+        assert_matches!(
+            store.record(Uuid::now_v7(), expires_at),
+            Err(JtiStoreError::Storage(_))
+        );
+    }
+
+    #[test]
+    fn file_stores_on_the_same_path_keep_every_record() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("jti.json");
+        let expires_at = get_current_timestamp() + 60;
+        let jtis = (0..40).map(|_| Uuid::now_v7()).collect::<Vec<_>>();
+
+        // Independent instances, as in separate processes, recording concurrently
+        std::thread::scope(|scope| {
+            for jtis in jtis.chunks(10) {
+                let store = FileJtiStore::new(path.clone());
+                scope.spawn(move || {
+                    for jti in jtis {
+                        store.record(*jti, expires_at).unwrap();
+                    }
+                });
+            }
+        });
+
+        let store = FileJtiStore::new(path);
+        for jti in jtis {
+            assert_eq!(
+                store.record(jti, expires_at),
+                Err(JtiStoreError::Replayed(jti))
+            );
+        }
+    }
+
+    #[test]
+    fn replayed_jwt_is_rejected() {
+        let signer = LocalPrivateKeySigner::try_from(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        let verifier = ReplayProtectedVerifier::new(
+            LocalJwtVerifier::from_public_key_pem(RS256_PUBLIC_KEY.as_bytes(), Algorithm::RS256)
+                .unwrap(),
+            InMemoryJtiStore::default(),
+        );
+        let claims = Claims::new(
+            "client-id".to_string(),
+            Uri::from_static("https://www.newrelic.com/"),
+            get_current_timestamp() + 60,
+        );
+
+        let signed = signer.sign(claims.clone()).unwrap();
+        assert!(verifier.verify(&signed).is_ok());
+        assert_matches!(verifier.verify(&signed), Err(JwtDecoderError::Replayed(_)));
+
+        // Cloned claims get a new jti, so they are accepted
+        let signed = signer.sign(claims.clone()).unwrap();
+        assert!(verifier.verify(&signed).is_ok());
+    }
+}
@@ -0,0 +1,417 @@
+use std::{fmt::Debug, str::FromStr};
+
+use http::Uri;
+use jsonwebtoken::jwk::{JwkSet, PublicKeyUse};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::jwt::{claims::Claims, error::JwtDecoderError, signed::SignedJwt};
+use crate::token_retriever::credential::DEFAULT_AUDIENCE;
+
+use super::{DEFAULT_LEEWAY, JwtVerifier};
+
+/// A public key the verifier accepts, along with the algorithms it can verify.
+struct VerificationKey {
+    key_id: Option<String>,
+    decoding_key: DecodingKey,
+    algorithms: Vec<Algorithm>,
+}
+
+/// Verifier structure that checks JWTs against local public keys (PEM or JWKS).
+///
+/// Besides the signature, it requires `exp`, `aud`, `iss` and `sub`, checks that the audience
+/// is the expected one, that the issuer and subject are the same client and that `jti` is a
+/// valid UUID.
+pub struct LocalJwtVerifier {
+    keys: Vec<VerificationKey>,
+    audience: String,
+    client_id: Option<String>,
+    leeway: u64,
+}
+
+impl Debug for LocalJwtVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalJwtVerifier")
+            .field(
+                "keys",
+                &self
+                    .keys
+                    .iter()
+                    .map(|key| (&key.key_id, &key.algorithms))
+                    .collect::<Vec<_>>(),
+            )
+            .field("audience", &self.audience)
+            .field("client_id", &self.client_id)
+            .field("leeway", &self.leeway)
+            .finish()
+    }
+}
+
+impl LocalJwtVerifier {
+    fn new(keys: Vec<VerificationKey>) -> Self {
+        Self {
+            keys,
+            audience: DEFAULT_AUDIENCE.to_string(),
+            client_id: None,
+            leeway: DEFAULT_LEEWAY,
+        }
+    }
+
+    /// Creates a verifier for JWTs signed with `algorithm` by the private key matching the given
+    /// PEM-encoded public key.
+    pub fn from_public_key_pem(pem: &[u8], algorithm: Algorithm) -> Result<Self, JwtDecoderError> {
+        let decoding_key = match algorithm {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => DecodingKey::from_rsa_pem(pem),
+            Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(pem),
+            Algorithm::EdDSA => DecodingKey::from_ed_pem(pem),
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                return Err(JwtDecoderError::VerificationKey(format!(
+                    "unsupported algorithm {algorithm:?}"
+                )));
+            }
+        }
+        .map_err(|e| JwtDecoderError::VerificationKey(e.to_string()))?;
+
+        Ok(Self::new(vec![VerificationKey {
+            key_id: None,
+            decoding_key,
+            algorithms: vec![algorithm],
+        }]))
+    }
+
+    /// Creates a verifier accepting any of the keys of a JWK Set. Keys are selected by the `kid`
+    /// header when present. Keys without `alg` accept every algorithm of their key type.
+    ///
+    /// Encryption keys (`use: enc`) and keys whose `alg` is not a JWS algorithm are skipped. It
+    /// fails when no key is left to verify signatures with.
+    pub fn from_jwks(jwks: &JwkSet) -> Result<Self, JwtDecoderError> {
+        let mut keys = Vec::with_capacity(jwks.keys.len());
+        for jwk in &jwks.keys {
+            if jwk.common.public_key_use == Some(PublicKeyUse::Encryption) {
+                debug!("skipping encryption key {:?}", jwk.common.key_id);
+                continue;
+            }
+            let algorithm = match jwk.common.key_algorithm {
+                Some(key_algorithm) => match Algorithm::from_str(&key_algorithm.to_string()) {
+                    Ok(algorithm) => Some(algorithm),
+                    Err(_) => {
+                        debug!(
+                            "skipping key {:?} for non-signature algorithm {key_algorithm}",
+                            jwk.common.key_id
+                        );
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            let decoding_key = DecodingKey::from_jwk(jwk)
+                .map_err(|e| JwtDecoderError::VerificationKey(e.to_string()))?;
+            let algorithms = match algorithm {
+                Some(algorithm) => vec![algorithm],
+                None => decoding_key.family().algorithms().to_vec(),
+            };
+            keys.push(VerificationKey {
+                key_id: jwk.common.key_id.clone(),
+                decoding_key,
+                algorithms,
+            });
+        }
+
+        if keys.is_empty() {
+            return Err(JwtDecoderError::VerificationKey(
+                "no signature verification key in the JWK set".to_string(),
+            ));
+        }
+        Ok(Self::new(keys))
+    }
+
+    /// Sets the expected `aud` claim. Defaults to the System Identity Service audience.
+    pub fn with_audience(self, audience: Uri) -> Self {
+        Self {
+            audience: audience.to_string(),
+            ..self
+        }
+    }
+
+    /// Only accepts JWTs issued by the given client (`iss` and `sub` claims).
+    pub fn with_client_id(self, client_id: impl Into<String>) -> Self {
+        Self {
+            client_id: Some(client_id.into()),
+            ..self
+        }
+    }
+
+    /// Sets the leeway in seconds applied to the `exp` and `nbf` checks.
+    pub fn with_leeway(self, leeway: u64) -> Self {
+        Self { leeway, ..self }
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway;
+        validation.validate_nbf = true;
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+        if let Some(client_id) = &self.client_id {
+            validation.set_issuer(&[client_id]);
+            validation.sub = Some(client_id.to_owned());
+        }
+        validation
+    }
+
+    fn decode(&self, jwt: &SignedJwt) -> Result<Claims, JwtDecoderError> {
+        let header = jsonwebtoken::decode_header(jwt.value())
+            .map_err(|e| JwtDecoderError::InvalidToken(e.to_string()))?;
+
+        let candidates = self
+            .keys
+            .iter()
+            .filter(|key| header.kid.is_none() || key.key_id == header.kid)
+            .filter(|key| key.algorithms.contains(&header.alg))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(JwtDecoderError::UnknownKey(format!(
+                "kid {:?}, alg {:?}",
+                header.kid, header.alg
+            )));
+        }
+
+        // Without `kid`, any of the compatible keys may have signed the token.
+        let mut last_error = None;
+        for key in candidates {
+            match jsonwebtoken::decode::<Claims>(
+                jwt.value(),
+                &key.decoding_key,
+                &self.validation(header.alg),
+            ) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let error = last_error.expect("at least one candidate key");
+        Err(match error.kind() {
+            jsonwebtoken::errors::ErrorKind::Json(_)
+            | jsonwebtoken::errors::ErrorKind::MissingRequiredClaim(_)
+            | jsonwebtoken::errors::ErrorKind::InvalidAudience
+            | jsonwebtoken::errors::ErrorKind::InvalidIssuer
+            | jsonwebtoken::errors::ErrorKind::InvalidSubject => {
+                JwtDecoderError::InvalidClaims(error.to_string())
+            }
+            _ => JwtDecoderError::InvalidToken(error.to_string()),
+        })
+    }
+}
+
+/// Verify a JWT using local public keys.
+impl JwtVerifier for LocalJwtVerifier {
+    fn verify(&self, jwt: &SignedJwt) -> Result<Claims, JwtDecoderError> {
+        let claims = self.decode(jwt)?;
+
+        if claims.iss != claims.sub {
+            return Err(JwtDecoderError::InvalidClaims(
+                "issuer and subject must be the same client".to_string(),
+            ));
+        }
+        // Deserializing already requires a UUID, but the nil UUID is not unique at all.
+        if claims.jti == Uuid::nil() {
+            return Err(JwtDecoderError::InvalidClaims("nil jti".to_string()));
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use jsonwebtoken::jwk::Jwk;
+    use jsonwebtoken::{EncodingKey, Header, get_current_timestamp};
+    use serde_json::json;
+
+    use super::*;
+    use crate::jwt::signer::JwtSigner;
+    use crate::jwt::signer::local::LocalPrivateKeySigner;
+    use crate::jwt::signer::local::test::{RS256_PRIVATE_KEY, RS256_PUBLIC_KEY};
+
+    fn signer() -> LocalPrivateKeySigner {
+        LocalPrivateKeySigner::try_from(RS256_PRIVATE_KEY.as_bytes()).unwrap()
+    }
+
+    fn claims(exp: u64) -> Claims {
+        Claims::new(
+            "client-id".to_string(),
+            Uri::from_static("https://www.newrelic.com/"),
+            exp,
+        )
+    }
+
+    fn verifier() -> LocalJwtVerifier {
+        LocalJwtVerifier::from_public_key_pem(RS256_PUBLIC_KEY.as_bytes(), Algorithm::RS256)
+            .unwrap()
+    }
+
+    /// Signs arbitrary JSON claims with the test key.
+    fn sign_json(claims: serde_json::Value) -> SignedJwt {
+        let encoding_key = EncodingKey::from_rsa_pem(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &encoding_key)
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn verify_signed_jwt() {
+        let claims = claims(get_current_timestamp() + 60);
+        let signed = signer().sign(claims.clone()).unwrap();
+
+        let decoded = verifier()
+            .with_client_id("client-id")
+            .verify(&signed)
+            .unwrap();
+        assert_eq!(decoded.iss, "client-id");
+        assert_eq!(decoded.aud, "https://www.newrelic.com/");
+    }
+
+    #[test]
+    fn verify_with_jwks_and_kid() {
+        let encoding_key = EncodingKey::from_rsa_pem(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        let mut jwk = Jwk::from_encoding_key(&encoding_key, Algorithm::RS256).unwrap();
+        jwk.common.key_id = Some("key-1".to_string());
+        let verifier = LocalJwtVerifier::from_jwks(&JwkSet { keys: vec![jwk] }).unwrap();
+
+        let claims = claims(get_current_timestamp() + 60);
+        let signed = signer().with_key_id("key-1").sign(claims.clone()).unwrap();
+        assert!(verifier.verify(&signed).is_ok());
+
+        let signed = signer().with_key_id("key-2").sign(claims).unwrap();
+        assert_matches!(
+            verifier.verify(&signed),
+            Err(JwtDecoderError::UnknownKey(_))
+        );
+    }
+
+    #[test]
+    fn verify_with_jwks_skipping_encryption_keys() {
+        let encoding_key = EncodingKey::from_rsa_pem(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        let mut signing_jwk = Jwk::from_encoding_key(&encoding_key, Algorithm::RS256).unwrap();
+        signing_jwk.common.key_id = Some("sig".to_string());
+        signing_jwk.common.public_key_use = Some(PublicKeyUse::Signature);
+        let encryption_jwk = |kid: &str, alg: Option<&str>| {
+            let mut jwk = serde_json::to_value(&signing_jwk).unwrap();
+            jwk["kid"] = json!(kid);
+            jwk["use"] = json!("enc");
+            match alg {
+                Some(alg) => jwk["alg"] = json!(alg),
+                None => {
+                    jwk.as_object_mut().unwrap().remove("alg");
+                }
+            }
+            serde_json::from_value::<Jwk>(jwk).unwrap()
+        };
+        let mut oaep_without_use = encryption_jwk("oaep", Some("RSA-OAEP-256"));
+        oaep_without_use.common.public_key_use = None;
+        let jwks = JwkSet {
+            keys: vec![
+                encryption_jwk("enc", Some("RSA-OAEP-256")),
+                encryption_jwk("enc-without-alg", None),
+                oaep_without_use,
+                signing_jwk.clone(),
+            ],
+        };
+        let verifier = LocalJwtVerifier::from_jwks(&jwks).unwrap();
+
+        let claims = claims(get_current_timestamp() + 60);
+        let signed = signer().with_key_id("sig").sign(claims.clone()).unwrap();
+        assert!(verifier.verify(&signed).is_ok());
+
+        // The same RSA key listed for encryption is never used to verify signatures.
+        for kid in ["enc-without-alg", "oaep"] {
+            let signed = signer().with_key_id(kid).sign(claims.clone()).unwrap();
+            assert_matches!(
+                verifier.verify(&signed),
+                Err(JwtDecoderError::UnknownKey(_))
+            );
+        }
+
+        let only_encryption_keys = JwkSet {
+            keys: vec![encryption_jwk("enc", Some("RSA-OAEP-256"))],
+        };
+        assert_matches!(
+            LocalJwtVerifier::from_jwks(&only_encryption_keys),
+            Err(JwtDecoderError::VerificationKey(_))
+        );
+    }
+
+    #[test]
+    fn reject_invalid_tokens() {
+        let now = get_current_timestamp();
+
+        // Expired
+        let signed = signer().sign(claims(now - 120)).unwrap();
+        assert_matches!(
+            verifier().verify(&signed),
+            Err(JwtDecoderError::InvalidToken(_))
+        );
+
+        // Wrong audience
+        let signed = signer().sign(claims(now + 60)).unwrap();
+        assert_matches!(
+            verifier()
+                .with_audience(Uri::from_static("https://staging.newrelic.com/"))
+                .verify(&signed),
+            Err(JwtDecoderError::InvalidClaims(_))
+        );
+
+        // Wrong client
+        assert_matches!(
+            verifier().with_client_id("other-client").verify(&signed),
+            Err(JwtDecoderError::InvalidClaims(_))
+        );
+
+        // Tampered signature
+        let tampered = SignedJwt::from(format!("{}AA", signed.value()));
+        assert_matches!(
+            verifier().verify(&tampered),
+            Err(JwtDecoderError::InvalidToken(_))
+        );
+    }
+
+    #[test]
+    fn reject_invalid_claims() {
+        let exp = get_current_timestamp() + 60;
+        let aud = "https://www.newrelic.com/";
+        let jti = Uuid::now_v7();
+
+        let different_subject =
+            sign_json(json!({"iss": "a", "sub": "b", "aud": aud, "jti": jti, "exp": exp}));
+        assert_matches!(
+            verifier().verify(&different_subject),
+            Err(JwtDecoderError::InvalidClaims(_))
+        );
+
+        let invalid_jti =
+            sign_json(json!({"iss": "a", "sub": "a", "aud": aud, "jti": "1234", "exp": exp}));
+        assert_matches!(
+            verifier().verify(&invalid_jti),
+            Err(JwtDecoderError::InvalidClaims(_))
+        );
+
+        let nil_jti =
+            sign_json(json!({"iss": "a", "sub": "a", "aud": aud, "jti": Uuid::nil(), "exp": exp}));
+        assert_matches!(
+            verifier().verify(&nil_jti),
+            Err(JwtDecoderError::InvalidClaims(_))
+        );
+
+        let missing_exp = sign_json(json!({"iss": "a", "sub": "a", "aud": aud, "jti": jti}));
+        assert_matches!(
+            verifier().verify(&missing_exp),
+            Err(JwtDecoderError::InvalidClaims(_))
+        );
+    }
+}
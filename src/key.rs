@@ -1,8 +1,16 @@
 use crate::jwt::signer::local::{LocalPrivateKeySigner, LocalPrivateKeySignerError};
 
+pub mod certificate;
+pub mod ec;
 pub mod generation;
+pub mod import;
+pub mod jwk;
 pub mod local;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+pub mod pkcs8;
 pub mod rsa;
+pub mod secret;
 
 /// Represents a PEM-encoded private key "byte string".
 #[derive(Clone, PartialEq)]
@@ -0,0 +1,335 @@
+//! Self-signed X.509 certificates for L2 key pairs.
+//! The certificate lets the key used to authenticate the system identity also be used for mTLS
+//! client authentication, and be referenced from the `x5c` / `x5t#S256` headers of signed JWTs
+//! (see [`LocalPrivateKeySigner::with_certificate_pem`](crate::jwt::signer::local::LocalPrivateKeySigner::with_certificate_pem)).
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use rcgen::{
+    CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, KeyPair, KeyUsagePurpose,
+};
+use thiserror::Error;
+use time::OffsetDateTime;
+
+/// Validity of the generated certificates by default, one year.
+pub const DEFAULT_CERTIFICATE_VALIDITY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+#[derive(Error, Debug)]
+pub enum CertificateError {
+    #[error("invalid certificate subject: `{0}`")]
+    InvalidSubject(String),
+    #[error("invalid subject alternative name: `{0}`")]
+    InvalidSubjectAltName(String),
+    #[error("invalid private key: `{0}`")]
+    InvalidKey(String),
+    #[error("generating certificate: `{0}`")]
+    Generation(String),
+    #[error("invalid certificate: `{0}`")]
+    InvalidCertificate(String),
+}
+
+/// Distinguished name of the certificate subject, parsed from its OpenSSL-like string
+/// representation (`CN=my-agent,O=My Company`).
+///
+/// Supported attributes are `CN`, `O`, `OU`, `C`, `ST` and `L`. The OpenSSL slash form
+/// (`/CN=my-agent/O=My Company`) is also accepted. Separators within values are escaped with a
+/// backslash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateSubject(Vec<(DnType, String)>);
+
+impl CertificateSubject {
+    /// Subject with only a common name.
+    pub fn common_name(common_name: impl Into<String>) -> Self {
+        Self(vec![(DnType::CommonName, common_name.into())])
+    }
+
+    fn distinguished_name(&self) -> DistinguishedName {
+        let mut name = DistinguishedName::new();
+        for (attribute, value) in &self.0 {
+            name.push(attribute.clone(), value.as_str());
+        }
+        name
+    }
+}
+
+impl FromStr for CertificateSubject {
+    type Err = CertificateError;
+
+    fn from_str(subject: &str) -> Result<Self, Self::Err> {
+        let subject = subject.trim();
+        // The OpenSSL slash form (`/CN=name/O=org`) is separated by slashes, otherwise by commas.
+        let attributes = match subject.strip_prefix('/') {
+            Some(subject) => split_unescaped(subject, '/'),
+            None => split_unescaped(subject, ','),
+        }
+        .into_iter()
+        .map(|attribute| {
+            let (key, value) = attribute.split_once('=').ok_or_else(|| {
+                CertificateError::InvalidSubject(format!("missing `=` in `{attribute}`"))
+            })?;
+            let attribute_type = match key.trim().to_ascii_uppercase().as_str() {
+                "CN" => DnType::CommonName,
+                "O" => DnType::OrganizationName,
+                "OU" => DnType::OrganizationalUnitName,
+                "C" => DnType::CountryName,
+                "ST" => DnType::StateOrProvinceName,
+                "L" => DnType::LocalityName,
+                other => {
+                    return Err(CertificateError::InvalidSubject(format!(
+                        "unsupported attribute `{other}`"
+                    )));
+                }
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                return Err(CertificateError::InvalidSubject(format!(
+                    "empty value for `{key}`"
+                )));
+            }
+            Ok((attribute_type, value.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self(attributes))
+    }
+}
+
+/// Splits `value` on the `separator` characters not escaped with a backslash, unescaping them.
+fn split_unescaped(value: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            c if c == separator => parts.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts.retain(|part| !part.trim().is_empty());
+    parts
+}
+
+/// Configuration of a self-signed certificate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateConfig {
+    /// Subject (and issuer) of the certificate.
+    pub subject: CertificateSubject,
+    /// Subject alternative names. IP addresses are added as such, anything else as DNS names.
+    pub subject_alt_names: Vec<String>,
+    /// Time the certificate is valid for, starting when it is generated.
+    pub validity: Duration,
+}
+
+impl CertificateConfig {
+    /// Configuration of a certificate for `subject`, without subject alternative names and valid
+    /// for [`DEFAULT_CERTIFICATE_VALIDITY`].
+    pub fn new(subject: CertificateSubject) -> Self {
+        Self {
+            subject,
+            subject_alt_names: Vec::new(),
+            validity: DEFAULT_CERTIFICATE_VALIDITY,
+        }
+    }
+
+    pub fn with_subject_alt_names(self, subject_alt_names: Vec<String>) -> Self {
+        Self {
+            subject_alt_names,
+            ..self
+        }
+    }
+
+    pub fn with_validity(self, validity: Duration) -> Self {
+        Self { validity, ..self }
+    }
+}
+
+/// Generates a PEM-encoded certificate for the PKCS#8 PEM private key, signed by the key itself.
+///
+/// The certificate is an end-entity certificate for digital signatures and TLS client
+/// authentication.
+pub fn self_signed_certificate_pem(
+    private_key_pem: &[u8],
+    config: &CertificateConfig,
+) -> Result<String, CertificateError> {
+    let private_key_pem = std::str::from_utf8(private_key_pem)
+        .map_err(|e| CertificateError::InvalidKey(e.to_string()))?;
+    let key_pair = KeyPair::from_pem(private_key_pem)
+        .map_err(|e| CertificateError::InvalidKey(e.to_string()))?;
+
+    let mut params = CertificateParams::new(config.subject_alt_names.clone())
+        .map_err(|e| CertificateError::InvalidSubjectAltName(e.to_string()))?;
+    params.distinguished_name = config.subject.distinguished_name();
+    let now = OffsetDateTime::now_utc();
+    params.not_before = now;
+    params.not_after = time::Duration::try_from(config.validity)
+        .ok()
+        .and_then(|validity| now.checked_add(validity))
+        .ok_or_else(|| CertificateError::Generation("validity is too long".to_string()))?;
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+
+    params
+        .self_signed(&key_pair)
+        .map(|cert| cert.pem())
+        .map_err(|e| CertificateError::Generation(e.to_string()))
+}
+
+/// Extracts the DER-encoded `SubjectPublicKeyInfo` of a DER-encoded X.509 certificate.
+pub(crate) fn certificate_spki_der(der: &[u8]) -> Result<Vec<u8>, CertificateError> {
+    yasna::parse_der(der, |reader| {
+        reader.read_sequence(|reader| {
+            let spki = reader.next().read_sequence(|reader| {
+                // version, serial number, signature algorithm, issuer, validity and subject
+                reader.read_optional(|reader| {
+                    reader.read_tagged(yasna::Tag::context(0), |reader| reader.read_der())
+                })?;
+                for _ in 0..5 {
+                    reader.next().read_der()?;
+                }
+                let spki = reader.next().read_der()?;
+                // Unique identifiers and extensions
+                while reader.read_optional(|reader| reader.read_der())?.is_some() {}
+                Ok(spki)
+            })?;
+            // Signature algorithm and value
+            reader.next().read_der()?;
+            reader.next().read_der()?;
+            Ok(spki)
+        })
+    })
+    .map_err(|e| CertificateError::InvalidCertificate(e.to_string()))
+}
+
+/// Path of the certificate written alongside the private key at `key_path`: the key path with
+/// the `.crt` extension (`private_key.pem` → `private_key.crt`).
+pub fn certificate_path(key_path: &Path) -> PathBuf {
+    let path = key_path.with_extension("crt");
+    if path == key_path {
+        let mut file_name = key_path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".crt");
+        return key_path.with_file_name(file_name);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+    use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+    use super::*;
+    use crate::key::generation::{KeyType, generate_key_pair};
+
+    #[rstest]
+    #[case::rsa(KeyType::Rsa2048)]
+    #[case::ecdsa_p256(KeyType::EcdsaP256)]
+    #[case::ecdsa_p384(KeyType::EcdsaP384)]
+    #[case::ed25519(KeyType::Ed25519)]
+    fn self_signed_certificate(#[case] key_type: KeyType) {
+        let key_pair = generate_key_pair(&key_type).unwrap();
+        let config = CertificateConfig::new("CN=my-agent,O=My\\, Company".parse().unwrap())
+            .with_subject_alt_names(vec![
+                "agent.example.com".to_string(),
+                "10.0.0.1".to_string(),
+            ])
+            .with_validity(Duration::from_secs(7 * 24 * 60 * 60));
+
+        let cert_pem = self_signed_certificate_pem(&key_pair.private_key, &config).unwrap();
+        let der = pem::parse(&cert_pem).unwrap().into_contents();
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+
+        assert_eq!(cert.subject().to_string(), "CN=my-agent, O=My, Company");
+        assert_eq!(cert.subject(), cert.issuer());
+        let sans = cert.subject_alternative_name().unwrap().unwrap();
+        assert_matches!(
+            sans.value.general_names.as_slice(),
+            [
+                GeneralName::DNSName("agent.example.com"),
+                GeneralName::IPAddress([10, 0, 0, 1])
+            ]
+        );
+        let validity = cert.validity();
+        assert_eq!(
+            validity.not_after.timestamp() - validity.not_before.timestamp(),
+            7 * 24 * 60 * 60
+        );
+        // The certificate holds the key's public key and is signed with the key.
+        let public_key = pem::parse(&key_pair.public_key).unwrap().into_contents();
+        assert_eq!(cert.public_key().raw, public_key.as_slice());
+        assert!(cert.verify_signature(None).is_ok());
+    }
+
+    #[rstest]
+    #[case::openssl_format("/CN=agent/O=Org/C=ES", 3)]
+    #[case::spaces(" CN = agent , OU = Team ", 2)]
+    fn parse_subject(#[case] subject: &str, #[case] attributes: usize) {
+        assert_eq!(
+            CertificateSubject::from_str(subject).unwrap().0.len(),
+            attributes
+        );
+    }
+
+    #[rstest]
+    #[case::slash_in_values(
+        "CN=agent/prod,O=A/B Inc",
+        &[(DnType::CommonName, "agent/prod"), (DnType::OrganizationName, "A/B Inc")]
+    )]
+    #[case::comma_in_openssl_format(
+        "/CN=agent,prod/O=A\\/B Inc",
+        &[(DnType::CommonName, "agent,prod"), (DnType::OrganizationName, "A/B Inc")]
+    )]
+    fn parse_subject_values(#[case] subject: &str, #[case] expected: &[(DnType, &str)]) {
+        let expected = expected
+            .iter()
+            .map(|(attribute, value)| (attribute.clone(), value.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(CertificateSubject::from_str(subject).unwrap().0, expected);
+    }
+
+    #[rstest]
+    #[case::missing_value("CN")]
+    #[case::empty_value("CN=")]
+    #[case::unsupported_attribute("emailAddress=a@b.c")]
+    fn invalid_subject(#[case] subject: &str) {
+        assert_matches!(
+            CertificateSubject::from_str(subject),
+            Err(CertificateError::InvalidSubject(_))
+        );
+    }
+
+    #[test]
+    fn invalid_private_key() {
+        let config = CertificateConfig::new(CertificateSubject::common_name("agent"));
+        assert_matches!(
+            self_signed_certificate_pem(b"not a key", &config),
+            Err(CertificateError::InvalidKey(_))
+        );
+    }
+
+    #[rstest]
+    #[case("/keys/private_key.pem", "/keys/private_key.crt")]
+    #[case("/keys/private_key", "/keys/private_key.crt")]
+    #[case("/keys/key.crt", "/keys/key.crt.crt")]
+    fn certificate_path_next_to_key(#[case] key_path: &str, #[case] expected: &str) {
+        assert_eq!(certificate_path(Path::new(key_path)), Path::new(expected));
+    }
+
+    #[test]
+    fn too_long_validity() {
+        let key_pair = generate_key_pair(&KeyType::EcdsaP256).unwrap();
+        let config = CertificateConfig::new(CertificateSubject::common_name("agent"))
+            .with_validity(Duration::from_secs(5_000_000 * 24 * 60 * 60));
+        assert_matches!(
+            self_signed_certificate_pem(&key_pair.private_key, &config),
+            Err(CertificateError::Generation(_))
+        );
+        let config = config.with_validity(Duration::MAX);
+        assert_matches!(
+            self_signed_certificate_pem(&key_pair.private_key, &config),
+            Err(CertificateError::Generation(_))
+        );
+    }
+}
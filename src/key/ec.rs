@@ -0,0 +1,49 @@
+use rcgen::{KeyPair as RcKeyPair, PKCS_ECDSA_P256_SHA256, PKCS_ECDSA_P384_SHA384, PKCS_ED25519};
+
+use crate::key::generation::{KeyPair, KeyType};
+
+/// Generates an elliptic curve (ECDSA or Ed25519) key pair based on the specified key type.
+///
+/// Returns the generated `KeyPair` or an error if key generation fails.
+pub fn ec(key_type: &KeyType) -> Result<KeyPair, Box<dyn std::error::Error>> {
+    let algorithm = match key_type {
+        KeyType::EcdsaP256 => &PKCS_ECDSA_P256_SHA256,
+        KeyType::EcdsaP384 => &PKCS_ECDSA_P384_SHA384,
+        KeyType::Ed25519 => &PKCS_ED25519,
+        key_type => return Err(format!("{key_type:?} is not an elliptic curve key type").into()),
+    };
+
+    let key_pair = RcKeyPair::generate_for(algorithm)?;
+
+    Ok(KeyPair {
+        private_key: key_pair.serialize_pem().into_bytes(),
+        public_key: key_pair.public_key_pem().into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::key::rsa::tests::{is_private_key_content, is_public_key_content};
+
+    #[rstest]
+    #[case::p256(KeyType::EcdsaP256)]
+    #[case::p384(KeyType::EcdsaP384)]
+    #[case::ed25519(KeyType::Ed25519)]
+    fn test_ec_key_generation(#[case] key_type: KeyType) {
+        let key_pair = ec(&key_type).expect("Failed to generate key pair");
+        assert!(is_private_key_content(
+            &String::from_utf8(key_pair.private_key).unwrap()
+        ));
+        assert!(is_public_key_content(
+            &String::from_utf8(key_pair.public_key).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_ec_rejects_rsa_key_type() {
+        assert!(ec(&KeyType::Rsa2048).is_err());
+    }
+}
@@ -1,8 +1,35 @@
+use jsonwebtoken::Algorithm;
+
+use crate::key::{ec::ec, rsa::rsa};
+
 /// Represents the type of cryptographic key to be created.
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum KeyType {
+    /// RSA key with a size of 2048 bits.
+    Rsa2048,
+    /// RSA key with a size of 3072 bits.
+    Rsa3072,
     /// RSA key with a size of 4096 bits.
+    #[default]
     Rsa4096,
+    /// ECDSA key on the NIST P-256 curve.
+    EcdsaP256,
+    /// ECDSA key on the NIST P-384 curve.
+    EcdsaP384,
+    /// Ed25519 (EdDSA) key. Much faster to generate and sign with than RSA on low-power devices.
+    Ed25519,
+}
+
+impl KeyType {
+    /// Returns the JWS algorithm used to sign JWTs with keys of this type.
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            KeyType::Rsa2048 | KeyType::Rsa3072 | KeyType::Rsa4096 => Algorithm::RS256,
+            KeyType::EcdsaP256 => Algorithm::ES256,
+            KeyType::EcdsaP384 => Algorithm::ES384,
+            KeyType::Ed25519 => Algorithm::EdDSA,
+        }
+    }
 }
 
 /// A PEM-encoded public key.
@@ -18,3 +45,12 @@ pub struct KeyPair {
     /// The public key in PEM format.
     pub public_key: PublicKeyPem,
 }
+
+/// Generates a key pair of the given type. The private key is PKCS#8 PEM-encoded, so it can be
+/// loaded by [`LocalPrivateKeySigner`](crate::jwt::signer::local::LocalPrivateKeySigner).
+pub fn generate_key_pair(key_type: &KeyType) -> Result<KeyPair, Box<dyn std::error::Error>> {
+    match key_type {
+        KeyType::Rsa2048 | KeyType::Rsa3072 | KeyType::Rsa4096 => rsa(key_type),
+        KeyType::EcdsaP256 | KeyType::EcdsaP384 | KeyType::Ed25519 => ec(key_type),
+    }
+}
@@ -0,0 +1,265 @@
+//! JWK / JWKS export of public keys.
+//! Public keys are exported as RFC 7517 JWKs whose `kid` is the RFC 7638 thumbprint of the key,
+//! so services can verify the assertions signed by the matching private keys.
+use std::path::Path;
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters, Jwk, JwkSet,
+    KeyAlgorithm, OctetKeyPairParameters, PublicKeyUse, RSAKeyParameters, ThumbprintHash,
+};
+use rcgen::PublicKeyData;
+use thiserror::Error;
+use yasna::models::ObjectIdentifier;
+
+use crate::key::generation::KeyPair;
+use crate::key::rsa::RSA_ENCRYPTION_OID;
+
+/// id-ecPublicKey (RFC 5480).
+const EC_PUBLIC_KEY_OID: &[u64] = &[1, 2, 840, 10045, 2, 1];
+/// secp256r1 named curve (RFC 5480).
+const P256_OID: &[u64] = &[1, 2, 840, 10045, 3, 1, 7];
+/// secp384r1 named curve (RFC 5480).
+const P384_OID: &[u64] = &[1, 3, 132, 0, 34];
+/// Ed25519 (RFC 8410).
+const ED25519_OID: &[u64] = &[1, 3, 101, 112];
+
+#[derive(Error, Debug)]
+pub enum JwkExportError {
+    #[error("unable to parse key: `{0}`")]
+    Parse(String),
+    #[error("unsupported key: `{0}`")]
+    UnsupportedKey(String),
+    #[error("filesystem i/o error: `{0}`")]
+    IO(#[from] std::io::Error),
+}
+
+/// Builds the JWK of a PEM-encoded key. Both public keys (`PUBLIC KEY`) and PKCS#8 private keys
+/// (`PRIVATE KEY`) are accepted; only the public part is ever exported.
+pub fn jwk_from_pem(pem: &[u8]) -> Result<Jwk, JwkExportError> {
+    let parsed = pem::parse(pem).map_err(|e| JwkExportError::Parse(e.to_string()))?;
+    match parsed.tag() {
+        "PUBLIC KEY" => jwk_from_spki_der(parsed.contents()),
+        "PRIVATE KEY" => {
+            let key_pair = rcgen::KeyPair::try_from(parsed.contents())
+                .map_err(|e| JwkExportError::Parse(e.to_string()))?;
+            jwk_from_spki_der(&key_pair.subject_public_key_info())
+        }
+        tag => Err(JwkExportError::UnsupportedKey(format!("PEM tag `{tag}`"))),
+    }
+}
+
+/// Builds the JWK of the PEM-encoded key stored at `path`.
+pub fn jwk_from_pem_file(path: &Path) -> Result<Jwk, JwkExportError> {
+    jwk_from_pem(&std::fs::read(path)?)
+}
+
+/// Builds the JWK of the public key of a generated key pair.
+pub fn jwk_from_key_pair(key_pair: &KeyPair) -> Result<Jwk, JwkExportError> {
+    jwk_from_pem(&key_pair.public_key)
+}
+
+/// Builds a JWK Set holding all the given keys.
+pub fn jwks<I: IntoIterator<Item = Jwk>>(keys: I) -> JwkSet {
+    JwkSet {
+        keys: keys.into_iter().collect(),
+    }
+}
+
+/// Sets the `kid` of the JWK to its RFC 7638 SHA-256 thumbprint and marks it as a signing key.
+pub(crate) fn with_thumbprint_kid(mut jwk: Jwk) -> Jwk {
+    jwk.common.key_id = Some(jwk.thumbprint(ThumbprintHash::SHA256));
+    jwk.common.public_key_use = Some(PublicKeyUse::Signature);
+    jwk
+}
+
+/// Builds the JWK of a DER-encoded `SubjectPublicKeyInfo`.
+pub fn jwk_from_spki_der(der: &[u8]) -> Result<Jwk, JwkExportError> {
+    let (algorithm, parameters, key) = yasna::parse_der(der, |reader| {
+        reader.read_sequence(|reader| {
+            let (algorithm, parameters) = reader.next().read_sequence(|reader| {
+                let algorithm = reader.next().read_oid()?;
+                let parameters = reader.read_optional(|reader| reader.read_der())?;
+                Ok((algorithm, parameters))
+            })?;
+            let (key, _) = reader.next().read_bitvec_bytes()?;
+            Ok((algorithm, parameters, key))
+        })
+    })
+    .map_err(|e| JwkExportError::Parse(e.to_string()))?;
+
+    let (key_algorithm, algorithm_parameters) = if algorithm == oid(RSA_ENCRYPTION_OID) {
+        let (n, e) = yasna::parse_der(&key, |reader| {
+            reader.read_sequence(|reader| {
+                let (n, _) = reader.next().read_bigint_bytes()?;
+                let (e, _) = reader.next().read_bigint_bytes()?;
+                Ok((n, e))
+            })
+        })
+        .map_err(|e| JwkExportError::Parse(e.to_string()))?;
+        (
+            KeyAlgorithm::RS256,
+            AlgorithmParameters::RSA(RSAKeyParameters {
+                n: base64url_unsigned(&n),
+                e: base64url_unsigned(&e),
+                ..Default::default()
+            }),
+        )
+    } else if algorithm == oid(EC_PUBLIC_KEY_OID) {
+        let curve = parameters
+            .and_then(|parameters| yasna::parse_der(&parameters, |r| r.read_oid()).ok())
+            .ok_or_else(|| JwkExportError::Parse("missing EC curve".to_string()))?;
+        let (key_algorithm, curve, len) = if curve == oid(P256_OID) {
+            (KeyAlgorithm::ES256, EllipticCurve::P256, 32)
+        } else if curve == oid(P384_OID) {
+            (KeyAlgorithm::ES384, EllipticCurve::P384, 48)
+        } else {
+            return Err(JwkExportError::UnsupportedKey(format!("EC curve {curve}")));
+        };
+        // Uncompressed point: 0x04 || x || y
+        if key.len() != 1 + 2 * len || key[0] != 0x04 {
+            return Err(JwkExportError::UnsupportedKey(
+                "compressed or invalid EC point".to_string(),
+            ));
+        }
+        (
+            key_algorithm,
+            AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                curve,
+                x: BASE64_URL_SAFE_NO_PAD.encode(&key[1..=len]),
+                y: BASE64_URL_SAFE_NO_PAD.encode(&key[len + 1..]),
+                ..Default::default()
+            }),
+        )
+    } else if algorithm == oid(ED25519_OID) {
+        (
+            KeyAlgorithm::EdDSA,
+            AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                curve: EllipticCurve::Ed25519,
+                x: BASE64_URL_SAFE_NO_PAD.encode(&key),
+                ..Default::default()
+            }),
+        )
+    } else {
+        return Err(JwkExportError::UnsupportedKey(format!(
+            "key algorithm {algorithm}"
+        )));
+    };
+
+    Ok(with_thumbprint_kid(Jwk {
+        common: CommonParameters {
+            key_algorithm: Some(key_algorithm),
+            ..Default::default()
+        },
+        algorithm: algorithm_parameters,
+    }))
+}
+
+fn oid(components: &[u64]) -> ObjectIdentifier {
+    ObjectIdentifier::from_slice(components)
+}
+
+/// Encodes a DER integer, dropping the sign byte, as required for JWK integers (RFC 7518 §6.3).
+fn base64url_unsigned(bytes: &[u8]) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(bytes.strip_prefix(&[0]).unwrap_or(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use http::Uri;
+    use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Validation, get_current_timestamp};
+    use rcgen::{PKCS_ECDSA_P256_SHA256, PKCS_ECDSA_P384_SHA384, PKCS_ED25519};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::jwt::claims::Claims;
+    use crate::jwt::signer::local::test::{RS256_PRIVATE_KEY, RS256_PUBLIC_KEY};
+
+    #[test]
+    fn rsa_public_and_private_keys_export_the_same_jwk() {
+        let from_public = jwk_from_pem(RS256_PUBLIC_KEY.as_bytes()).unwrap();
+        let from_private = jwk_from_pem(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        assert_eq!(from_public, from_private);
+
+        // Same key material as the one jsonwebtoken derives from the private key
+        let encoding_key = EncodingKey::from_rsa_pem(RS256_PRIVATE_KEY.as_bytes()).unwrap();
+        let expected = Jwk::from_encoding_key(&encoding_key, Algorithm::RS256).unwrap();
+        assert_eq!(from_public.algorithm, expected.algorithm);
+        assert_eq!(
+            from_public.common.key_id,
+            Some(expected.thumbprint(ThumbprintHash::SHA256))
+        );
+        assert_eq!(from_public.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        assert_eq!(
+            from_public.common.public_key_use,
+            Some(PublicKeyUse::Signature)
+        );
+    }
+
+    #[rstest]
+    #[case::p256(&PKCS_ECDSA_P256_SHA256, Algorithm::ES256)]
+    #[case::p384(&PKCS_ECDSA_P384_SHA384, Algorithm::ES384)]
+    #[case::ed25519(&PKCS_ED25519, Algorithm::EdDSA)]
+    fn exported_jwk_verifies_signatures(
+        #[case] alg: &'static rcgen::SignatureAlgorithm,
+        #[case] algorithm: Algorithm,
+    ) {
+        let key_pair = rcgen::KeyPair::generate_for(alg).unwrap();
+        let jwk = jwk_from_key_pair(&KeyPair {
+            private_key: key_pair.serialize_pem().into_bytes(),
+            public_key: key_pair.public_key_pem().into_bytes(),
+        })
+        .unwrap();
+
+        let encoding_key = match algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_der(&key_pair.serialize_der()),
+            _ => EncodingKey::from_ec_der(&key_pair.serialize_der()),
+        };
+        let claims = Claims::new(
+            "client-id".to_string(),
+            Uri::from_static("https://www.newrelic.com/"),
+            get_current_timestamp() + 60,
+        );
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(algorithm),
+            &claims,
+            &encoding_key,
+        )
+        .unwrap();
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&["https://www.newrelic.com/"]);
+        assert!(
+            jsonwebtoken::decode::<Claims>(
+                &token,
+                &DecodingKey::from_jwk(&jwk).unwrap(),
+                &validation
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn jwks_document() {
+        let jwk = jwk_from_pem(RS256_PUBLIC_KEY.as_bytes()).unwrap();
+        let document = serde_json::to_value(jwks([jwk.clone()])).unwrap();
+        assert_eq!(document["keys"][0]["kty"], "RSA");
+        assert_eq!(document["keys"][0]["use"], "sig");
+        assert_eq!(document["keys"][0]["alg"], "RS256");
+        assert_eq!(
+            document["keys"][0]["kid"],
+            jwk.common.key_id.clone().unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_keys() {
+        assert_matches!(jwk_from_pem(b"garbage"), Err(JwkExportError::Parse(_)));
+        let cert = pem::encode(&pem::Pem::new("CERTIFICATE", vec![0; 4]));
+        assert_matches!(
+            jwk_from_pem(cert.as_bytes()),
+            Err(JwkExportError::UnsupportedKey(_))
+        );
+    }
+}
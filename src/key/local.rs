@@ -1,13 +1,18 @@
 //! Local filesystem key pair generator.
-//! Generates RSA key pairs and persists the private key to a local file path.
-use crate::key::generation::{KeyType, PublicKeyPem};
-use crate::key::rsa::rsa;
-use std::fs::{self, File};
-use std::io::Write;
+//! Generates key pairs and persists the private key to a local file path.
+use crate::files::{create_private_dir_all, write_new_file, write_new_private_file};
+use crate::key::certificate::{CertificateConfig, certificate_path, self_signed_certificate_pem};
+use crate::key::generation::{KeyType, PublicKeyPem, generate_key_pair};
+use crate::key::pkcs8::{EncryptedKeyError, encrypt_private_key_pem};
+use crate::key::secret::SecretSource;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 use tracing::debug;
 
+pub use crate::files::FileOwner;
+
 /// Errors that can occur during local key creation.
 #[derive(Error, Debug)]
 pub enum LocalKeyCreationError {
@@ -23,6 +28,12 @@ pub enum LocalKeyCreationError {
     /// Error that occurs when writing the private key fails.
     #[error("unable to write private key: `{0}`")]
     UnableToWritePrivateKey(String),
+    /// Error that occurs when encrypting the private key with the passphrase fails.
+    #[error("unable to encrypt private key: `{0}`")]
+    UnableToEncryptPrivateKey(String),
+    /// Error that occurs when generating or writing the self-signed certificate fails.
+    #[error("unable to create certificate: `{0}`")]
+    UnableToCreateCertificate(String),
 }
 
 /// Configuration for [`LocalKeyPairGenerator`].
@@ -41,6 +52,12 @@ pub struct LocalKeyPairGenerator {
     key_type: KeyType,
     /// The file path where the private key will be stored.
     file_path: PathBuf,
+    /// Passphrase the private key is encrypted with, if any.
+    passphrase: Option<SecretSource>,
+    /// Owner and group the private key file is assigned to, if any.
+    owner: Option<FileOwner>,
+    /// Self-signed certificate written alongside the private key, if any.
+    certificate: Option<CertificateConfig>,
 }
 
 impl From<LocalKeyPairGeneratorConfig> for LocalKeyPairGenerator {
@@ -53,21 +70,81 @@ impl From<LocalKeyPairGeneratorConfig> for LocalKeyPairGenerator {
         Self {
             key_type,
             file_path: path,
+            passphrase: None,
+            owner: None,
+            certificate: None,
         }
     }
 }
 
 impl LocalKeyPairGenerator {
+    /// Stores the private key as a passphrase-encrypted PKCS#8 PEM (`ENCRYPTED PRIVATE KEY`).
+    pub fn with_passphrase(self, passphrase: SecretSource) -> Self {
+        Self {
+            passphrase: Some(passphrase),
+            ..self
+        }
+    }
+
+    /// Assigns the private key file to the given owner and group (requires the privileges to do so).
+    pub fn with_owner(self, owner: FileOwner) -> Self {
+        Self {
+            owner: Some(owner),
+            ..self
+        }
+    }
+
+    /// Also writes a self-signed certificate of the key next to the private key (see
+    /// [`certificate_path`]).
+    pub fn with_certificate(self, certificate: CertificateConfig) -> Self {
+        Self {
+            certificate: Some(certificate),
+            ..self
+        }
+    }
+
     /// Creates a cryptographic key and stores the private key locally.
     ///
     /// Returns the public key in PEM format, or an error if key creation fails.
     pub fn generate(&self) -> Result<PublicKeyPem, LocalKeyCreationError> {
-        let key_pair = match self.key_type {
-            KeyType::Rsa4096 => rsa(&self.key_type)
-                .map_err(|err| LocalKeyCreationError::UnableToGenerateKey(err.to_string()))?,
-        };
+        let key_pair = generate_key_pair(&self.key_type)
+            .map_err(|err| LocalKeyCreationError::UnableToGenerateKey(err.to_string()))?;
+        // Generated before persisting anything, so an invalid configuration leaves no files.
+        let certificate = self
+            .certificate
+            .as_ref()
+            .map(|config| {
+                let path = certificate_path(&self.file_path);
+                Self::validate_path(&path)?;
+                self_signed_certificate_pem(&key_pair.private_key, config)
+                    .map(|pem| (path, pem))
+                    .map_err(|e| LocalKeyCreationError::UnableToCreateCertificate(e.to_string()))
+            })
+            .transpose()?;
+
+        match &self.passphrase {
+            Some(passphrase) => {
+                let encrypted = passphrase
+                    .read()
+                    .map_err(EncryptedKeyError::from)
+                    .and_then(|passphrase| {
+                        encrypt_private_key_pem(&key_pair.private_key, &passphrase)
+                    })
+                    .map_err(|e| LocalKeyCreationError::UnableToEncryptPrivateKey(e.to_string()))?;
+                self.persist_private_key(&encrypted)?;
+            }
+            None => self.persist_private_key(&key_pair.private_key)?,
+        }
 
-        self.persist_private_key(&key_pair.private_key)?;
+        if let Some((path, pem)) = certificate {
+            debug!("persisting certificate in {}", path.display());
+            if let Err(e) = write_new_file(&path, pem.as_bytes()) {
+                let _ = fs::remove_file(&self.file_path);
+                return Err(LocalKeyCreationError::UnableToCreateCertificate(
+                    e.to_string(),
+                ));
+            }
+        }
 
         Ok(key_pair.public_key)
     }
@@ -85,13 +162,19 @@ impl LocalKeyPairGenerator {
                 "local key path parent directory does not exist or is not a directory",
             ))
         })?;
-        fs::create_dir_all(parent_dir)
+        create_private_dir_all(parent_dir)
             .map_err(|e| LocalKeyCreationError::UnableToCreatePrivateKeyFile(e.to_string()))?;
 
-        let mut file = File::create(&self.file_path)
-            .map_err(|e| LocalKeyCreationError::UnableToCreatePrivateKeyFile(e.to_string()))?;
-        file.write_all(key)
-            .map_err(|e| LocalKeyCreationError::UnableToWritePrivateKey(e.to_string()))
+        // The key is never written over a file created since the path was validated.
+        write_new_private_file(&self.file_path, key, self.owner.as_ref()).map_err(|e| {
+            match e.kind() {
+                io::ErrorKind::AlreadyExists => LocalKeyCreationError::InvalidPath(format!(
+                    "local key path already exists at {}",
+                    self.file_path.display()
+                )),
+                _ => LocalKeyCreationError::UnableToWritePrivateKey(e.to_string()),
+            }
+        })
     }
 
     fn validate_path(path: &Path) -> Result<(), LocalKeyCreationError> {
@@ -114,6 +197,9 @@ mod tests {
     use assert_matches::assert_matches;
     use tempfile::{NamedTempFile, TempDir, tempdir};
 
+    use crate::jwt::signer::local::LocalPrivateKeySigner;
+    use crate::key::certificate::CertificateSubject;
+    use crate::key::pkcs8::is_encrypted_pem;
     use crate::key::rsa::tests::{is_private_key_content, is_public_key_content};
 
     use super::*;
@@ -177,4 +263,118 @@ mod tests {
             "invalid private key content"
         );
     }
+
+    #[test]
+    fn test_local_key_pair_generator_create_encrypted() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("key");
+        let passphrase = SecretSource::Value("passphrase".to_string());
+
+        let creator = LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
+            key_type: KeyType::EcdsaP256,
+            file_path: key_path.clone(),
+        })
+        .with_passphrase(passphrase.clone());
+        creator.generate().expect("Failed to create key pair");
+
+        let private_key = fs::read(&key_path).unwrap();
+        assert!(is_encrypted_pem(&private_key));
+        assert!(LocalPrivateKeySigner::from_pem_with_passphrase(&private_key, &passphrase).is_ok());
+    }
+
+    #[test]
+    fn test_local_key_pair_generator_create_with_certificate() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("key.pem");
+        let certificate = CertificateConfig::new(CertificateSubject::common_name("agent"));
+
+        let creator = LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
+            key_type: KeyType::EcdsaP256,
+            file_path: key_path.clone(),
+        })
+        .with_certificate(certificate);
+        creator.generate().expect("Failed to create key pair");
+
+        let cert_pem = fs::read(tmp_dir.path().join("key.crt")).unwrap();
+        assert_eq!(pem::parse(&cert_pem).unwrap().tag(), "CERTIFICATE");
+        let signer = LocalPrivateKeySigner::try_from(key_path.as_path()).unwrap();
+        assert!(signer.with_certificate_pem(&cert_pem).is_ok());
+    }
+
+    #[test]
+    fn test_local_key_pair_generator_certificate_already_exists() {
+        let tmp_dir = tempdir().unwrap();
+        let key_path = tmp_dir.path().join("key.pem");
+        fs::write(tmp_dir.path().join("key.crt"), "existing").unwrap();
+
+        let creator = LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
+            key_type: KeyType::EcdsaP256,
+            file_path: key_path.clone(),
+        })
+        .with_certificate(CertificateConfig::new(CertificateSubject::common_name(
+            "agent",
+        )));
+        assert_matches!(
+            creator.generate(),
+            Err(LocalKeyCreationError::InvalidPath(_))
+        );
+        assert!(!key_path.exists());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod permission_tests {
+    use std::fs;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn test_private_key_file_is_owner_only() {
+        let tmp_dir = tempdir().unwrap();
+        let key_dir = tmp_dir.path().join("keys").join("nested");
+        let key_path = key_dir.join("key");
+
+        LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
+            key_type: KeyType::EcdsaP256,
+            file_path: key_path.clone(),
+        })
+        .generate()
+        .unwrap();
+
+        assert_eq!(mode(&key_path), 0o600);
+        assert_eq!(mode(&key_dir), 0o700);
+        assert_eq!(mode(&tmp_dir.path().join("keys")), 0o700);
+        // Only the key remains, no temporary file is left behind
+        assert_eq!(fs::read_dir(&key_dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_private_key_file_owner() {
+        let tmp_dir = tempdir().unwrap();
+        let metadata = fs::metadata(tmp_dir.path()).unwrap();
+        let key_path = tmp_dir.path().join("key");
+
+        LocalKeyPairGenerator::from(LocalKeyPairGeneratorConfig {
+            key_type: KeyType::EcdsaP256,
+            file_path: key_path.clone(),
+        })
+        .with_owner(FileOwner {
+            uid: Some(metadata.uid()),
+            gid: Some(metadata.gid()),
+        })
+        .generate()
+        .unwrap();
+
+        let key_metadata = fs::metadata(&key_path).unwrap();
+        assert_eq!(key_metadata.uid(), metadata.uid());
+        assert_eq!(key_metadata.gid(), metadata.gid());
+        assert_eq!(mode(&key_path), 0o600);
+    }
 }
@@ -0,0 +1,287 @@
+//! PKCS#11 token key pair generator.
+//! Generates key pairs inside a PKCS#11 token (HSM, TPM-backed tokens, SoftHSM...) so the private
+//! key never leaves the device. Only the public key is returned.
+use std::path::PathBuf;
+
+use cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+use cryptoki::error::{Error as CryptokiError, RvError};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, KeyType as Pkcs11KeyType, ObjectClass};
+use cryptoki::session::{Session, UserType};
+use cryptoki::slot::Slot;
+use cryptoki::types::AuthPin;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::key::generation::{KeyType, PublicKeyPem};
+use crate::key::rsa::rsa_public_key_pem;
+use crate::key::secret::{SecretSource, SecretSourceError};
+
+/// Errors that can occur when interacting with a PKCS#11 token.
+#[derive(Error, Debug)]
+pub enum Pkcs11Error {
+    #[error("loading PKCS#11 module: `{0}`")]
+    Module(String),
+    #[error("slot not found: `{0}`")]
+    SlotNotFound(String),
+    #[error("reading PIN: `{0}`")]
+    Pin(String),
+    #[error("PKCS#11 session: `{0}`")]
+    Session(String),
+    #[error("key with label `{0}` not found in token")]
+    KeyNotFound(String),
+    #[error("key with label `{0}` already exists in token")]
+    KeyAlreadyExists(String),
+    #[error("PKCS#11 operation failed: `{0}`")]
+    Operation(String),
+    #[error("unsupported key type: `{0:?}`")]
+    UnsupportedKeyType(KeyType),
+}
+
+impl From<CryptokiError> for Pkcs11Error {
+    fn from(err: CryptokiError) -> Self {
+        Pkcs11Error::Operation(err.to_string())
+    }
+}
+
+/// Identifies the slot holding the token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pkcs11Slot {
+    /// Slot identifier as reported by the module.
+    Id(u64),
+    /// Label of the token inserted in the slot. Useful with modules such as SoftHSM, which
+    /// assign random slot identifiers.
+    TokenLabel(String),
+}
+
+impl From<SecretSourceError> for Pkcs11Error {
+    fn from(err: SecretSourceError) -> Self {
+        Pkcs11Error::Pin(err.to_string())
+    }
+}
+
+/// Location of a key inside a PKCS#11 token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pkcs11KeyConfig {
+    /// Path to the PKCS#11 module (shared library), e.g. `/usr/lib/softhsm/libsofthsm2.so`.
+    pub module_path: PathBuf,
+    /// Slot holding the token.
+    pub slot: Pkcs11Slot,
+    /// Source of the user PIN.
+    pub pin: SecretSource,
+    /// Label (`CKA_LABEL`) of the key pair objects.
+    pub key_label: String,
+}
+
+impl Pkcs11KeyConfig {
+    /// Loads the module and opens a read-write session logged in as the token user.
+    pub(crate) fn open_session(&self) -> Result<(Pkcs11, Session), Pkcs11Error> {
+        let pkcs11 = Pkcs11::new(&self.module_path)
+            .map_err(|e| Pkcs11Error::Module(format!("{}: {e}", self.module_path.display())))?;
+        match pkcs11.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK)) {
+            // The module may be shared with another signer or generator in the same process.
+            Ok(()) | Err(CryptokiError::Pkcs11(RvError::CryptokiAlreadyInitialized, _)) => {}
+            Err(e) => return Err(Pkcs11Error::Module(e.to_string())),
+        }
+
+        let slot = self.find_slot(&pkcs11)?;
+        let session = pkcs11
+            .open_rw_session(slot)
+            .map_err(|e| Pkcs11Error::Session(e.to_string()))?;
+        match session.login(UserType::User, Some(&AuthPin::from(self.pin.read()?))) {
+            Ok(()) | Err(CryptokiError::Pkcs11(RvError::UserAlreadyLoggedIn, _)) => {}
+            Err(e) => return Err(Pkcs11Error::Session(format!("login: {e}"))),
+        }
+
+        Ok((pkcs11, session))
+    }
+
+    fn find_slot(&self, pkcs11: &Pkcs11) -> Result<Slot, Pkcs11Error> {
+        let slots = pkcs11.get_slots_with_initialized_token()?;
+        match &self.slot {
+            Pkcs11Slot::Id(id) => slots.into_iter().find(|slot| slot.id() == *id),
+            Pkcs11Slot::TokenLabel(label) => slots.into_iter().find(|slot| {
+                pkcs11
+                    .get_token_info(*slot)
+                    .is_ok_and(|info| info.label() == label)
+            }),
+        }
+        .ok_or_else(|| Pkcs11Error::SlotNotFound(format!("{:?}", self.slot)))
+    }
+
+    /// Returns the handles of the objects of the given class with the configured label.
+    pub(crate) fn find_objects(
+        &self,
+        session: &Session,
+        class: ObjectClass,
+    ) -> Result<Vec<cryptoki::object::ObjectHandle>, Pkcs11Error> {
+        Ok(session.find_objects(&[
+            Attribute::Class(class),
+            Attribute::Label(self.key_label.as_bytes().to_vec()),
+        ])?)
+    }
+}
+
+/// Generates a non-exportable key pair inside a PKCS#11 token.
+#[derive(Debug)]
+pub struct Pkcs11KeyPairGenerator {
+    /// The type of key to be created.
+    key_type: KeyType,
+    /// Where the key pair will be created.
+    config: Pkcs11KeyConfig,
+}
+
+impl Pkcs11KeyPairGenerator {
+    pub fn new(key_type: KeyType, config: Pkcs11KeyConfig) -> Self {
+        Self { key_type, config }
+    }
+
+    /// Creates a key pair in the token, marked as sensitive and non-extractable.
+    ///
+    /// Returns the public key in PEM format, or an error if key creation fails.
+    pub fn generate(&self) -> Result<PublicKeyPem, Pkcs11Error> {
+        let (_pkcs11, session) = self.config.open_session()?;
+
+        // Key labels should be unique so signers can find the key unambiguously
+        if !self
+            .config
+            .find_objects(&session, ObjectClass::PRIVATE_KEY)?
+            .is_empty()
+        {
+            return Err(Pkcs11Error::KeyAlreadyExists(self.config.key_label.clone()));
+        }
+
+        let modulus_bits = match self.key_type {
+            KeyType::Rsa2048 => 2048,
+            KeyType::Rsa3072 => 3072,
+            KeyType::Rsa4096 => 4096,
+            key_type => return Err(Pkcs11Error::UnsupportedKeyType(key_type)),
+        };
+        let label = self.config.key_label.as_bytes().to_vec();
+
+        debug!(
+            "generating key pair in PKCS#11 token with label {}",
+            self.config.key_label
+        );
+        let public_key_template = [
+            Attribute::Token(true),
+            Attribute::Private(false),
+            Attribute::Verify(true),
+            Attribute::Label(label.clone()),
+            Attribute::ModulusBits(modulus_bits.into()),
+            Attribute::PublicExponent(vec![0x01, 0x00, 0x01]),
+        ];
+        let private_key_template = [
+            Attribute::Token(true),
+            Attribute::Private(true),
+            Attribute::Sensitive(true),
+            Attribute::Extractable(false),
+            Attribute::Sign(true),
+            Attribute::KeyType(Pkcs11KeyType::RSA),
+            Attribute::Label(label),
+        ];
+        let (public_key, _) = session.generate_key_pair(
+            &Mechanism::RsaPkcsKeyPairGen,
+            &public_key_template,
+            &private_key_template,
+        )?;
+
+        let attributes = session.get_attributes(
+            public_key,
+            &[AttributeType::Modulus, AttributeType::PublicExponent],
+        )?;
+        let (mut modulus, mut exponent) = (None, None);
+        for attribute in attributes {
+            match attribute {
+                Attribute::Modulus(value) => modulus = Some(value),
+                Attribute::PublicExponent(value) => exponent = Some(value),
+                _ => {}
+            }
+        }
+        let (Some(modulus), Some(exponent)) = (modulus, exponent) else {
+            return Err(Pkcs11Error::Operation(
+                "reading generated public key components".to_string(),
+            ));
+        };
+
+        Ok(rsa_public_key_pem(&modulus, &exponent))
+    }
+
+    /// Removes the key pair objects with the configured label from the token, undoing
+    /// [`Pkcs11KeyPairGenerator::generate`].
+    pub fn remove(&self) -> Result<(), Pkcs11Error> {
+        let (_pkcs11, session) = self.config.open_session()?;
+        for class in [ObjectClass::PRIVATE_KEY, ObjectClass::PUBLIC_KEY] {
+            for object in self.config.find_objects(&session, class)? {
+                session.destroy_object(object)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::env;
+
+    use super::*;
+
+    pub(crate) const SOFTHSM_MODULE_ENV: &str = "NR_AUTH_PKCS11_MODULE";
+    pub(crate) const SOFTHSM_TOKEN_LABEL_ENV: &str = "NR_AUTH_PKCS11_TOKEN_LABEL";
+    pub(crate) const SOFTHSM_PIN_ENV: &str = "NR_AUTH_PKCS11_PIN";
+
+    /// Configuration of the SoftHSM token used by the tests that need a real PKCS#11 module.
+    /// The token must be initialized beforehand, e.g.:
+    ///
+    /// ```shell
+    /// softhsm2-util --init-token --free --label nr-auth --pin 1234 --so-pin 1234
+    /// NR_AUTH_PKCS11_MODULE=/usr/lib/softhsm/libsofthsm2.so NR_AUTH_PKCS11_TOKEN_LABEL=nr-auth \
+    ///   NR_AUTH_PKCS11_PIN=1234 cargo test --features pkcs11 -- --ignored
+    /// ```
+    pub(crate) fn softhsm_config(key_label: &str) -> Pkcs11KeyConfig {
+        Pkcs11KeyConfig {
+            module_path: env::var(SOFTHSM_MODULE_ENV)
+                .expect("PKCS#11 module path env var")
+                .into(),
+            slot: Pkcs11Slot::TokenLabel(
+                env::var(SOFTHSM_TOKEN_LABEL_ENV).expect("PKCS#11 token label env var"),
+            ),
+            pin: SecretSource::Env(SOFTHSM_PIN_ENV.to_string()),
+            key_label: key_label.to_string(),
+        }
+    }
+
+    #[test]
+    fn invalid_module_path() {
+        let config = Pkcs11KeyConfig {
+            module_path: "/non/existing/module.so".into(),
+            slot: Pkcs11Slot::Id(0),
+            pin: SecretSource::Value("1234".to_string()),
+            key_label: "key".to_string(),
+        };
+        let result = Pkcs11KeyPairGenerator::new(KeyType::Rsa4096, config).generate();
+        assert!(matches!(result, Err(Pkcs11Error::Module(_))));
+    }
+
+    #[test]
+    #[ignore = "requires a SoftHSM token, see softhsm_config"]
+    fn generate_key_pair_in_token() {
+        let key_label = format!("nr-auth-test-{}", uuid::Uuid::now_v7());
+        let generator = Pkcs11KeyPairGenerator::new(KeyType::Rsa4096, softhsm_config(&key_label));
+
+        let public_key = generator.generate().unwrap();
+        let public_key = String::from_utf8(public_key).unwrap();
+        assert!(crate::key::rsa::tests::is_public_key_content(&public_key));
+
+        // The label cannot be reused
+        assert!(matches!(
+            generator.generate(),
+            Err(Pkcs11Error::KeyAlreadyExists(_))
+        ));
+
+        // Until the key pair is removed
+        generator.remove().unwrap();
+        assert!(generator.generate().is_ok());
+        generator.remove().unwrap();
+    }
+}
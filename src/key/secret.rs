@@ -0,0 +1,101 @@
+//! Sources of secrets protecting keys, such as private key passphrases and PKCS#11 PINs.
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::{env, fs};
+
+use thiserror::Error;
+
+/// Errors that can occur when reading a secret.
+#[derive(Error, Debug)]
+pub enum SecretSourceError {
+    #[error("env var {0}: `{1}`")]
+    Env(String, env::VarError),
+    #[error("file {0}: `{1}`")]
+    File(String, std::io::Error),
+    #[error("empty secret")]
+    Empty,
+}
+
+/// Where a secret is read from.
+#[derive(Clone, PartialEq)]
+pub enum SecretSource {
+    /// The secret itself.
+    Value(String),
+    /// Name of an environment variable holding the secret.
+    Env(String),
+    /// Path to a file holding the secret. Trailing whitespace is ignored.
+    File(PathBuf),
+}
+
+impl Debug for SecretSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Value(_) => write!(f, "SecretSource::Value(<REDACTED>)"),
+            Self::Env(name) => write!(f, "SecretSource::Env({name})"),
+            Self::File(path) => write!(f, "SecretSource::File({})", path.display()),
+        }
+    }
+}
+
+impl SecretSource {
+    /// Reads the secret, failing if it is empty.
+    pub fn read(&self) -> Result<String, SecretSourceError> {
+        let secret = match self {
+            Self::Value(secret) => secret.to_owned(),
+            Self::Env(name) => {
+                env::var(name).map_err(|e| SecretSourceError::Env(name.clone(), e))?
+            }
+            Self::File(path) => fs::read_to_string(path)
+                .map_err(|e| SecretSourceError::File(path.display().to_string(), e))?
+                .trim_end()
+                .to_string(),
+        };
+        if secret.is_empty() {
+            return Err(SecretSourceError::Empty);
+        }
+        Ok(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn secret_sources() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("secret");
+        fs::write(&path, "from-file\n").unwrap();
+        assert_eq!(
+            SecretSource::File(path).read().unwrap(),
+            "from-file".to_string()
+        );
+        assert_eq!(
+            SecretSource::Value("value".to_string()).read().unwrap(),
+            "value".to_string()
+        );
+        assert_matches!(
+            SecretSource::Value(String::new()).read(),
+            Err(SecretSourceError::Empty)
+        );
+        assert_matches!(
+            SecretSource::Env("NR_AUTH_TEST_UNSET_SECRET".to_string()).read(),
+            Err(SecretSourceError::Env(_, _))
+        );
+        assert_matches!(
+            SecretSource::File(dir.path().join("missing")).read(),
+            Err(SecretSourceError::File(_, _))
+        );
+    }
+
+    #[test]
+    fn secret_source_debug_is_redacted() {
+        assert_eq!(
+            format!("{:?}", SecretSource::Value("secret".to_string())),
+            "SecretSource::Value(<REDACTED>)"
+        );
+    }
+}
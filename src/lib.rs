@@ -30,11 +30,14 @@
 
 pub mod authenticator;
 pub mod commands;
+mod files;
 pub mod http;
 pub mod http_client;
 pub mod jwt;
 pub mod key;
 pub mod parameters;
+mod reload;
+mod sync;
 pub mod system_identity;
 pub mod token;
 pub mod token_retriever;
@@ -1,18 +1,27 @@
 use crate::http::config::ProxyConfig;
 use crate::key::PrivateKeyPem;
+use crate::key::certificate::{CertificateConfig, CertificateSubject};
+use crate::key::generation::KeyType;
+use crate::key::pkcs8::{EncryptedKeyError, decrypt_private_key_pem, is_encrypted_pem};
+use crate::key::secret::SecretSource;
+use crate::system_identity::deletion::DeletionFilter;
 use crate::system_identity::input_data::auth_method::{AuthMethod, ClientSecret};
 use crate::system_identity::input_data::environment::NewRelicEnvironment;
 use crate::system_identity::input_data::output_platform::OutputPlatform;
 use crate::system_identity::input_data::{
     SystemIdentityCreationMetadata, SystemTokenCreationMetadata,
 };
-use clap::error::ErrorKind::MissingRequiredArgument;
+use crate::system_identity::query::{SystemIdentityFilter, SystemIdentityTag};
+use crate::system_identity::update::SystemIdentityUpdate;
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeDelta, Utc};
+use clap::error::ErrorKind::{InvalidValue, MissingRequiredArgument};
 use clap::{Args, Error, Subcommand, ValueEnum};
 use std::clone::Clone;
 use std::convert::{From, Into};
 use std::fs;
 use std::path::PathBuf;
 use std::result::Result;
+use std::str::FromStr;
 use std::time::Duration;
 
 #[derive(Debug, Clone)]
@@ -24,6 +33,8 @@ pub enum IdentityCreationCredential {
 }
 
 pub const DEFAULT_AUTHENTICATOR_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest validity accepted for generated certificates, 100 years.
+const MAX_CERTIFICATE_VALIDITY_DAYS: u64 = 100 * 365;
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     #[command(verbatim_doc_comment)]
@@ -46,6 +57,7 @@ pub enum Commands {
     /// Creates a bootstrap system identity with NR Control Group membership.
     ///
     /// Bootstrap identities can create other identities. Only API key authentication is supported.
+    /// If the identity cannot be added to the group, it is deleted and its generated key removed.
     ///
     CreateBootstrapIdentity {
         #[command(subcommand)]
@@ -73,6 +85,375 @@ pub enum Commands {
         #[arg(long, ignore_case = true)]
         output_token_format: OutputTokenFormat,
     },
+    #[command(verbatim_doc_comment)]
+    /// Rotates the private key of an existing key (L2) system identity.
+    ///
+    /// A new key pair is generated and its public key is registered for the identity. Once a
+    /// token is obtained with the new key, the previous private key is archived next to it
+    /// (`<private-key-path>.<timestamp>.old`) and replaced by the new one. Token requests with the
+    /// new key are retried with backoff while it propagates. Any failure rolls back the rotation,
+    /// leaving the previous key in place.
+    ///
+    /// The previous public key stays registered, so processes still using the previous key keep
+    /// obtaining tokens until they load the new one. The archived private key remains a valid
+    /// credential until its public key is removed with `--revoke-old-key`.
+    RotateKey(RotateKeyArgs),
+    /// Manages the keys of L2 system identities.
+    Key {
+        #[command(subcommand)]
+        command: KeyCommand,
+    },
+    /// Lists, inspects and updates the system identities of an organization.
+    Identity {
+        #[command(subcommand)]
+        command: IdentityCommand,
+    },
+    #[command(verbatim_doc_comment)]
+    /// Deletes system identities, by id or matching criteria.
+    ///
+    /// Identities matching criteria must match all of them, e.g. `--name-prefix host-
+    /// --never-used` only deletes the never used identities whose name starts with `host-`.
+    /// Use `--dry-run` to print the identities that would be deleted without deleting them.
+    DeleteIdentity(DeleteIdentityArgs),
+    /// Manages the system identity groups of an organization and their members.
+    Group {
+        #[command(subcommand)]
+        command: GroupCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum GroupCommand {
+    /// Creates a system identity group.
+    Create(CreateGroupArgs),
+    /// Lists the system identity groups of an organization.
+    List(ListGroupsArgs),
+    /// Deletes a system identity group. Its member identities are kept.
+    Delete(GroupIdArgs),
+    #[command(verbatim_doc_comment)]
+    /// Adds identities to groups.
+    ///
+    /// Every identity is added to every group in a single request.
+    AddMembers(GroupMembershipArgs),
+    #[command(verbatim_doc_comment)]
+    /// Removes identities from groups.
+    ///
+    /// Every identity is removed from every group in a single request.
+    RemoveMembers(GroupMembershipArgs),
+    /// Lists the member identities of a group.
+    Members(GroupMembersArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CreateGroupArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    /// Name of the group
+    #[arg(long, short)]
+    pub name: String,
+
+    /// Description of the group
+    #[arg(long, short)]
+    pub description: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table", ignore_case = true)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListGroupsArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    /// Only groups whose name contains this text
+    #[arg(long, short)]
+    pub name: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table", ignore_case = true)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct GroupIdArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    /// ID of the group
+    #[arg(long)]
+    pub id: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct GroupMembershipArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    /// ID of a system identity. Can be repeated.
+    #[arg(long = "identity-id", required = true)]
+    pub identity_ids: Vec<String>,
+
+    /// ID of a group. Can be repeated.
+    #[arg(long = "group-id", required = true)]
+    pub group_ids: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct GroupMembersArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    /// ID of the group
+    #[arg(long)]
+    pub id: String,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table", ignore_case = true)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DeleteIdentityArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    #[command(flatten)]
+    criteria: DeletionCriteriaArgs,
+
+    /// Print the identities that would be deleted, without deleting them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table", ignore_case = true)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+#[group(required = true, multiple = true)]
+pub struct DeletionCriteriaArgs {
+    /// ID of the system identity to delete. Can be repeated.
+    #[arg(long = "id", conflicts_with_all = ["name_prefix", "older_than_days", "never_used"])]
+    ids: Vec<String>,
+
+    /// Only identities whose name starts with this text
+    #[arg(long)]
+    name_prefix: Option<String>,
+
+    /// Only identities created more than this number of days ago
+    #[arg(long)]
+    older_than_days: Option<u32>,
+
+    /// Only identities never used to obtain a token
+    #[arg(long)]
+    never_used: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum IdentityCommand {
+    #[command(verbatim_doc_comment)]
+    /// Lists the system identities of an organization, optionally filtered.
+    ///
+    /// Identities are listed one page at a time; pass the printed next cursor with `--cursor`
+    /// to get the following page, or use `--all` to list every page.
+    List(ListIdentitiesArgs),
+    /// Gets a system identity by its id.
+    Get(GetIdentityArgs),
+    #[command(verbatim_doc_comment)]
+    /// Changes the name, description or tags of a system identity.
+    ///
+    /// Only the given fields are changed. Tags replace the current ones and are only accepted by
+    /// organizations supporting them. Tags are not read back by `identity list` nor
+    /// `identity get`; the JSON output of this command shows them when they are changed.
+    Update(UpdateIdentityArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct OrganizationArgs {
+    /// Organization ID of the identities
+    #[arg(long, short)]
+    organization_id: String,
+
+    /// Environment to target
+    #[arg(long, short, ignore_case = true)]
+    environment: Environments,
+
+    /// Authentication method for the identity management API
+    #[command(flatten)]
+    auth_credential: AuthCredentialArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListIdentitiesArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    /// Only identities whose name contains this text
+    #[arg(long, short)]
+    name: Option<String>,
+
+    /// Only the identity with this client ID
+    #[arg(long, short)]
+    client_id: Option<String>,
+
+    /// Only identities created after this date (RFC 3339 date-time or YYYY-MM-DD)
+    #[arg(long, value_parser = parse_date_time)]
+    created_after: Option<DateTime<Utc>>,
+
+    /// Only identities created before this date (RFC 3339 date-time or YYYY-MM-DD)
+    #[arg(long, value_parser = parse_date_time)]
+    created_before: Option<DateTime<Utc>>,
+
+    /// Only members of the system identity group with this ID
+    #[arg(long, short)]
+    group_id: Option<String>,
+
+    /// Cursor of the page to list, as printed for the previous page
+    #[arg(long, conflicts_with = "all")]
+    pub cursor: Option<String>,
+
+    /// List every page
+    #[arg(long)]
+    pub all: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table", ignore_case = true)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct GetIdentityArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    /// ID of the system identity
+    #[arg(long)]
+    pub id: String,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table", ignore_case = true)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UpdateIdentityArgs {
+    #[command(flatten)]
+    pub organization: OrganizationArgs,
+
+    /// ID of the system identity
+    #[arg(long)]
+    pub id: String,
+
+    #[command(flatten)]
+    changes: IdentityChangesArgs,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table", ignore_case = true)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+#[group(required = true, multiple = true)]
+pub struct IdentityChangesArgs {
+    /// New name of the identity
+    #[arg(long, short)]
+    name: Option<String>,
+
+    /// New description of the identity
+    #[arg(long, short)]
+    description: Option<String>,
+
+    /// Tag of the identity, as `key=value`. Can be repeated; replaces every current tag.
+    #[arg(long = "tag", value_parser = SystemIdentityTag::from_str)]
+    tags: Vec<SystemIdentityTag>,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Human readable table
+    Table,
+    /// JSON document
+    Json,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum KeyCommand {
+    #[command(verbatim_doc_comment)]
+    /// Exports public keys as a JWK Set (RFC 7517) document.
+    ///
+    /// The `kid` of each key is its RFC 7638 thumbprint. Private keys are accepted as input,
+    /// but only their public part is exported.
+    ExportJwks(ExportJwksArgs),
+    #[command(verbatim_doc_comment)]
+    /// Validates a PEM key and reports its algorithm, size and fingerprint in JSON.
+    ///
+    /// The fingerprint is the SHA-256 digest of the DER public key, and the key id its RFC 7638
+    /// thumbprint. Private (PKCS#8 or PKCS#1) and public keys are accepted.
+    Validate(ValidateKeyArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ValidateKeyArgs {
+    /// Path to the PEM key to validate
+    #[arg(long)]
+    pub key_path: PathBuf,
+
+    /// Passphrase of the private key, if it is encrypted.
+    #[command(flatten)]
+    key_passphrase: KeyPassphraseArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportJwksArgs {
+    /// Path to a PEM public key or PKCS#8 private key. Can be repeated to export several keys.
+    #[arg(long = "key-path", required = true)]
+    pub key_paths: Vec<PathBuf>,
+
+    /// Path to the file where the JWKS document will be saved. Printed to stdout if not set.
+    #[arg(long)]
+    pub output_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RotateKeyArgs {
+    /// Organization ID of the system identity
+    #[arg(long, short)]
+    organization_id: String,
+
+    /// ID of the system identity whose key is rotated
+    #[arg(long)]
+    pub identity_id: String,
+
+    /// Client ID of the system identity
+    #[arg(long, short)]
+    pub client_id: String,
+
+    /// Environment to target
+    #[arg(long, short, ignore_case = true)]
+    environment: Environments,
+
+    /// Path to the current private key file, replaced by the new key
+    #[arg(long)]
+    pub private_key_path: PathBuf,
+
+    /// Type of the new key.
+    #[arg(long, value_enum, default_value = "rsa4096", ignore_case = true)]
+    key_type: KeyTypeChoice,
+
+    /// Authentication method for registering the new key
+    #[command(flatten)]
+    auth_credential: AuthCredentialArgs,
+
+    /// Passphrase of the current private key, if it is encrypted. The new key is encrypted with it too.
+    #[command(flatten)]
+    key_passphrase: KeyPassphraseArgs,
+
+    /// Remove the previous public key from the identity once the rotation succeeds. Processes
+    /// still signing with the previous key stop obtaining tokens.
+    #[arg(long)]
+    pub revoke_old_key: bool,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -117,6 +498,10 @@ pub struct AuthenticationArgs {
     /// At least one authentication method must be specified.
     #[command(flatten)]
     input_auth_args: AuthInputArgs,
+
+    /// Passphrase of the private key, if it is encrypted.
+    #[command(flatten)]
+    key_passphrase: KeyPassphraseArgs,
 }
 
 #[derive(ValueEnum, Clone, Debug, PartialEq)]
@@ -225,6 +610,11 @@ pub struct KeyArgs {
     /// Options for configuring the output destination (required for Key identity)
     #[command(flatten)]
     output_options: OutputDestinationArgs,
+
+    /// Return the existing identity with the same name and type instead of creating a new one.
+    /// For key identities, the public key of the existing identity must match the local key.
+    #[arg(long, requires = "name")]
+    if_not_exists: bool,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -236,6 +626,10 @@ pub struct SecretArgs {
     /// Authentication method for identity creation
     #[command(flatten)]
     auth_credential: AuthCredentialArgs,
+
+    /// Return the existing identity with the same name and type instead of creating a new one.
+    #[arg(long, requires = "name")]
+    if_not_exists: bool,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -277,15 +671,70 @@ pub enum OutputPlatformChoice {
     LocalFile,
 }
 
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq)]
+pub enum KeyTypeChoice {
+    Rsa2048,
+    Rsa3072,
+    Rsa4096,
+    EcdsaP256,
+    EcdsaP384,
+    Ed25519,
+}
+
 #[derive(Args, Debug, Clone)]
 pub struct OutputDestinationArgs {
     /// Platform for the output of the generated key or resource.
-    #[arg(long, value_enum)]
-    output_platform: OutputPlatformChoice,
+    #[arg(long, value_enum, required_unless_present = "import_key_path")]
+    output_platform: Option<OutputPlatformChoice>,
+
+    /// Path to an existing PEM private or public key to register instead of generating a new one.
+    #[arg(long, conflicts_with_all = ["output_platform", "output_local_filepath", "key_type"])]
+    import_key_path: Option<PathBuf>,
+
+    /// Type of the generated key. ECDSA and Ed25519 keys are much faster to generate on low-power devices.
+    #[arg(long, value_enum, default_value = "rsa4096", ignore_case = true)]
+    key_type: KeyTypeChoice,
 
     /// Path to the file where the private key output will be saved (required if --output-platform=local-file).
     #[arg(long)]
     output_local_filepath: Option<PathBuf>,
+
+    /// Passphrase to encrypt the private key output with (PKCS#8, PBES2 with AES-256-CBC).
+    #[command(flatten)]
+    key_passphrase: KeyPassphraseArgs,
+
+    /// Subject of a self-signed certificate of the key to write next to the private key, with the `.crt` extension (e.g. "CN=my-agent,O=My Company").
+    #[arg(long, conflicts_with = "import_key_path")]
+    certificate_subject: Option<CertificateSubject>,
+
+    /// Subject alternative name (DNS name or IP address) of the certificate. Can be repeated.
+    #[arg(long = "certificate-san", requires = "certificate_subject")]
+    certificate_sans: Vec<String>,
+
+    /// Days the certificate is valid for, up to 100 years.
+    #[arg(
+        long,
+        default_value_t = 365,
+        requires = "certificate_subject",
+        value_parser = clap::value_parser!(u64).range(1..=MAX_CERTIFICATE_VALIDITY_DAYS)
+    )]
+    certificate_validity_days: u64,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+#[group(multiple = false)]
+pub struct KeyPassphraseArgs {
+    /// Name of the environment variable holding the private key passphrase
+    #[arg(long)]
+    key_passphrase_env: Option<String>,
+
+    /// Path to the file holding the private key passphrase
+    #[arg(long)]
+    key_passphrase_file: Option<PathBuf>,
+
+    /// Prompt for the private key passphrase
+    #[arg(long)]
+    key_passphrase_prompt: bool,
 }
 
 pub fn create_metadata_for_token_retrieve(
@@ -294,6 +743,7 @@ pub fn create_metadata_for_token_retrieve(
     let auth_method = select_auth_method(
         auth_args.input_auth_args.client_secret.clone(),
         auth_args.input_auth_args.private_key_path.clone(),
+        select_passphrase(&auth_args.key_passphrase, false)?,
     )?;
 
     Ok(SystemTokenCreationMetadata {
@@ -318,6 +768,19 @@ pub fn create_metadata_for_identity_creation(
     }
 }
 
+impl From<KeyTypeChoice> for KeyType {
+    fn from(value: KeyTypeChoice) -> Self {
+        match value {
+            KeyTypeChoice::Rsa2048 => KeyType::Rsa2048,
+            KeyTypeChoice::Rsa3072 => KeyType::Rsa3072,
+            KeyTypeChoice::Rsa4096 => KeyType::Rsa4096,
+            KeyTypeChoice::EcdsaP256 => KeyType::EcdsaP256,
+            KeyTypeChoice::EcdsaP384 => KeyType::EcdsaP384,
+            KeyTypeChoice::Ed25519 => KeyType::Ed25519,
+        }
+    }
+}
+
 impl From<Environments> for NewRelicEnvironment {
     fn from(value: Environments) -> Self {
         match value {
@@ -337,7 +800,12 @@ pub fn extract_identity_creation_credential(
         IdentityType::Secret(secret_args) => &secret_args.auth_credential,
         IdentityType::Key(key_args) => &key_args.auth_credential,
     };
+    select_identity_credential(auth_credential)
+}
 
+fn select_identity_credential(
+    auth_credential: &AuthCredentialArgs,
+) -> Result<IdentityCreationCredential, Box<dyn std::error::Error>> {
     if let Some(bearer_token) = &auth_credential.bearer_access_token {
         Ok(IdentityCreationCredential::BearerToken(
             bearer_token.clone(),
@@ -352,12 +820,16 @@ pub fn extract_identity_creation_credential(
     }
 }
 
+pub fn select_key_type(key_args: &KeyArgs) -> KeyType {
+    key_args.output_options.key_type.into()
+}
+
 pub fn select_output_platform(key_args: KeyArgs) -> OutputPlatform {
     let output_platform = &key_args.output_options.output_platform;
     let output_filepath = key_args.output_options.output_local_filepath.clone();
 
     match output_platform {
-        OutputPlatformChoice::LocalFile => {
+        Some(OutputPlatformChoice::LocalFile) | None => {
             OutputPlatform::LocalPrivateKeyPath(output_filepath.unwrap_or_default())
         }
     }
@@ -366,16 +838,111 @@ pub fn select_output_platform(key_args: KeyArgs) -> OutputPlatform {
 pub fn select_auth_method(
     input_client_secret: Option<String>,
     input_private_key_path: Option<PathBuf>,
+    key_passphrase: Option<SecretSource>,
 ) -> Result<AuthMethod, Error> {
     if input_client_secret.is_some() {
         Ok(AuthMethod::ClientSecret(ClientSecret::from(
             input_client_secret.unwrap_or_default(),
         )))
     } else {
-        let private_key = fs::read_to_string(input_private_key_path.unwrap_or_default())?;
-        Ok(AuthMethod::PrivateKey(PrivateKeyPem::from(
-            private_key.into_bytes(),
-        )))
+        let mut private_key =
+            fs::read_to_string(input_private_key_path.unwrap_or_default())?.into_bytes();
+        if is_encrypted_pem(&private_key) {
+            let passphrase = key_passphrase.ok_or_else(|| {
+                Error::raw(
+                    MissingRequiredArgument,
+                    "The private key is encrypted, a --key-passphrase-* option must be provided",
+                )
+            })?;
+            private_key = passphrase
+                .read()
+                .map_err(EncryptedKeyError::from)
+                .and_then(|passphrase| decrypt_private_key_pem(&private_key, &passphrase))
+                .map_err(|e| Error::raw(InvalidValue, e.to_string()))?;
+        }
+        Ok(AuthMethod::PrivateKey(PrivateKeyPem::from(private_key)))
+    }
+}
+
+pub fn select_key_passphrase(key_args: &KeyArgs) -> Result<Option<SecretSource>, Error> {
+    select_output_passphrase(&key_args.output_options)
+}
+
+pub fn select_key_passphrase_bootstrap(
+    key_args: &KeyArgsBootstrap,
+) -> Result<Option<SecretSource>, Error> {
+    select_output_passphrase(&key_args.output_options)
+}
+
+/// Imported keys are decrypted with the passphrase, so it is not asked twice.
+fn select_output_passphrase(
+    output_options: &OutputDestinationArgs,
+) -> Result<Option<SecretSource>, Error> {
+    let confirm = output_options.import_key_path.is_none();
+    select_passphrase(&output_options.key_passphrase, confirm)
+}
+
+pub fn select_certificate(key_args: &KeyArgs) -> Option<CertificateConfig> {
+    select_output_certificate(&key_args.output_options)
+}
+
+pub fn select_certificate_bootstrap(key_args: &KeyArgsBootstrap) -> Option<CertificateConfig> {
+    select_output_certificate(&key_args.output_options)
+}
+
+fn select_output_certificate(output_options: &OutputDestinationArgs) -> Option<CertificateConfig> {
+    let subject = output_options.certificate_subject.clone()?;
+    let validity = Duration::from_secs(
+        output_options
+            .certificate_validity_days
+            .saturating_mul(24 * 60 * 60),
+    );
+    Some(
+        CertificateConfig::new(subject)
+            .with_subject_alt_names(output_options.certificate_sans.clone())
+            .with_validity(validity),
+    )
+}
+
+pub fn select_if_not_exists(identity_type: &IdentityType) -> bool {
+    match identity_type {
+        IdentityType::Secret(secret_args) => secret_args.if_not_exists,
+        IdentityType::Key(key_args) => key_args.if_not_exists,
+    }
+}
+
+pub fn select_key_import(key_args: &KeyArgs) -> Option<PathBuf> {
+    key_args.output_options.import_key_path.clone()
+}
+
+pub fn select_key_import_bootstrap(key_args: &KeyArgsBootstrap) -> Option<PathBuf> {
+    key_args.output_options.import_key_path.clone()
+}
+
+pub fn select_validate_key_passphrase(
+    args: &ValidateKeyArgs,
+) -> Result<Option<SecretSource>, Error> {
+    select_passphrase(&args.key_passphrase, false)
+}
+
+/// Builds the passphrase source from the arguments. Prompted passphrases are asked twice when
+/// `confirm` is set, as they are used to encrypt a new key.
+fn select_passphrase(
+    args: &KeyPassphraseArgs,
+    confirm: bool,
+) -> Result<Option<SecretSource>, Error> {
+    if let Some(name) = &args.key_passphrase_env {
+        Ok(Some(SecretSource::Env(name.clone())))
+    } else if let Some(path) = &args.key_passphrase_file {
+        Ok(Some(SecretSource::File(path.clone())))
+    } else if args.key_passphrase_prompt {
+        let passphrase = rpassword::prompt_password("Private key passphrase: ")?;
+        if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+            return Err(Error::raw(InvalidValue, "The passphrases do not match"));
+        }
+        Ok(Some(SecretSource::Value(passphrase)))
+    } else {
+        Ok(None)
     }
 }
 
@@ -401,17 +968,113 @@ pub fn extract_api_key_from_bootstrap(identity_type: &IdentityTypeBootstrap) ->
     }
 }
 
+pub fn select_key_type_bootstrap(key_args: &KeyArgsBootstrap) -> KeyType {
+    key_args.output_options.key_type.into()
+}
+
 pub fn select_output_platform_bootstrap(key_args: KeyArgsBootstrap) -> OutputPlatform {
     let output_platform = &key_args.output_options.output_platform;
     let output_filepath = key_args.output_options.output_local_filepath.clone();
 
     match output_platform {
-        OutputPlatformChoice::LocalFile => {
+        Some(OutputPlatformChoice::LocalFile) | None => {
             OutputPlatform::LocalPrivateKeyPath(output_filepath.unwrap_or_default())
         }
     }
 }
 
+pub fn create_metadata_for_key_rotation(args: &RotateKeyArgs) -> SystemIdentityCreationMetadata {
+    SystemIdentityCreationMetadata {
+        organization_id: args.organization_id.clone(),
+        name: None,
+        environment: args.environment.into(),
+    }
+}
+
+pub fn extract_key_rotation_credential(
+    args: &RotateKeyArgs,
+) -> Result<IdentityCreationCredential, Box<dyn std::error::Error>> {
+    select_identity_credential(&args.auth_credential)
+}
+
+pub fn select_key_type_rotation(args: &RotateKeyArgs) -> KeyType {
+    args.key_type.into()
+}
+
+pub fn select_key_passphrase_rotation(args: &RotateKeyArgs) -> Result<Option<SecretSource>, Error> {
+    select_passphrase(&args.key_passphrase, false)
+}
+
+pub fn create_metadata_for_organization(args: &OrganizationArgs) -> SystemIdentityCreationMetadata {
+    SystemIdentityCreationMetadata {
+        organization_id: args.organization_id.clone(),
+        name: None,
+        environment: args.environment.into(),
+    }
+}
+
+pub fn extract_organization_credential(
+    args: &OrganizationArgs,
+) -> Result<IdentityCreationCredential, Box<dyn std::error::Error>> {
+    select_identity_credential(&args.auth_credential)
+}
+
+pub fn select_identity_filter(args: &ListIdentitiesArgs) -> SystemIdentityFilter {
+    SystemIdentityFilter {
+        name: args.name.clone(),
+        client_id: args.client_id.clone(),
+        created_after: args.created_after,
+        created_before: args.created_before,
+        group_id: args.group_id.clone(),
+    }
+}
+
+pub fn select_identity_update(args: &UpdateIdentityArgs) -> SystemIdentityUpdate {
+    let changes = &args.changes;
+    SystemIdentityUpdate {
+        name: changes.name.clone(),
+        description: changes.description.clone(),
+        tags: (!changes.tags.is_empty()).then(|| changes.tags.clone()),
+    }
+}
+
+pub fn select_identity_ids_deletion(args: &DeleteIdentityArgs) -> Vec<String> {
+    args.criteria.ids.clone()
+}
+
+pub fn select_deletion_filter(args: &DeleteIdentityArgs) -> Result<DeletionFilter, Error> {
+    let criteria = &args.criteria;
+    let created_before = criteria
+        .older_than_days
+        .map(|days| {
+            TimeDelta::try_days(days.into())
+                .and_then(|age| Utc::now().checked_sub_signed(age))
+                .ok_or_else(|| {
+                    Error::raw(
+                        InvalidValue,
+                        format!("--older-than-days {days} is out of the supported date range"),
+                    )
+                })
+        })
+        .transpose()?;
+    Ok(DeletionFilter {
+        name_prefix: criteria.name_prefix.clone(),
+        created_before,
+        never_used: criteria.never_used,
+    })
+}
+
+/// Parses an RFC 3339 date-time, or a date taken as its midnight in UTC.
+fn parse_date_time(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date_time| date_time.to_utc())
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+        })
+        .map_err(|_| format!("`{value}` is not an RFC 3339 date-time or a YYYY-MM-DD date"))
+}
+
 pub fn build_proxy_args(proxy_args: ProxyArgs) -> Result<ProxyConfig, Error> {
     let config_result = ProxyConfig::new(
         proxy_args.proxy_url.unwrap_or_default(),
@@ -484,4 +1147,120 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_select_auth_method_encrypted_private_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("key.pem");
+        let private_key = crate::key::ec::ec(&KeyType::Ed25519).unwrap().private_key;
+        fs::write(
+            &key_path,
+            crate::key::pkcs8::encrypt_private_key_pem(&private_key, "passphrase").unwrap(),
+        )
+        .unwrap();
+
+        let result = select_auth_method(
+            None,
+            Some(key_path.clone()),
+            Some(SecretSource::Value("passphrase".to_string())),
+        );
+        assert!(
+            matches!(result, Ok(AuthMethod::PrivateKey(key)) if key == PrivateKeyPem::from(&private_key))
+        );
+
+        let result = select_auth_method(None, Some(key_path), None);
+        assert!(result.is_err_and(|e| e.kind() == MissingRequiredArgument));
+    }
+
+    #[test]
+    fn test_parse_date_time() {
+        let expected: DateTime<Utc> = "2026-03-01T00:00:00Z".parse().unwrap();
+        assert_eq!(parse_date_time("2026-03-01").unwrap(), expected);
+        assert_eq!(
+            parse_date_time("2026-03-01T01:00:00+01:00").unwrap(),
+            expected
+        );
+        assert!(parse_date_time("01/03/2026").is_err());
+    }
+
+    #[test]
+    fn test_certificate_validity_days_is_bounded() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            output: OutputDestinationArgs,
+        }
+        let parse = |days: &str| {
+            <Cli as clap::Parser>::try_parse_from([
+                "cli",
+                "--output-platform=local-file",
+                "--certificate-subject=CN=agent",
+                "--certificate-validity-days",
+                days,
+            ])
+        };
+
+        assert!(parse("36500").is_ok());
+        assert!(parse("0").is_err_and(|e| e.kind() == clap::error::ErrorKind::ValueValidation));
+        assert!(
+            parse("5000000").is_err_and(|e| e.kind() == clap::error::ErrorKind::ValueValidation)
+        );
+    }
+
+    #[test]
+    fn test_select_deletion_filter_out_of_range_age() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            delete: DeleteIdentityArgs,
+        }
+        let args = |older_than_days: &str| {
+            <Cli as clap::Parser>::try_parse_from([
+                "cli",
+                "--organization-id=org",
+                "--environment=US",
+                "--api-key=key",
+                "--older-than-days",
+                older_than_days,
+            ])
+            .unwrap()
+            .delete
+        };
+
+        let filter = select_deletion_filter(&args("30")).unwrap();
+        assert!(filter.created_before.unwrap() < Utc::now() - TimeDelta::days(29));
+        assert!(
+            select_deletion_filter(&args("4000000000")).is_err_and(|e| e.kind() == InvalidValue)
+        );
+    }
+
+    #[test]
+    fn test_key_rotation_requires_organization_id() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            rotate: RotateKeyArgs,
+        }
+        let parse = |organization_id: Option<&str>| {
+            let mut args = vec![
+                "cli",
+                "--identity-id=identity",
+                "--client-id=client",
+                "--environment=US",
+                "--api-key=key",
+                "--private-key-path=key.pem",
+            ];
+            args.extend(organization_id);
+            <Cli as clap::Parser>::try_parse_from(args)
+        };
+
+        assert!(
+            parse(None).is_err_and(|e| e.kind() == clap::error::ErrorKind::MissingRequiredArgument)
+        );
+        let args = parse(Some("--organization-id=org")).unwrap().rotate;
+        assert_eq!(
+            create_metadata_for_key_rotation(&args).organization_id,
+            "org"
+        );
+    }
 }
@@ -0,0 +1,243 @@
+//! Credentials loaded from a file and reloaded when the file changes.
+//! Files are polled: the modification time, size and inode are compared with the ones of the
+//! last load at most once per check interval, so credentials replaced on disk (e.g. by a key
+//! rotation) are picked up without restarting the process.
+use std::fmt::{self, Debug, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing::{debug, warn};
+
+use crate::sync::lock_recovering_poison;
+
+/// How often the file is checked for changes by default.
+pub(crate) const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+type Loader<T, E> = Box<dyn Fn(&Path) -> Result<T, E> + Send + Sync>;
+
+/// Identifies a version of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    #[cfg(unix)]
+    inode: u64,
+}
+
+impl FileStamp {
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            #[cfg(unix)]
+            inode: std::os::unix::fs::MetadataExt::ino(&metadata),
+        })
+    }
+}
+
+struct ReloadState<T> {
+    value: Arc<T>,
+    /// Version of the file last loaded, or that failed to load.
+    stamp: Option<FileStamp>,
+    checked_at: Instant,
+    /// Incremented each time the value is reloaded.
+    generation: u64,
+}
+
+/// Value loaded from a file, reloaded when the file changes. The previous value is kept when
+/// the changed file cannot be loaded.
+pub(crate) struct Reloadable<T, E> {
+    path: PathBuf,
+    load: Loader<T, E>,
+    check_interval: Duration,
+    state: Mutex<ReloadState<T>>,
+}
+
+impl<T, E: Display> Reloadable<T, E> {
+    /// Loads the value from `path`, failing if it cannot be loaded.
+    pub(crate) fn new<F>(path: PathBuf, load: F) -> Result<Self, E>
+    where
+        F: Fn(&Path) -> Result<T, E> + Send + Sync + 'static,
+    {
+        let stamp = FileStamp::read(&path);
+        let value = load(&path)?;
+        Ok(Self {
+            path,
+            load: Box::new(load),
+            check_interval: DEFAULT_CHECK_INTERVAL,
+            state: Mutex::new(ReloadState {
+                value: Arc::new(value),
+                stamp,
+                checked_at: Instant::now(),
+                generation: 0,
+            }),
+        })
+    }
+
+    pub(crate) fn with_check_interval(self, check_interval: Duration) -> Self {
+        Self {
+            check_interval,
+            ..self
+        }
+    }
+
+    /// Returns the current value, reloading it first if the file changed.
+    pub(crate) fn current(&self) -> Arc<T> {
+        let mut state = self.lock();
+        if state.checked_at.elapsed() < self.check_interval {
+            return state.value.clone();
+        }
+        state.checked_at = Instant::now();
+
+        let stamp = FileStamp::read(&self.path);
+        if stamp.is_none() || stamp == state.stamp {
+            // A missing file is most likely being replaced, keep using the loaded value.
+            return state.value.clone();
+        }
+        // The stamp is only recorded once the load returns, so a panicking loader leaves the
+        // previous version recorded and the file is loaded again on the next check.
+        match (self.load)(&self.path) {
+            Ok(value) => {
+                debug!("reloaded {}", self.path.display());
+                state.value = Arc::new(value);
+                state.generation += 1;
+            }
+            Err(e) => warn!(
+                "unable to reload {}, keeping the previous value: {e}",
+                self.path.display()
+            ),
+        }
+        state.stamp = stamp;
+        state.value.clone()
+    }
+
+    /// Returns how many times the value was reloaded, reloading it first if the file changed.
+    pub(crate) fn generation(&self) -> u64 {
+        self.current();
+        self.lock().generation
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ReloadState<T>> {
+        // The loader runs with the guard held, but the value, stamp and generation are only
+        // updated after it returns, so a panicking loader leaves the previous ones in place.
+        lock_recovering_poison(&self.state)
+    }
+}
+
+impl<T, E> Debug for Reloadable<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reloadable")
+            .field("path", &self.path)
+            .field("check_interval", &self.check_interval)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn load_number(path: &Path) -> Result<u32, String> {
+        fs::read_to_string(path)
+            .map_err(|e| e.to_string())?
+            .trim()
+            .parse()
+            .map_err(|e: std::num::ParseIntError| e.to_string())
+    }
+
+    /// Replaces the file the way key rotation tooling does, so the inode changes.
+    fn replace(path: &Path, content: &str) {
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content).unwrap();
+        fs::rename(tmp_path, path).unwrap();
+    }
+
+    #[test]
+    fn reloads_changed_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("value");
+        fs::write(&path, "1").unwrap();
+
+        let reloadable = Reloadable::new(path.clone(), load_number)
+            .unwrap()
+            .with_check_interval(Duration::ZERO);
+        assert_eq!(*reloadable.current(), 1);
+
+        replace(&path, "2");
+        assert_eq!(*reloadable.current(), 2);
+    }
+
+    #[test]
+    fn keeps_value_on_load_failure() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("value");
+        fs::write(&path, "1").unwrap();
+        let reloadable = Reloadable::new(path.clone(), load_number)
+            .unwrap()
+            .with_check_interval(Duration::ZERO);
+
+        replace(&path, "not a number");
+        assert_eq!(*reloadable.current(), 1);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(*reloadable.current(), 1);
+
+        replace(&path, "3");
+        assert_eq!(*reloadable.current(), 3);
+    }
+
+    #[test]
+    fn retries_load_after_loader_panic() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("value");
+        fs::write(&path, "1").unwrap();
+        let panic_next = Arc::new(AtomicBool::new(false));
+        let should_panic = panic_next.clone();
+        let reloadable = Reloadable::new(path.clone(), move |path| {
+            if should_panic.swap(false, Ordering::SeqCst) {
+                panic!("loader panic");
+            }
+            load_number(path)
+        })
+        .unwrap()
+        .with_check_interval(Duration::ZERO);
+
+        panic_next.store(true, Ordering::SeqCst);
+        replace(&path, "2");
+        let result = panic::catch_unwind(AssertUnwindSafe(|| reloadable.current()));
+        assert!(result.is_err());
+
+        // The unchanged file is loaded again instead of being considered already seen.
+        assert_eq!(*reloadable.current(), 2);
+        assert_eq!(reloadable.generation(), 1);
+    }
+
+    #[test]
+    fn checks_at_most_once_per_interval() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("value");
+        fs::write(&path, "1").unwrap();
+        let reloadable = Reloadable::new(path.clone(), load_number)
+            .unwrap()
+            .with_check_interval(Duration::from_secs(3600));
+
+        replace(&path, "2");
+        assert_eq!(*reloadable.current(), 1);
+    }
+
+    #[test]
+    fn initial_load_failure() {
+        let result = Reloadable::new(PathBuf::from("/non/existing/file"), |path| {
+            fs::read(path).map_err(|e: io::Error| e.to_string())
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,31 @@
+//! Synchronization helpers.
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Locks `mutex`, recovering the guard if a thread panicked while holding it.
+///
+/// Only for state that a panic in the critical section cannot leave half-updated, as each caller
+/// documents. Otherwise, report the poisoned lock as an error, as
+/// [`TokenRetrieverWithCache`](crate::token_retriever::TokenRetrieverWithCache) does.
+pub(crate) fn lock_recovering_poison<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::*;
+
+    #[test]
+    fn recovers_poisoned_lock() {
+        let mutex = Mutex::new(1);
+        let _ = panic::catch_unwind(|| {
+            let _guard = mutex.lock().unwrap();
+            panic!("poisoning the lock");
+        });
+        assert!(mutex.is_poisoned());
+
+        *lock_recovering_poison(&mutex) += 1;
+        assert_eq!(*lock_recovering_poison(&mutex), 2);
+    }
+}
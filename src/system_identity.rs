@@ -4,9 +4,14 @@ use std::fmt::Debug;
 use serde::Serialize;
 
 pub mod creation_response;
+pub mod deletion;
+pub mod group;
 pub mod iam_client;
 pub mod identity_creator;
 pub mod input_data;
+pub mod key_manager;
+pub mod query;
+pub mod update;
 
 /// System identity information. Final output of the System Identity creation process.
 #[derive(Debug, Clone, PartialEq, Serialize)]
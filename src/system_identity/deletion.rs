@@ -0,0 +1,130 @@
+//! Selection of the system identities removed by a bulk deletion, such as the ones left behind
+//! by decommissioned hosts.
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::query::{SystemIdentityFilter, SystemIdentityInfo};
+
+/// Criteria the identities removed by a bulk deletion must all match. Unset criteria match every
+/// identity, so an empty filter selects every identity of the organization.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeletionFilter {
+    /// Text the identity name starts with. Identities without a name never match.
+    pub name_prefix: Option<String>,
+    /// Only identities created before this time.
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only identities never used to obtain a token.
+    pub never_used: bool,
+}
+
+impl DeletionFilter {
+    pub fn with_name_prefix(self, name_prefix: impl Into<String>) -> Self {
+        Self {
+            name_prefix: Some(name_prefix.into()),
+            ..self
+        }
+    }
+
+    pub fn with_created_before(self, created_before: DateTime<Utc>) -> Self {
+        Self {
+            created_before: Some(created_before),
+            ..self
+        }
+    }
+
+    pub fn with_never_used(self) -> Self {
+        Self {
+            never_used: true,
+            ..self
+        }
+    }
+
+    /// Listing filter narrowing down the candidates server-side. The listed identities must
+    /// still be checked with [`DeletionFilter::matches`].
+    pub fn listing_filter(&self) -> SystemIdentityFilter {
+        SystemIdentityFilter {
+            name: self.name_prefix.clone(),
+            created_before: self.created_before,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the identity matches every criterion.
+    pub fn matches(&self, identity: &SystemIdentityInfo) -> bool {
+        let name_matches = self.name_prefix.as_ref().is_none_or(|prefix| {
+            identity
+                .name
+                .as_ref()
+                .is_some_and(|name| name.starts_with(prefix.as_str()))
+        });
+        // Identities without a known creation time are kept, they may well be recent.
+        let created_before_matches = self.created_before.is_none_or(|created_before| {
+            identity
+                .created_at
+                .is_some_and(|created_at| created_at < created_before)
+        });
+        let usage_matches = !self.never_used || identity.last_used_at.is_none();
+        name_matches && created_before_matches && usage_matches
+    }
+}
+
+/// Identity that could not be deleted.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailedDeletion {
+    pub identity: SystemIdentityInfo,
+    pub error: String,
+}
+
+/// Outcome of a bulk deletion.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DeletionReport {
+    pub deleted: Vec<SystemIdentityInfo>,
+    pub failed: Vec<FailedDeletion>,
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn identity(
+        name: Option<&str>,
+        created_at: Option<&str>,
+        last_used_at: Option<&str>,
+    ) -> SystemIdentityInfo {
+        SystemIdentityInfo {
+            id: "identity-id".to_string(),
+            name: name.map(String::from),
+            client_id: "client-id".to_string(),
+            organization_id: "org-id".to_string(),
+            created_at: created_at.map(|date| date.parse().unwrap()),
+            last_used_at: last_used_at.map(|date| date.parse().unwrap()),
+            description: None,
+            tags: Vec::new(),
+        }
+    }
+
+    const OLD: Option<&str> = Some("2025-01-01T00:00:00Z");
+    const RECENT: Option<&str> = Some("2026-06-01T00:00:00Z");
+
+    #[rstest]
+    #[case::all_match(identity(Some("host-a"), OLD, None), true)]
+    #[case::other_prefix(identity(Some("agent-host-a"), OLD, None), false)]
+    #[case::no_name(identity(None, OLD, None), false)]
+    #[case::recent(identity(Some("host-a"), RECENT, None), false)]
+    #[case::unknown_creation(identity(Some("host-a"), None, None), false)]
+    #[case::used(identity(Some("host-a"), OLD, RECENT), false)]
+    fn matches_every_criterion(#[case] identity: SystemIdentityInfo, #[case] expected: bool) {
+        let filter = DeletionFilter::default()
+            .with_name_prefix("host-")
+            .with_created_before("2026-01-01T00:00:00Z".parse().unwrap())
+            .with_never_used();
+        assert_eq!(filter.matches(&identity), expected);
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        assert!(DeletionFilter::default().matches(&identity(None, None, RECENT)));
+    }
+}
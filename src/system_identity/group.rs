@@ -0,0 +1,12 @@
+//! System identity groups, which grant the capabilities of the group to its member identities.
+use serde::Serialize;
+
+/// Existing system identity group.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SystemIdentityGroup {
+    pub id: String,
+    pub name: String,
+    pub organization_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
@@ -0,0 +1,462 @@
+//! GraphQL operations sent by the [`HttpIAMClient`](super::http::HttpIAMClient).
+//! Operations are static documents whose arguments are passed in the `variables` object of the
+//! request, so user-provided values (names, ids, keys) are never interpolated into the document.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::system_identity::group::SystemIdentityGroup;
+use crate::system_identity::query::{
+    SystemIdentityCredentialType, SystemIdentityInfo, SystemIdentityTag,
+};
+
+/// Body of a GraphQL request.
+#[derive(Debug, Serialize)]
+pub struct GraphQLRequest<V> {
+    pub query: &'static str,
+    pub variables: V,
+}
+
+impl<V: Serialize> GraphQLRequest<V> {
+    pub fn new(query: &'static str, variables: V) -> Self {
+        Self { query, variables }
+    }
+}
+
+/// Body of a successful GraphQL response.
+#[derive(Debug, Deserialize)]
+pub struct GraphQLResponse<D> {
+    pub data: D,
+}
+
+/// Object only queried for its id.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Id {
+    pub id: String,
+}
+
+pub const SYSTEM_IDENTITY_CREATE: &str = "\
+mutation SystemIdentityCreate($organizationId: String!, $name: String, $publicKey: String) { \
+systemIdentityCreate(organizationId: $organizationId, name: $name, publicKey: $publicKey) { \
+clientId, publicKey, id, name, organizationId, clientSecret, credentialExpiration } }";
+
+/// Variables of [`SYSTEM_IDENTITY_CREATE`]. The response is a
+/// [`SystemIdentityCreationResponse`](crate::system_identity::creation_response::SystemIdentityCreationResponse).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityCreateVariables<'a> {
+    pub organization_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'a str>,
+    /// Base64-encoded PEM public key, only set for L2 identities.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+pub const SYSTEM_IDENTITY_GROUPS_BY_NAME: &str = "\
+query SystemIdentityGroupsByName($organizationId: String!, $name: String!) { \
+customerAdministration { systemIdentityGroups(filter: \
+{ organizationId: { eq: $organizationId }, name: { eq: $name } }) { items { id } } } }";
+
+/// Variables of [`SYSTEM_IDENTITY_GROUPS_BY_NAME`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupsByNameVariables<'a> {
+    pub organization_id: &'a str,
+    pub name: &'a str,
+}
+
+/// Data of the [`SYSTEM_IDENTITY_GROUPS_BY_NAME`] response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupsData {
+    pub customer_administration: CustomerAdministration,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerAdministration {
+    pub system_identity_groups: Items<Id>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Items<T> {
+    pub items: Vec<T>,
+}
+
+pub const SYSTEM_IDENTITY_ADD_TO_GROUPS: &str = "\
+mutation SystemIdentityAddToGroups($systemIdentityIds: [ID!]!, $systemIdentityGroupIds: [ID!]!) { \
+systemIdentityAddToGroups(systemIdentityIds: $systemIdentityIds, \
+systemIdentityGroupIds: $systemIdentityGroupIds) { systemIdentityGroups { id } } }";
+
+pub const SYSTEM_IDENTITY_REMOVE_FROM_GROUPS: &str = "\
+mutation SystemIdentityRemoveFromGroups($systemIdentityIds: [ID!]!, \
+$systemIdentityGroupIds: [ID!]!) { \
+systemIdentityRemoveFromGroups(systemIdentityIds: $systemIdentityIds, \
+systemIdentityGroupIds: $systemIdentityGroupIds) { systemIdentityGroups { id } } }";
+
+/// Variables of [`SYSTEM_IDENTITY_ADD_TO_GROUPS`] and [`SYSTEM_IDENTITY_REMOVE_FROM_GROUPS`].
+/// Every identity is added to (or removed from) every group.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupMembershipVariables<'a> {
+    pub system_identity_ids: &'a [&'a str],
+    pub system_identity_group_ids: &'a [&'a str],
+}
+
+/// Data of the [`SYSTEM_IDENTITY_ADD_TO_GROUPS`] and [`SYSTEM_IDENTITY_REMOVE_FROM_GROUPS`]
+/// responses.
+#[derive(Debug, Deserialize)]
+pub struct SystemIdentityGroupMembershipData {
+    #[serde(
+        alias = "systemIdentityAddToGroups",
+        alias = "systemIdentityRemoveFromGroups"
+    )]
+    pub membership: SystemIdentityGroups,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroups {
+    pub system_identity_groups: Vec<Id>,
+}
+
+pub const SYSTEM_IDENTITY_ADD_PUBLIC_KEY: &str = "\
+mutation SystemIdentityAddPublicKey($systemIdentityId: ID!, $publicKey: String!) { \
+systemIdentityAddPublicKey(systemIdentityId: $systemIdentityId, publicKey: $publicKey) { id } }";
+
+pub const SYSTEM_IDENTITY_REMOVE_PUBLIC_KEY: &str = "\
+mutation SystemIdentityRemovePublicKey($systemIdentityId: ID!, $publicKey: String!) { \
+systemIdentityRemovePublicKey(systemIdentityId: $systemIdentityId, publicKey: $publicKey) { id } }";
+
+/// Variables of [`SYSTEM_IDENTITY_ADD_PUBLIC_KEY`] and [`SYSTEM_IDENTITY_REMOVE_PUBLIC_KEY`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityPublicKeyVariables<'a> {
+    pub system_identity_id: &'a str,
+    /// Base64-encoded PEM public key.
+    pub public_key: String,
+}
+
+/// Data of the [`SYSTEM_IDENTITY_ADD_PUBLIC_KEY`] and [`SYSTEM_IDENTITY_REMOVE_PUBLIC_KEY`]
+/// responses.
+#[derive(Debug, Deserialize)]
+pub struct SystemIdentityPublicKeyData {
+    #[serde(
+        alias = "systemIdentityAddPublicKey",
+        alias = "systemIdentityRemovePublicKey"
+    )]
+    pub system_identity: Id,
+}
+
+/// Fields selected for the [`SystemIdentityItem`] in responses. Tags are only selected by
+/// [`SYSTEM_IDENTITY_UPDATE_WITH_TAGS`], as not every environment supports them.
+macro_rules! system_identity_fields {
+    () => {
+        "id, name, clientId, organizationId, createdAt, lastUsedAt, description"
+    };
+}
+
+pub const SYSTEM_IDENTITIES: &str = concat!(
+    "query SystemIdentities($filter: SystemIdentityFilterInput!, $cursor: String) { ",
+    "customerAdministration { systemIdentities(filter: $filter, cursor: $cursor) { ",
+    "items { ",
+    system_identity_fields!(),
+    " } nextCursor } } }"
+);
+
+/// Variables of [`SYSTEM_IDENTITIES`].
+#[derive(Debug, Serialize)]
+pub struct SystemIdentitiesVariables<'a> {
+    pub filter: SystemIdentityFilterInput<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<&'a str>,
+}
+
+/// Filter of [`SYSTEM_IDENTITIES`]. Unset fields are left out so they don't filter anything.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityFilterInput<'a> {
+    pub organization_id: Eq<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Eq<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Contains<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<Eq<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<Range<DateTime<Utc>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_identity_group_id: Option<Eq<&'a str>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Eq<T> {
+    pub eq: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Contains<T> {
+    pub contains: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Range<T> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gt: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lt: Option<T>,
+}
+
+/// Data of the [`SYSTEM_IDENTITIES`] and [`SYSTEM_IDENTITIES_WITH_PUBLIC_KEYS`] responses.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentitiesData<I = SystemIdentityItem> {
+    pub customer_administration: SystemIdentitiesAdministration<I>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentitiesAdministration<I> {
+    pub system_identities: SystemIdentityItems<I>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityItems<I> {
+    pub items: Vec<I>,
+    pub next_cursor: Option<String>,
+}
+
+/// System identity in the [`SYSTEM_IDENTITIES`] response. Tags are only set in the
+/// [`SYSTEM_IDENTITY_UPDATE_WITH_TAGS`] response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityItem {
+    pub id: String,
+    pub name: Option<String>,
+    pub client_id: String,
+    pub organization_id: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tags: Vec<SystemIdentityTag>,
+}
+
+/// Deserializes a `null` value as the default one.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl From<SystemIdentityItem> for SystemIdentityInfo {
+    fn from(item: SystemIdentityItem) -> Self {
+        Self {
+            id: item.id,
+            name: item.name,
+            client_id: item.client_id,
+            organization_id: item.organization_id,
+            created_at: item.created_at,
+            last_used_at: item.last_used_at,
+            description: item.description,
+            tags: item.tags,
+        }
+    }
+}
+
+pub const SYSTEM_IDENTITIES_WITH_PUBLIC_KEYS: &str = concat!(
+    "query SystemIdentitiesWithPublicKeys($filter: SystemIdentityFilterInput!, ",
+    "$cursor: String) { customerAdministration { ",
+    "systemIdentities(filter: $filter, cursor: $cursor) { items { ",
+    system_identity_fields!(),
+    ", credentialType, publicKeys { publicKey } } nextCursor } } }"
+);
+
+/// System identity in the [`SYSTEM_IDENTITIES_WITH_PUBLIC_KEYS`] response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityWithPublicKeysItem {
+    #[serde(flatten)]
+    pub identity: SystemIdentityItem,
+    pub credential_type: SystemIdentityCredentialType,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub public_keys: Vec<PublicKeyItem>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyItem {
+    /// Base64-encoded PEM public key, as registered.
+    pub public_key: String,
+}
+
+pub const SYSTEM_IDENTITY_DELETE: &str = "\
+mutation SystemIdentityDelete($id: ID!) { systemIdentityDelete(id: $id) { id } }";
+
+/// Variables of [`SYSTEM_IDENTITY_DELETE`].
+#[derive(Debug, Serialize)]
+pub struct SystemIdentityDeleteVariables<'a> {
+    pub id: &'a str,
+}
+
+/// Data of the [`SYSTEM_IDENTITY_DELETE`] response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityDeleteData {
+    pub system_identity_delete: Id,
+}
+
+pub const SYSTEM_IDENTITY_UPDATE: &str = concat!(
+    "mutation SystemIdentityUpdate($id: ID!, $name: String, $description: String) { ",
+    "systemIdentityUpdate(id: $id, name: $name, description: $description) { ",
+    system_identity_fields!(),
+    " } }"
+);
+
+/// Same as [`SYSTEM_IDENTITY_UPDATE`], also replacing the tags. Only sent when tags are changed,
+/// so updates of the name or description keep working where tags are not supported.
+pub const SYSTEM_IDENTITY_UPDATE_WITH_TAGS: &str = concat!(
+    "mutation SystemIdentityUpdate($id: ID!, $name: String, $description: String, ",
+    "$tags: [SystemIdentityTagInput!]) { ",
+    "systemIdentityUpdate(id: $id, name: $name, description: $description, tags: $tags) { ",
+    system_identity_fields!(),
+    ", tags { key, value } } }"
+);
+
+/// Variables of [`SYSTEM_IDENTITY_UPDATE`] and [`SYSTEM_IDENTITY_UPDATE_WITH_TAGS`]. Unset fields
+/// are left out so they are not changed.
+#[derive(Debug, Serialize)]
+pub struct SystemIdentityUpdateVariables<'a> {
+    pub id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<&'a [SystemIdentityTag]>,
+}
+
+/// Data of the [`SYSTEM_IDENTITY_UPDATE`] and [`SYSTEM_IDENTITY_UPDATE_WITH_TAGS`] responses.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityUpdateData {
+    pub system_identity_update: SystemIdentityItem,
+}
+
+/// Fields selected for the [`SystemIdentityGroupItem`] in responses.
+macro_rules! system_identity_group_fields {
+    () => {
+        "id, name, organizationId, description"
+    };
+}
+
+pub const SYSTEM_IDENTITY_GROUP_CREATE: &str = concat!(
+    "mutation SystemIdentityGroupCreate($organizationId: String!, $name: String!, ",
+    "$description: String) { systemIdentityGroupCreate(organizationId: $organizationId, ",
+    "name: $name, description: $description) { ",
+    system_identity_group_fields!(),
+    " } }"
+);
+
+/// Variables of [`SYSTEM_IDENTITY_GROUP_CREATE`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupCreateVariables<'a> {
+    pub organization_id: &'a str,
+    pub name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'a str>,
+}
+
+/// Data of the [`SYSTEM_IDENTITY_GROUP_CREATE`] response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupCreateData {
+    pub system_identity_group_create: SystemIdentityGroupItem,
+}
+
+pub const SYSTEM_IDENTITY_GROUPS: &str = concat!(
+    "query SystemIdentityGroups($filter: SystemIdentityGroupFilterInput!, $cursor: String) { ",
+    "customerAdministration { systemIdentityGroups(filter: $filter, cursor: $cursor) { ",
+    "items { ",
+    system_identity_group_fields!(),
+    " } nextCursor } } }"
+);
+
+/// Variables of [`SYSTEM_IDENTITY_GROUPS`].
+#[derive(Debug, Serialize)]
+pub struct SystemIdentityGroupsVariables<'a> {
+    pub filter: SystemIdentityGroupFilterInput<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<&'a str>,
+}
+
+/// Filter of [`SYSTEM_IDENTITY_GROUPS`]. Unset fields are left out so they don't filter anything.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupFilterInput<'a> {
+    pub organization_id: Eq<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Contains<&'a str>>,
+}
+
+/// Data of the [`SYSTEM_IDENTITY_GROUPS`] response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupsListData {
+    pub customer_administration: SystemIdentityGroupsAdministration,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupsAdministration {
+    pub system_identity_groups: SystemIdentityGroupItems,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupItems {
+    pub items: Vec<SystemIdentityGroupItem>,
+    pub next_cursor: Option<String>,
+}
+
+/// System identity group in the [`SYSTEM_IDENTITY_GROUPS`] and
+/// [`SYSTEM_IDENTITY_GROUP_CREATE`] responses.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupItem {
+    pub id: String,
+    pub name: String,
+    pub organization_id: String,
+    pub description: Option<String>,
+}
+
+impl From<SystemIdentityGroupItem> for SystemIdentityGroup {
+    fn from(item: SystemIdentityGroupItem) -> Self {
+        Self {
+            id: item.id,
+            name: item.name,
+            organization_id: item.organization_id,
+            description: item.description,
+        }
+    }
+}
+
+pub const SYSTEM_IDENTITY_GROUP_DELETE: &str = "\
+mutation SystemIdentityGroupDelete($id: ID!) { systemIdentityGroupDelete(id: $id) { id } }";
+
+/// Variables of [`SYSTEM_IDENTITY_GROUP_DELETE`].
+#[derive(Debug, Serialize)]
+pub struct SystemIdentityGroupDeleteVariables<'a> {
+    pub id: &'a str,
+}
+
+/// Data of the [`SYSTEM_IDENTITY_GROUP_DELETE`] response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemIdentityGroupDeleteData {
+    pub system_identity_group_delete: Id,
+}
@@ -1,11 +1,35 @@
 use super::error::IAMClientError;
+use super::graphql::{
+    Contains, Eq, GraphQLRequest, GraphQLResponse, Range, SYSTEM_IDENTITIES,
+    SYSTEM_IDENTITIES_WITH_PUBLIC_KEYS, SYSTEM_IDENTITY_ADD_PUBLIC_KEY,
+    SYSTEM_IDENTITY_ADD_TO_GROUPS, SYSTEM_IDENTITY_CREATE, SYSTEM_IDENTITY_DELETE,
+    SYSTEM_IDENTITY_GROUP_CREATE, SYSTEM_IDENTITY_GROUP_DELETE, SYSTEM_IDENTITY_GROUPS,
+    SYSTEM_IDENTITY_GROUPS_BY_NAME, SYSTEM_IDENTITY_REMOVE_FROM_GROUPS,
+    SYSTEM_IDENTITY_REMOVE_PUBLIC_KEY, SYSTEM_IDENTITY_UPDATE, SYSTEM_IDENTITY_UPDATE_WITH_TAGS,
+    SystemIdentitiesData, SystemIdentitiesVariables, SystemIdentityCreateVariables,
+    SystemIdentityDeleteData, SystemIdentityDeleteVariables, SystemIdentityFilterInput,
+    SystemIdentityGroupCreateData, SystemIdentityGroupCreateVariables,
+    SystemIdentityGroupDeleteData, SystemIdentityGroupDeleteVariables,
+    SystemIdentityGroupFilterInput, SystemIdentityGroupMembershipData,
+    SystemIdentityGroupMembershipVariables, SystemIdentityGroupsByNameVariables,
+    SystemIdentityGroupsData, SystemIdentityGroupsListData, SystemIdentityGroupsVariables,
+    SystemIdentityPublicKeyData, SystemIdentityPublicKeyVariables, SystemIdentityUpdateData,
+    SystemIdentityUpdateVariables, SystemIdentityWithPublicKeysItem,
+};
 use crate::{
     http_client::HttpClient,
     system_identity::{
         SystemIdentity,
         creation_response::SystemIdentityCreationResponse,
+        deletion::{DeletionFilter, DeletionReport, FailedDeletion},
+        group::SystemIdentityGroup,
         identity_creator::{L1IdentityCreator, L2IdentityCreator},
         input_data::SystemIdentityCreationMetadata,
+        key_manager::L2KeyManager,
+        query::{
+            ExistingSystemIdentity, SystemIdentityFilter, SystemIdentityInfo, SystemIdentityPage,
+        },
+        update::SystemIdentityUpdate,
     },
 };
 use base64::{Engine, engine::general_purpose};
@@ -13,7 +37,8 @@ use http::{
     HeaderName, HeaderValue, Request,
     header::{AUTHORIZATION, CONTENT_TYPE},
 };
-use serde_json::{Value, json};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
 use std::str::FromStr;
 
 const API_KEY_HEADER: &str = "Api-Key";
@@ -51,6 +76,11 @@ where
         }
     }
 
+    /// Organization, name and environment of the identities managed by the client.
+    pub fn metadata(&self) -> &SystemIdentityCreationMetadata {
+        &self.metadata
+    }
+
     fn get_auth_header(
         auth_credential: &IAMAuthCredential,
     ) -> Result<(HeaderName, HeaderValue), IAMClientError> {
@@ -87,21 +117,14 @@ where
         auth_credentials: &IAMAuthCredential,
         maybe_pub_key: Option<&[u8]>,
     ) -> Result<SystemIdentity, IAMClientError> {
-        let pub_key_b64 = maybe_pub_key.map(|k| general_purpose::STANDARD.encode(k));
-        let json_body = assemble_create_identity_json_value(
-            self.metadata.name.as_ref(),
+        let request = assemble_create_identity_request(
+            self.metadata.name.as_deref(),
             self.metadata.organization_id.as_str(),
-            pub_key_b64,
+            maybe_pub_key.map(|k| general_purpose::STANDARD.encode(k)),
         );
 
-        let json = self.perform_graphql_request(auth_credentials, json_body)?;
-
         let system_identity_response: SystemIdentityCreationResponse =
-                    serde_json::from_value(json.clone()).map_err(|e| {
-                        IAMClientError::Decoder(format!(
-                            "Failed to decode JSON response for system identity creation: {e}. Response body: {json}"
-                        ))
-                    })?;
+            self.perform_graphql_request(auth_credentials, &request)?;
 
         let system_identity = system_identity_response
             .data()
@@ -121,34 +144,28 @@ where
         group_name: &str,
         auth_credentials: &IAMAuthCredential,
     ) -> Result<String, IAMClientError> {
-        let query = json!({"query": format!(
-            "query {{ customerAdministration {{ systemIdentityGroups(filter: {{ organizationId: {{ eq: \"{}\" }}, name: {{ eq: \"{}\" }} }}) {{ items {{ id }} }} }} }}",
-            self.metadata.organization_id, group_name
-        )});
-
-        let json = self.perform_graphql_request(auth_credentials, query)?;
-
-        let items = json
-            .get("data")
-            .and_then(|d| d.get("customerAdministration"))
-            .and_then(|ca| ca.get("systemIdentityGroups"))
-            .and_then(|sig| sig.get("items"))
-            .and_then(|items| items.as_array())
-            .ok_or_else(|| {
-                IAMClientError::Decoder(format!(
-                    "Failed to extract groups from response. Body: {json}"
-                ))
-            })?;
+        let request = GraphQLRequest::new(
+            SYSTEM_IDENTITY_GROUPS_BY_NAME,
+            SystemIdentityGroupsByNameVariables {
+                organization_id: &self.metadata.organization_id,
+                name: group_name,
+            },
+        );
 
-        items
-            .first()
-            .and_then(|group| group.get("id"))
-            .and_then(|id| id.as_str())
-            .map(String::from)
+        let response: GraphQLResponse<SystemIdentityGroupsData> =
+            self.perform_graphql_request(auth_credentials, &request)?;
+
+        response
+            .data
+            .customer_administration
+            .system_identity_groups
+            .items
+            .into_iter()
+            .next()
+            .map(|group| group.id)
             .ok_or_else(|| {
                 IAMClientError::IAMClient(format!(
-                    "{} not found in organization",
-                    NR_CONTROL_GROUP_NAME
+                    "system identity group `{group_name}` not found in organization"
                 ))
             })
     }
@@ -159,16 +176,144 @@ where
         group_id: &str,
         auth_credentials: &IAMAuthCredential,
     ) -> Result<(), IAMClientError> {
-        let query = json!({"query": format!(
-            "mutation {{ systemIdentityAddToGroups(systemIdentityIds: \"{}\", systemIdentityGroupIds: \"{}\") {{ systemIdentityGroups {{ id }} }} }}",
-            identity_id, group_id
-        )});
+        self.add_identities_to_groups(&[identity_id], &[group_id], auth_credentials)
+    }
+
+    /// Adds every identity to every group in a single request.
+    pub fn add_identities_to_groups(
+        &self,
+        identity_ids: &[&str],
+        group_ids: &[&str],
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<(), IAMClientError> {
+        self.change_group_membership(
+            SYSTEM_IDENTITY_ADD_TO_GROUPS,
+            identity_ids,
+            group_ids,
+            auth_credentials,
+        )
+    }
+
+    /// Removes every identity from every group in a single request.
+    pub fn remove_identities_from_groups(
+        &self,
+        identity_ids: &[&str],
+        group_ids: &[&str],
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<(), IAMClientError> {
+        self.change_group_membership(
+            SYSTEM_IDENTITY_REMOVE_FROM_GROUPS,
+            identity_ids,
+            group_ids,
+            auth_credentials,
+        )
+    }
+
+    fn change_group_membership(
+        &self,
+        mutation: &'static str,
+        identity_ids: &[&str],
+        group_ids: &[&str],
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<(), IAMClientError> {
+        if identity_ids.is_empty() || group_ids.is_empty() {
+            return Err(IAMClientError::Encoder(
+                "at least one system identity and one group are required".to_string(),
+            ));
+        }
+        let request = GraphQLRequest::new(
+            mutation,
+            SystemIdentityGroupMembershipVariables {
+                system_identity_ids: identity_ids,
+                system_identity_group_ids: group_ids,
+            },
+        );
+
+        let _: GraphQLResponse<SystemIdentityGroupMembershipData> =
+            self.perform_graphql_request(auth_credentials, &request)?;
+
+        Ok(())
+    }
+
+    /// Creates a system identity group in the organization.
+    pub fn create_system_identity_group(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentityGroup, IAMClientError> {
+        let request = GraphQLRequest::new(
+            SYSTEM_IDENTITY_GROUP_CREATE,
+            SystemIdentityGroupCreateVariables {
+                organization_id: &self.metadata.organization_id,
+                name,
+                description,
+            },
+        );
+        let response: GraphQLResponse<SystemIdentityGroupCreateData> =
+            self.perform_graphql_request(auth_credentials, &request)?;
+        Ok(response.data.system_identity_group_create.into())
+    }
 
-        self.perform_graphql_request(auth_credentials, query)?;
+    /// Lists all the organization's system identity groups, only the ones whose name contains
+    /// `name` if set, following the page cursors.
+    pub fn list_system_identity_groups(
+        &self,
+        name: Option<&str>,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<Vec<SystemIdentityGroup>, IAMClientError> {
+        let mut groups = Vec::new();
+        let mut cursor = None;
+        loop {
+            let request = GraphQLRequest::new(
+                SYSTEM_IDENTITY_GROUPS,
+                SystemIdentityGroupsVariables {
+                    filter: SystemIdentityGroupFilterInput {
+                        organization_id: Eq {
+                            eq: &self.metadata.organization_id,
+                        },
+                        name: name.map(|name| Contains { contains: name }),
+                    },
+                    cursor: cursor.as_deref(),
+                },
+            );
+            let response: GraphQLResponse<SystemIdentityGroupsListData> =
+                self.perform_graphql_request(auth_credentials, &request)?;
+            let page = response.data.customer_administration.system_identity_groups;
+            let last_page = page.items.is_empty() || page.next_cursor.is_none();
+            groups.extend(page.items.into_iter().map(SystemIdentityGroup::from));
+            if last_page {
+                return Ok(groups);
+            }
+            cursor = page.next_cursor;
+        }
+    }
 
+    /// Deletes the system identity group with the given id. Its member identities are kept.
+    pub fn delete_system_identity_group(
+        &self,
+        group_id: &str,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<(), IAMClientError> {
+        let request = GraphQLRequest::new(
+            SYSTEM_IDENTITY_GROUP_DELETE,
+            SystemIdentityGroupDeleteVariables { id: group_id },
+        );
+        let _: GraphQLResponse<SystemIdentityGroupDeleteData> =
+            self.perform_graphql_request(auth_credentials, &request)?;
         Ok(())
     }
 
+    /// Lists all the member identities of the system identity group with the given id.
+    pub fn list_system_identity_group_members(
+        &self,
+        group_id: &str,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<Vec<SystemIdentityInfo>, IAMClientError> {
+        let filter = SystemIdentityFilter::default().with_group_id(group_id);
+        self.list_all_system_identities(&filter, auth_credentials)
+    }
+
     /// Add identity to NR Control Group, granting organization.create.system_identities capability.
     pub fn add_identity_to_nr_control_group_by_id(
         &self,
@@ -180,12 +325,256 @@ where
         self.add_identity_to_group_by_id(identity_id, &group_id, auth_credentials)
     }
 
-    fn perform_graphql_request(
+    /// Lists a page of the organization's system identities matching `filter`, starting from
+    /// `cursor` (the first page if unset).
+    pub fn list_system_identities(
+        &self,
+        filter: &SystemIdentityFilter,
+        cursor: Option<&str>,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentityPage, IAMClientError> {
+        let created_at = (filter.created_after.is_some() || filter.created_before.is_some())
+            .then_some(Range {
+                gt: filter.created_after,
+                lt: filter.created_before,
+            });
+        let request = GraphQLRequest::new(
+            SYSTEM_IDENTITIES,
+            SystemIdentitiesVariables {
+                filter: SystemIdentityFilterInput {
+                    organization_id: Eq {
+                        eq: &self.metadata.organization_id,
+                    },
+                    id: None,
+                    name: filter
+                        .name
+                        .as_deref()
+                        .map(|name| Contains { contains: name }),
+                    client_id: filter.client_id.as_deref().map(|eq| Eq { eq }),
+                    created_at,
+                    system_identity_group_id: filter.group_id.as_deref().map(|eq| Eq { eq }),
+                },
+                cursor,
+            },
+        );
+        self.query_system_identities(&request, auth_credentials)
+    }
+
+    /// Lists all the organization's system identities matching `filter`, following the page
+    /// cursors.
+    pub fn list_all_system_identities(
+        &self,
+        filter: &SystemIdentityFilter,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<Vec<SystemIdentityInfo>, IAMClientError> {
+        let mut identities = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = self.list_system_identities(filter, cursor.as_deref(), auth_credentials)?;
+            let last_page = page.items.is_empty() || page.next_cursor.is_none();
+            identities.extend(page.items);
+            if last_page {
+                return Ok(identities);
+            }
+            cursor = page.next_cursor;
+        }
+    }
+
+    /// Gets the system identity with the given id, if it exists in the organization.
+    pub fn get_system_identity(
+        &self,
+        identity_id: &str,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<Option<SystemIdentityInfo>, IAMClientError> {
+        let request = GraphQLRequest::new(
+            SYSTEM_IDENTITIES,
+            SystemIdentitiesVariables {
+                filter: SystemIdentityFilterInput {
+                    organization_id: Eq {
+                        eq: &self.metadata.organization_id,
+                    },
+                    id: Some(Eq { eq: identity_id }),
+                    name: None,
+                    client_id: None,
+                    created_at: None,
+                    system_identity_group_id: None,
+                },
+                cursor: None,
+            },
+        );
+        let page = self.query_system_identities(&request, auth_credentials)?;
+        Ok(page.items.into_iter().next())
+    }
+
+    /// Finds the organization's system identities named exactly `name`, along with their
+    /// credential type and the public keys registered for them.
+    pub fn find_system_identities_by_name(
+        &self,
+        name: &str,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<Vec<ExistingSystemIdentity>, IAMClientError> {
+        let mut identities = Vec::new();
+        let mut cursor = None;
+        loop {
+            let request = GraphQLRequest::new(
+                SYSTEM_IDENTITIES_WITH_PUBLIC_KEYS,
+                SystemIdentitiesVariables {
+                    filter: SystemIdentityFilterInput {
+                        organization_id: Eq {
+                            eq: &self.metadata.organization_id,
+                        },
+                        id: None,
+                        name: Some(Contains { contains: name }),
+                        client_id: None,
+                        created_at: None,
+                        system_identity_group_id: None,
+                    },
+                    cursor: cursor.as_deref(),
+                },
+            );
+            let response: GraphQLResponse<SystemIdentitiesData<SystemIdentityWithPublicKeysItem>> =
+                self.perform_graphql_request(auth_credentials, &request)?;
+            let page = response.data.customer_administration.system_identities;
+            let last_page = page.items.is_empty() || page.next_cursor.is_none();
+            for item in page.items {
+                // The name filter matches names containing `name`.
+                if item.identity.name.as_deref() != Some(name) {
+                    continue;
+                }
+                let public_keys = item
+                    .public_keys
+                    .iter()
+                    .map(|key| general_purpose::STANDARD.decode(&key.public_key))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| {
+                        IAMClientError::Decoder(format!("invalid registered public key: {e}"))
+                    })?;
+                identities.push(ExistingSystemIdentity {
+                    identity: item.identity.into(),
+                    credential_type: item.credential_type,
+                    public_keys,
+                });
+            }
+            if last_page {
+                return Ok(identities);
+            }
+            cursor = page.next_cursor;
+        }
+    }
+
+    /// Changes the name, description or tags of the system identity with the given id, returning
+    /// the updated identity.
+    pub fn update_system_identity(
+        &self,
+        identity_id: &str,
+        update: &SystemIdentityUpdate,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentityInfo, IAMClientError> {
+        if update.is_empty() {
+            return Err(IAMClientError::Encoder(
+                "no system identity field to update".to_string(),
+            ));
+        }
+        let query = if update.tags.is_some() {
+            SYSTEM_IDENTITY_UPDATE_WITH_TAGS
+        } else {
+            SYSTEM_IDENTITY_UPDATE
+        };
+        let request = GraphQLRequest::new(
+            query,
+            SystemIdentityUpdateVariables {
+                id: identity_id,
+                name: update.name.as_deref(),
+                description: update.description.as_deref(),
+                tags: update.tags.as_deref(),
+            },
+        );
+        let response: GraphQLResponse<SystemIdentityUpdateData> =
+            self.perform_graphql_request(auth_credentials, &request)?;
+        Ok(response.data.system_identity_update.into())
+    }
+
+    /// Deletes the system identity with the given id.
+    pub fn delete_system_identity(
+        &self,
+        identity_id: &str,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<(), IAMClientError> {
+        let request = GraphQLRequest::new(
+            SYSTEM_IDENTITY_DELETE,
+            SystemIdentityDeleteVariables { id: identity_id },
+        );
+        let _: GraphQLResponse<SystemIdentityDeleteData> =
+            self.perform_graphql_request(auth_credentials, &request)?;
+        Ok(())
+    }
+
+    /// Lists the organization's system identities matching every criterion of `filter`, which
+    /// [`HttpIAMClient::delete_matching_system_identities`] would delete.
+    pub fn find_system_identities_to_delete(
+        &self,
+        filter: &DeletionFilter,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<Vec<SystemIdentityInfo>, IAMClientError> {
+        let identities =
+            self.list_all_system_identities(&filter.listing_filter(), auth_credentials)?;
+        Ok(identities
+            .into_iter()
+            .filter(|identity| filter.matches(identity))
+            .collect())
+    }
+
+    /// Deletes the given system identities. A failed deletion doesn't stop the others, it is
+    /// reported instead.
+    pub fn delete_system_identities(
+        &self,
+        identities: Vec<SystemIdentityInfo>,
+        auth_credentials: &IAMAuthCredential,
+    ) -> DeletionReport {
+        let mut report = DeletionReport::default();
+        for identity in identities {
+            match self.delete_system_identity(&identity.id, auth_credentials) {
+                Ok(()) => report.deleted.push(identity),
+                Err(e) => report.failed.push(FailedDeletion {
+                    identity,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        report
+    }
+
+    /// Deletes the organization's system identities matching every criterion of `filter`.
+    pub fn delete_matching_system_identities(
         &self,
+        filter: &DeletionFilter,
         auth_credentials: &IAMAuthCredential,
-        json_body: Value,
-    ) -> Result<Value, IAMClientError> {
-        let request = self.build_graphql_request(json_body, auth_credentials)?;
+    ) -> Result<DeletionReport, IAMClientError> {
+        let identities = self.find_system_identities_to_delete(filter, auth_credentials)?;
+        Ok(self.delete_system_identities(identities, auth_credentials))
+    }
+
+    fn query_system_identities(
+        &self,
+        request: &GraphQLRequest<SystemIdentitiesVariables>,
+        auth_credentials: &IAMAuthCredential,
+    ) -> Result<SystemIdentityPage, IAMClientError> {
+        let response: GraphQLResponse<SystemIdentitiesData> =
+            self.perform_graphql_request(auth_credentials, request)?;
+        let identities = response.data.customer_administration.system_identities;
+        Ok(SystemIdentityPage {
+            items: identities.items.into_iter().map(Into::into).collect(),
+            next_cursor: identities.next_cursor,
+        })
+    }
+
+    /// Sends the GraphQL `request` and decodes its response, failing if it contains errors.
+    fn perform_graphql_request<R: DeserializeOwned>(
+        &self,
+        auth_credentials: &IAMAuthCredential,
+        request: &impl Serialize,
+    ) -> Result<R, IAMClientError> {
+        let request = self.build_graphql_request(request, auth_credentials)?;
 
         let response = self.http_client.send(request).map_err(|e| {
             IAMClientError::Transport(format!("Failed to send graphql request: {e}"))
@@ -214,15 +603,19 @@ where
             )));
         }
 
-        Ok(json)
+        serde_json::from_value(json.clone()).map_err(|e| {
+            IAMClientError::Decoder(format!(
+                "Failed to decode GraphQL response data: {e}. Body: {json}"
+            ))
+        })
     }
 
     fn build_graphql_request(
         &self,
-        body: Value,
+        body: &impl Serialize,
         auth_credential: &IAMAuthCredential,
     ) -> Result<Request<Vec<u8>>, IAMClientError> {
-        let json_body = serde_json::to_vec(&body)
+        let json_body = serde_json::to_vec(body)
             .map_err(|e| IAMClientError::Encoder(format!("Failed to encode JSON: {e}")))?;
 
         let mut request_builder = Request::builder()
@@ -239,23 +632,19 @@ where
     }
 }
 
-fn assemble_create_identity_json_value(
-    maybe_name: Option<&String>,
-    organization_id: &str,
+fn assemble_create_identity_request<'a>(
+    maybe_name: Option<&'a str>,
+    organization_id: &'a str,
     maybe_pub_key_b64: Option<String>,
-) -> Value {
-    let optional_name_str = maybe_name
-        .map(|n| format!(", name: \"{n}\""))
-        .unwrap_or_default();
-    let optional_key_str = maybe_pub_key_b64
-        .map(|k| format!(", publicKey: \"{k}\""))
-        .unwrap_or_default();
-
-    json!({
-        "query": format!(
-            "mutation {{ systemIdentityCreate(organizationId: \"{organization_id}\"{optional_name_str}{optional_key_str}) {{ clientId, publicKey, id, name, organizationId, clientSecret, credentialExpiration }} }}",
-        ),
-    })
+) -> GraphQLRequest<SystemIdentityCreateVariables<'a>> {
+    GraphQLRequest::new(
+        SYSTEM_IDENTITY_CREATE,
+        SystemIdentityCreateVariables {
+            organization_id,
+            name: maybe_name,
+            public_key: maybe_pub_key_b64,
+        },
+    )
 }
 
 impl<C> L2IdentityCreator for HttpIAMClient<C>
@@ -285,16 +674,65 @@ where
     }
 }
 
+fn assemble_public_key_request<'a>(
+    mutation: &'static str,
+    identity_id: &'a str,
+    pub_key: &[u8],
+) -> GraphQLRequest<SystemIdentityPublicKeyVariables<'a>> {
+    GraphQLRequest::new(
+        mutation,
+        SystemIdentityPublicKeyVariables {
+            system_identity_id: identity_id,
+            public_key: general_purpose::STANDARD.encode(pub_key),
+        },
+    )
+}
+
+impl<C> L2KeyManager for HttpIAMClient<C>
+where
+    C: HttpClient,
+{
+    type Error = IAMClientError;
+    fn add_l2_public_key(
+        &self,
+        auth_credentials: &IAMAuthCredential,
+        identity_id: &str,
+        pub_key: &[u8],
+    ) -> Result<(), Self::Error> {
+        let request =
+            assemble_public_key_request(SYSTEM_IDENTITY_ADD_PUBLIC_KEY, identity_id, pub_key);
+        let _: GraphQLResponse<SystemIdentityPublicKeyData> =
+            self.perform_graphql_request(auth_credentials, &request)?;
+        Ok(())
+    }
+
+    fn remove_l2_public_key(
+        &self,
+        auth_credentials: &IAMAuthCredential,
+        identity_id: &str,
+        pub_key: &[u8],
+    ) -> Result<(), Self::Error> {
+        let request =
+            assemble_public_key_request(SYSTEM_IDENTITY_REMOVE_PUBLIC_KEY, identity_id, pub_key);
+        let _: GraphQLResponse<SystemIdentityPublicKeyData> =
+            self.perform_graphql_request(auth_credentials, &request)?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::http_client::HttpClientError;
     use crate::http_client::tests::MockHttpClient;
     use crate::system_identity::input_data::{
         SystemIdentityCreationMetadata, environment::NewRelicEnvironment,
     };
+    use crate::system_identity::query::SystemIdentityTag;
     use assert_matches::assert_matches;
     use http::{Method, Response, Uri};
     use rstest::rstest;
+    use serde_json::json;
 
     #[rstest]
     #[case(None, IAMAuthCredential::BearerToken("test_token".to_string()))]
@@ -319,14 +757,14 @@ mod tests {
 
         let iam_client = HttpIAMClient::new(MockHttpClient::new(), metadata.clone());
 
-        let json_body = assemble_create_identity_json_value(
-            metadata.name.as_ref(),
+        let graphql_request = assemble_create_identity_request(
+            metadata.name.as_deref(),
             metadata.organization_id.as_str(),
             maybe_pub_key_b64.clone(),
         );
 
         let request = iam_client
-            .build_graphql_request(json_body, &auth_credential)
+            .build_graphql_request(&graphql_request, &auth_credential)
             .unwrap();
 
         assert_eq!(request.method(), Method::POST);
@@ -354,26 +792,63 @@ mod tests {
         }
 
         let body: Value = serde_json::from_slice(request.body()).unwrap();
+        assert_eq!(body["query"], SYSTEM_IDENTITY_CREATE);
         assert_eq!(
-            body,
+            body["variables"],
             if let Some(pub_key_b64) = maybe_pub_key_b64 {
-                json!({
-                    "query": format!(
-                        "mutation {{ systemIdentityCreate(organizationId: \"{}\", name: \"{}\", publicKey: \"{}\") {{ clientId, publicKey, id, name, organizationId, clientSecret, credentialExpiration }} }}",
-                        org_id, name, pub_key_b64
-                    ),
-                })
+                json!({"organizationId": org_id, "name": name, "publicKey": pub_key_b64})
             } else {
-                json!({
-                    "query": format!(
-                        "mutation {{ systemIdentityCreate(organizationId: \"{}\", name: \"{}\") {{ clientId, publicKey, id, name, organizationId, clientSecret, credentialExpiration }} }}",
-                        org_id, name
-                    ),
-                })
+                json!({"organizationId": org_id, "name": name})
             }
         );
     }
 
+    #[rstest]
+    #[case::quote(r#"agent "prod""#)]
+    #[case::backslash(r"agent\prod\")]
+    #[case::injection(r#"x") { id } systemIdentityDelete(id: "other"#)]
+    #[case::braces_and_newline("agent {\n} $name")]
+    #[case::unicode("agente ñ 🚀")]
+    fn create_identity_with_special_characters_in_name(#[case] name: &'static str) {
+        let metadata = SystemIdentityCreationMetadata {
+            organization_id: "org-123".to_string(),
+            name: Some(name.to_string()),
+            environment: NewRelicEnvironment::Staging,
+        };
+
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(move |req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                // The document is static, the name is only sent as a variable.
+                body["query"] == SYSTEM_IDENTITY_CREATE
+                    && body["variables"] == json!({"organizationId": "org-123", "name": name})
+            })
+            .returning(move |_| {
+                let response = json!({"data": {"systemIdentityCreate": {
+                    "clientId": "client-id",
+                    "id": "identity-id",
+                    "name": name,
+                    "organizationId": "org-123",
+                    "clientSecret": "secret",
+                    "credentialExpiration": "2026-01-01T00:00:00Z",
+                }}});
+                Ok(Response::builder()
+                    .status(200)
+                    .body(serde_json::to_vec(&response).unwrap())
+                    .unwrap())
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, metadata);
+        let auth_credential = IAMAuthCredential::BearerToken("test-token".to_string());
+        let identity = iam_client
+            .create_l1_system_identity(&auth_credential)
+            .unwrap();
+        assert_eq!(identity.name.as_deref(), Some(name));
+    }
+
     #[rstest]
     #[case::found(
         r#"{"data":{"customerAdministration":{"systemIdentityGroups":{"items":[{"id":"group-456"}]}}}}"#,
@@ -401,10 +876,9 @@ mod tests {
             .once()
             .withf(|req| {
                 let body: Value = serde_json::from_slice(req.body()).unwrap();
-                let query = body["query"].as_str().unwrap();
-
-                query.contains("organizationId: { eq: \"org-123\" }")
-                    && query.contains("name: { eq: \"NR Control Group\" }")
+                body["query"] == SYSTEM_IDENTITY_GROUPS_BY_NAME
+                    && body["variables"]
+                        == json!({"organizationId": "org-123", "name": "NR Control Group"})
             })
             .returning(move |_| {
                 let response = Response::builder()
@@ -459,7 +933,7 @@ mod tests {
         let auth_credential = IAMAuthCredential::BearerToken("test-token".to_string());
         let query = json!({"query": "{ test }"});
 
-        let result = iam_client.perform_graphql_request(&auth_credential, query);
+        let result: Result<Value, _> = iam_client.perform_graphql_request(&auth_credential, &query);
 
         if let Some(expected_msg) = expected_error_contains {
             assert_matches!(result.unwrap_err(), IAMClientError::Transport(msg) =>{
@@ -469,4 +943,679 @@ mod tests {
             assert!(result.is_ok());
         }
     }
+
+    #[test]
+    fn test_add_identity_to_group_with_special_characters() {
+        let metadata = SystemIdentityCreationMetadata {
+            organization_id: "org-123".to_string(),
+            name: None,
+            environment: NewRelicEnvironment::Staging,
+        };
+        let identity_id = r#"id", systemIdentityGroupIds: "admins"#;
+
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(move |req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITY_ADD_TO_GROUPS
+                    && body["variables"]
+                        == json!({
+                            "systemIdentityIds": [identity_id],
+                            "systemIdentityGroupIds": ["group-\\456"],
+                        })
+            })
+            .returning(|_| {
+                Ok(Response::builder()
+                    .status(200)
+                    .body(
+                        br#"{"data":{"systemIdentityAddToGroups":{"systemIdentityGroups":[{"id":"group-\\456"}]}}}"#
+                            .to_vec(),
+                    )
+                    .unwrap())
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, metadata);
+        let auth_credential = IAMAuthCredential::BearerToken("test-token".to_string());
+        let result =
+            iam_client.add_identity_to_group_by_id(identity_id, "group-\\456", &auth_credential);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_unexpected_response_data() {
+        let metadata = SystemIdentityCreationMetadata {
+            organization_id: "org-123".to_string(),
+            name: None,
+            environment: NewRelicEnvironment::Staging,
+        };
+
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client.expect_send().once().returning(|_| {
+            Ok(Response::builder()
+                .status(200)
+                .body(br#"{"data":{"customerAdministration":null}}"#.to_vec())
+                .unwrap())
+        });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, metadata);
+        let auth_credential = IAMAuthCredential::BearerToken("test-token".to_string());
+        let result = iam_client
+            .find_system_identity_group_id_by_name(NR_CONTROL_GROUP_NAME, &auth_credential);
+        assert_matches!(result, Err(IAMClientError::Decoder(_)));
+    }
+
+    #[rstest]
+    #[case::add(SYSTEM_IDENTITY_ADD_PUBLIC_KEY, "systemIdentityAddPublicKey")]
+    #[case::remove(SYSTEM_IDENTITY_REMOVE_PUBLIC_KEY, "systemIdentityRemovePublicKey")]
+    fn test_manage_l2_public_key(#[case] mutation: &'static str, #[case] field: &'static str) {
+        let metadata = SystemIdentityCreationMetadata {
+            organization_id: "org-123".to_string(),
+            name: None,
+            environment: NewRelicEnvironment::Staging,
+        };
+
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(move |req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == mutation
+                    && body["variables"]
+                        == json!({"systemIdentityId": "identity-123", "publicKey": "AQID"})
+            })
+            .returning(move |_| {
+                let response = json!({"data": {field: {"id": "identity-123"}}});
+                Ok(Response::builder()
+                    .status(200)
+                    .body(serde_json::to_vec(&response).unwrap())
+                    .unwrap())
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, metadata);
+        let auth_credential = IAMAuthCredential::BearerToken("test-token".to_string());
+        let result = match mutation {
+            SYSTEM_IDENTITY_ADD_PUBLIC_KEY => {
+                iam_client.add_l2_public_key(&auth_credential, "identity-123", &[1, 2, 3])
+            }
+            _ => iam_client.remove_l2_public_key(&auth_credential, "identity-123", &[1, 2, 3]),
+        };
+        assert!(result.is_ok());
+    }
+
+    /// Successful response with `body` serialized as JSON.
+    pub(crate) fn json_response(body: Value) -> Result<Response<Vec<u8>>, HttpClientError> {
+        Ok(Response::builder()
+            .status(200)
+            .body(serde_json::to_vec(&body).unwrap())
+            .unwrap())
+    }
+
+    fn identities_response(ids: &[&str], next_cursor: Option<&str>) -> Value {
+        let items = ids
+            .iter()
+            .map(|id| {
+                json!({
+                    "id": id,
+                    "name": format!("name-{id}"),
+                    "clientId": format!("client-{id}"),
+                    "organizationId": "org-123",
+                    "createdAt": "2026-03-01T10:00:00Z",
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({"data": {"customerAdministration": {"systemIdentities": {
+            "items": items,
+            "nextCursor": next_cursor,
+        }}}})
+    }
+
+    fn management_metadata() -> SystemIdentityCreationMetadata {
+        SystemIdentityCreationMetadata {
+            organization_id: "org-123".to_string(),
+            name: None,
+            environment: NewRelicEnvironment::Staging,
+        }
+    }
+
+    #[test]
+    fn test_list_system_identities_with_filter() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITIES
+                    && body["variables"]
+                        == json!({
+                            "filter": {
+                                "organizationId": {"eq": "org-123"},
+                                "name": {"contains": "host \"a\""},
+                                "clientId": {"eq": "client-1"},
+                                "createdAt": {"gt": "2026-01-01T00:00:00Z"},
+                            },
+                            "cursor": "cursor-1",
+                        })
+            })
+            .returning(|_| json_response(identities_response(&["1"], Some("cursor-2"))));
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let filter = SystemIdentityFilter::default()
+            .with_name("host \"a\"")
+            .with_client_id("client-1")
+            .with_created_after("2026-01-01T00:00:00Z".parse().unwrap());
+        let page = iam_client
+            .list_system_identities(&filter, Some("cursor-1"), &auth_credential)
+            .unwrap();
+
+        assert_eq!(
+            page,
+            SystemIdentityPage {
+                items: vec![SystemIdentityInfo {
+                    id: "1".to_string(),
+                    name: Some("name-1".to_string()),
+                    client_id: "client-1".to_string(),
+                    organization_id: "org-123".to_string(),
+                    created_at: Some("2026-03-01T10:00:00Z".parse().unwrap()),
+                    last_used_at: None,
+                    description: None,
+                    tags: Vec::new(),
+                }],
+                next_cursor: Some("cursor-2".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_list_all_system_identities_follows_cursors() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["variables"] == json!({"filter": {"organizationId": {"eq": "org-123"}}})
+            })
+            .returning(|_| json_response(identities_response(&["1", "2"], Some("cursor-2"))));
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["variables"]["cursor"] == "cursor-2"
+            })
+            .returning(|_| json_response(identities_response(&["3"], None)));
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let identities = iam_client
+            .list_all_system_identities(&SystemIdentityFilter::default(), &auth_credential)
+            .unwrap();
+
+        let ids = identities.iter().map(|i| i.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2", "3"]);
+    }
+
+    #[rstest]
+    #[case::found(&["identity-1"], Some("identity-1"))]
+    #[case::not_found(&[], None)]
+    fn test_get_system_identity(
+        #[case] ids: &'static [&'static str],
+        #[case] expected: Option<&str>,
+    ) {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITIES
+                    && body["variables"]
+                        == json!({"filter": {
+                            "organizationId": {"eq": "org-123"},
+                            "id": {"eq": "identity-1"},
+                        }})
+            })
+            .returning(move |_| json_response(identities_response(ids, None)));
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::BearerToken("test-token".to_string());
+        let identity = iam_client
+            .get_system_identity("identity-1", &auth_credential)
+            .unwrap();
+        assert_eq!(identity.map(|i| i.id).as_deref(), expected);
+    }
+
+    fn is_delete_request(req: &Request<Vec<u8>>, id: &str) -> bool {
+        let body: Value = serde_json::from_slice(req.body()).unwrap();
+        body["query"] == SYSTEM_IDENTITY_DELETE && body["variables"] == json!({"id": id})
+    }
+
+    #[test]
+    fn test_delete_system_identity() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| is_delete_request(req, r#"identity-"1""#))
+            .returning(|_| {
+                json_response(json!({"data": {"systemIdentityDelete": {"id": "identity-\"1\""}}}))
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        assert!(
+            iam_client
+                .delete_system_identity(r#"identity-"1""#, &auth_credential)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_delete_matching_system_identities() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITIES
+                    && body["variables"]["filter"]
+                        == json!({
+                            "organizationId": {"eq": "org-123"},
+                            "name": {"contains": "name-"},
+                            "createdAt": {"lt": "2026-06-01T00:00:00Z"},
+                        })
+            })
+            .returning(|_| {
+                let mut response = identities_response(&["1", "2", "3"], None);
+                let items =
+                    &mut response["data"]["customerAdministration"]["systemIdentities"]["items"];
+                // Identity 2 was used, so it is kept.
+                items[1]["lastUsedAt"] = json!("2026-04-01T00:00:00Z");
+                json_response(response)
+            });
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| is_delete_request(req, "1"))
+            .returning(|_| json_response(json!({"data": {"systemIdentityDelete": {"id": "1"}}})));
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| is_delete_request(req, "3"))
+            .returning(|_| {
+                json_response(json!({"errors": [{"message": "not allowed"}], "data": null}))
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let filter = DeletionFilter::default()
+            .with_name_prefix("name-")
+            .with_created_before("2026-06-01T00:00:00Z".parse().unwrap())
+            .with_never_used();
+        let report = iam_client
+            .delete_matching_system_identities(&filter, &auth_credential)
+            .unwrap();
+
+        let deleted = report
+            .deleted
+            .iter()
+            .map(|i| i.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(deleted, ["1"]);
+        assert_matches!(report.failed.as_slice(), [failed] => {
+            assert_eq!(failed.identity.id, "3");
+            assert!(failed.error.contains("not allowed"));
+        });
+    }
+
+    #[test]
+    fn test_rename_system_identity_without_tags() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                // Tags are neither declared nor selected when they are not changed.
+                body["query"] == SYSTEM_IDENTITY_UPDATE
+                    && body["variables"] == json!({"id": "identity-1", "name": "host-b"})
+            })
+            .returning(|_| {
+                json_response(json!({"data": {"systemIdentityUpdate": {
+                    "id": "identity-1",
+                    "name": "host-b",
+                    "clientId": "client-1",
+                    "organizationId": "org-123",
+                    "description": "edge host",
+                }}}))
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let update = SystemIdentityUpdate::default().with_name("host-b");
+        let identity = iam_client
+            .update_system_identity("identity-1", &update, &auth_credential)
+            .unwrap();
+
+        assert_eq!(identity.name.as_deref(), Some("host-b"));
+        assert_eq!(identity.description.as_deref(), Some("edge host"));
+        assert!(identity.tags.is_empty());
+    }
+
+    #[test]
+    fn test_update_system_identity() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                // Unchanged fields are left out of the variables.
+                body["query"] == SYSTEM_IDENTITY_UPDATE_WITH_TAGS
+                    && body["variables"]
+                        == json!({
+                            "id": "identity-1",
+                            "name": "host \"b\"",
+                            "tags": [{"key": "env", "value": "prod"}],
+                        })
+            })
+            .returning(|_| {
+                json_response(json!({"data": {"systemIdentityUpdate": {
+                    "id": "identity-1",
+                    "name": "host \"b\"",
+                    "clientId": "client-1",
+                    "organizationId": "org-123",
+                    "description": "moved to prod",
+                    "tags": [{"key": "env", "value": "prod"}],
+                }}}))
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let update = SystemIdentityUpdate::default()
+            .with_name("host \"b\"")
+            .with_tags(vec![SystemIdentityTag::new("env", "prod")]);
+        let identity = iam_client
+            .update_system_identity("identity-1", &update, &auth_credential)
+            .unwrap();
+
+        assert_eq!(identity.name.as_deref(), Some("host \"b\""));
+        assert_eq!(identity.description.as_deref(), Some("moved to prod"));
+        assert_eq!(identity.tags, [SystemIdentityTag::new("env", "prod")]);
+    }
+
+    #[test]
+    fn test_update_system_identity_without_changes() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client.expect_send().never();
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        assert_matches!(
+            iam_client.update_system_identity(
+                "identity-1",
+                &SystemIdentityUpdate::default(),
+                &auth_credential
+            ),
+            Err(IAMClientError::Encoder(_))
+        );
+    }
+
+    #[test]
+    fn test_group_not_found_error_names_the_group() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client.expect_send().once().returning(|_| {
+            json_response(json!({"data": {"customerAdministration": {
+                "systemIdentityGroups": {"items": []}
+            }}}))
+        });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let err = iam_client
+            .find_system_identity_group_id_by_name("hosts", &auth_credential)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("system identity group `hosts` not found"));
+        assert!(!err.contains(NR_CONTROL_GROUP_NAME));
+    }
+
+    #[test]
+    fn test_create_system_identity_group() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITY_GROUP_CREATE
+                    && body["variables"]
+                        == json!({"organizationId": "org-123", "name": "hosts \"eu\""})
+            })
+            .returning(|_| {
+                json_response(json!({"data": {"systemIdentityGroupCreate": {
+                    "id": "group-1",
+                    "name": "hosts \"eu\"",
+                    "organizationId": "org-123",
+                    "description": null,
+                }}}))
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let group = iam_client
+            .create_system_identity_group("hosts \"eu\"", None, &auth_credential)
+            .unwrap();
+
+        assert_eq!(
+            group,
+            SystemIdentityGroup {
+                id: "group-1".to_string(),
+                name: "hosts \"eu\"".to_string(),
+                organization_id: "org-123".to_string(),
+                description: None,
+            }
+        );
+    }
+
+    fn groups_response(ids: &[&str], next_cursor: Option<&str>) -> Value {
+        let items = ids
+            .iter()
+            .map(|id| json!({"id": id, "name": format!("name-{id}"), "organizationId": "org-123"}))
+            .collect::<Vec<_>>();
+        json!({"data": {"customerAdministration": {"systemIdentityGroups": {
+            "items": items,
+            "nextCursor": next_cursor,
+        }}}})
+    }
+
+    #[test]
+    fn test_list_system_identity_groups_follows_cursors() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITY_GROUPS
+                    && body["variables"]
+                        == json!({"filter": {
+                            "organizationId": {"eq": "org-123"},
+                            "name": {"contains": "host"},
+                        }})
+            })
+            .returning(|_| json_response(groups_response(&["1", "2"], Some("cursor-2"))));
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["variables"]["cursor"] == "cursor-2"
+            })
+            .returning(|_| json_response(groups_response(&["3"], None)));
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let groups = iam_client
+            .list_system_identity_groups(Some("host"), &auth_credential)
+            .unwrap();
+
+        let ids = groups.iter().map(|g| g.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_delete_system_identity_group() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITY_GROUP_DELETE
+                    && body["variables"] == json!({"id": "group-1"})
+            })
+            .returning(|_| {
+                json_response(json!({"data": {"systemIdentityGroupDelete": {"id": "group-1"}}}))
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        assert!(
+            iam_client
+                .delete_system_identity_group("group-1", &auth_credential)
+                .is_ok()
+        );
+    }
+
+    #[rstest]
+    #[case::add(SYSTEM_IDENTITY_ADD_TO_GROUPS, "systemIdentityAddToGroups")]
+    #[case::remove(SYSTEM_IDENTITY_REMOVE_FROM_GROUPS, "systemIdentityRemoveFromGroups")]
+    fn test_change_group_membership(#[case] mutation: &'static str, #[case] field: &'static str) {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(move |req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == mutation
+                    && body["variables"]
+                        == json!({
+                            "systemIdentityIds": ["identity-1", "identity-2"],
+                            "systemIdentityGroupIds": ["group-1", "group-2"],
+                        })
+            })
+            .returning(move |_| {
+                json_response(json!({"data": {field: {"systemIdentityGroups": [
+                    {"id": "group-1"}, {"id": "group-2"}
+                ]}}}))
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let identity_ids = ["identity-1", "identity-2"];
+        let group_ids = ["group-1", "group-2"];
+        let result = match mutation {
+            SYSTEM_IDENTITY_ADD_TO_GROUPS => {
+                iam_client.add_identities_to_groups(&identity_ids, &group_ids, &auth_credential)
+            }
+            _ => iam_client.remove_identities_from_groups(
+                &identity_ids,
+                &group_ids,
+                &auth_credential,
+            ),
+        };
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_change_group_membership_without_groups() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client.expect_send().never();
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        assert_matches!(
+            iam_client.add_identities_to_groups(&["identity-1"], &[], &auth_credential),
+            Err(IAMClientError::Encoder(_))
+        );
+    }
+
+    #[test]
+    fn test_list_system_identity_group_members() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITIES
+                    && body["variables"]
+                        == json!({"filter": {
+                            "organizationId": {"eq": "org-123"},
+                            "systemIdentityGroupId": {"eq": "group-1"},
+                        }})
+            })
+            .returning(|_| json_response(identities_response(&["1", "2"], None)));
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let members = iam_client
+            .list_system_identity_group_members("group-1", &auth_credential)
+            .unwrap();
+
+        let ids = members.iter().map(|i| i.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2"]);
+    }
+
+    #[test]
+    fn test_find_system_identities_by_exact_name() {
+        let mut mock_http_client = MockHttpClient::default();
+        mock_http_client
+            .expect_send()
+            .once()
+            .withf(|req| {
+                let body: Value = serde_json::from_slice(req.body()).unwrap();
+                body["query"] == SYSTEM_IDENTITIES_WITH_PUBLIC_KEYS
+                    && body["variables"]
+                        == json!({"filter": {
+                            "organizationId": {"eq": "org-123"},
+                            "name": {"contains": "name-1"},
+                        }})
+            })
+            .returning(|_| {
+                let mut response = identities_response(&["1", "11", "111"], None);
+                let items =
+                    &mut response["data"]["customerAdministration"]["systemIdentities"]["items"];
+                items[0]["credentialType"] = json!("PUBLIC_KEY");
+                items[0]["publicKeys"] = json!([{"publicKey": "cHVibGljLWtleQ=="}]);
+                items[1]["credentialType"] = json!("CLIENT_SECRET");
+                // An L2 identity without registered keys, e.g. during a key rotation.
+                items[2]["name"] = json!("name-1");
+                items[2]["credentialType"] = json!("PUBLIC_KEY");
+                json_response(response)
+            });
+
+        let iam_client = HttpIAMClient::new(mock_http_client, management_metadata());
+        let auth_credential = IAMAuthCredential::ApiKey("NRAK-XXXX".to_string());
+        let identities = iam_client
+            .find_system_identities_by_name("name-1", &auth_credential)
+            .unwrap();
+
+        // `name-11` contains the name, but is not named so.
+        assert_matches!(identities.as_slice(), [existing, without_keys] => {
+            assert_eq!(existing.identity.id, "1");
+            assert_eq!(existing.public_keys, [b"public-key".to_vec()]);
+            assert!(existing.is_l2());
+            assert_eq!(without_keys.identity.id, "111");
+            assert!(without_keys.public_keys.is_empty());
+            assert!(without_keys.is_l2());
+        });
+    }
 }
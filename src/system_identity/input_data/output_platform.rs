@@ -1,5 +1,8 @@
 use std::path::PathBuf;
 
+#[cfg(feature = "pkcs11")]
+use crate::key::pkcs11::Pkcs11KeyConfig;
+
 /// This type represents the output platform for the authentication credentials created with
 /// the System Identity.
 ///
@@ -8,5 +11,8 @@ use std::path::PathBuf;
 #[derive(Debug, Clone, PartialEq)]
 pub enum OutputPlatform {
     LocalPrivateKeyPath(PathBuf),
+    /// The key pair is generated inside a PKCS#11 token and the private key is never exported.
+    #[cfg(feature = "pkcs11")]
+    Pkcs11Token(Pkcs11KeyConfig),
     // Vault(VaultConfig), // TODO: Vault
 }
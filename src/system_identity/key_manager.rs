@@ -0,0 +1,50 @@
+use super::iam_client::http::IAMAuthCredential;
+
+/// Interface describing being able to manage the public keys registered for an existing
+/// L2 System Identity.
+pub trait L2KeyManager {
+    type Error: std::error::Error;
+    /// Registers an additional public key for the identity. Tokens can be obtained with any of
+    /// the registered keys.
+    fn add_l2_public_key(
+        &self,
+        auth_credentials: &IAMAuthCredential,
+        identity_id: &str,
+        pub_key: &[u8],
+    ) -> Result<(), Self::Error>;
+
+    /// Removes a public key from the identity.
+    fn remove_l2_public_key(
+        &self,
+        auth_credentials: &IAMAuthCredential,
+        identity_id: &str,
+        pub_key: &[u8],
+    ) -> Result<(), Self::Error>;
+}
+
+#[cfg(test)]
+pub mod tests {
+    use mockall::mock;
+
+    use super::*;
+    use crate::system_identity::identity_creator::tests::MockIAMClientError;
+
+    mock! {
+        pub L2KeyManager {}
+        impl L2KeyManager for L2KeyManager {
+            type Error = MockIAMClientError;
+            fn add_l2_public_key(
+                &self,
+                auth_credentials: &IAMAuthCredential,
+                identity_id: &str,
+                pub_key: &[u8]
+            ) -> Result<(), MockIAMClientError>;
+            fn remove_l2_public_key(
+                &self,
+                auth_credentials: &IAMAuthCredential,
+                identity_id: &str,
+                pub_key: &[u8]
+            ) -> Result<(), MockIAMClientError>;
+        }
+    }
+}
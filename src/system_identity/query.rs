@@ -0,0 +1,165 @@
+//! Read model of existing system identities, as returned when listing them from the IAM API.
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::key::generation::PublicKeyPem;
+
+/// Existing system identity. Credentials are never returned when reading identities back.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SystemIdentityInfo {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub client_id: String,
+    pub organization_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Last time a token was obtained with the identity, unset if it was never used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<SystemIdentityTag>,
+}
+
+/// Key-value tag of a system identity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemIdentityTag {
+    pub key: String,
+    pub value: String,
+}
+
+impl SystemIdentityTag {
+    pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl FromStr for SystemIdentityTag {
+    type Err = String;
+
+    /// Parses a `key=value` tag.
+    fn from_str(tag: &str) -> Result<Self, Self::Err> {
+        match tag.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => Ok(Self::new(key.trim(), value)),
+            _ => Err(format!("`{tag}` is not a key=value tag")),
+        }
+    }
+}
+
+/// Credential a system identity authenticates with, as reported by the IAM API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SystemIdentityCredentialType {
+    /// Client secret (L1 identity).
+    ClientSecret,
+    /// Private key whose public keys are registered (L2 identity).
+    PublicKey,
+}
+
+/// Existing system identity along with its credential type and the public keys registered for
+/// it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExistingSystemIdentity {
+    pub identity: SystemIdentityInfo,
+    pub credential_type: SystemIdentityCredentialType,
+    /// Registered public keys in PEM format. An L2 identity may have none, e.g. while its key is
+    /// being rotated.
+    pub public_keys: Vec<PublicKeyPem>,
+}
+
+impl ExistingSystemIdentity {
+    /// Whether the identity authenticates with a private key (L2) rather than a client secret.
+    pub fn is_l2(&self) -> bool {
+        self.credential_type == SystemIdentityCredentialType::PublicKey
+    }
+}
+
+/// Criteria the listed system identities must match. Unset criteria match every identity.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemIdentityFilter {
+    /// Text contained in the identity name.
+    pub name: Option<String>,
+    /// Client ID of the identity.
+    pub client_id: Option<String>,
+    /// Only identities created after this time.
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only identities created before this time.
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only members of the system identity group with this id.
+    pub group_id: Option<String>,
+}
+
+impl SystemIdentityFilter {
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+
+    pub fn with_client_id(self, client_id: impl Into<String>) -> Self {
+        Self {
+            client_id: Some(client_id.into()),
+            ..self
+        }
+    }
+
+    pub fn with_created_after(self, created_after: DateTime<Utc>) -> Self {
+        Self {
+            created_after: Some(created_after),
+            ..self
+        }
+    }
+
+    pub fn with_created_before(self, created_before: DateTime<Utc>) -> Self {
+        Self {
+            created_before: Some(created_before),
+            ..self
+        }
+    }
+
+    pub fn with_group_id(self, group_id: impl Into<String>) -> Self {
+        Self {
+            group_id: Some(group_id.into()),
+            ..self
+        }
+    }
+}
+
+/// A page of listed system identities.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SystemIdentityPage {
+    pub items: Vec<SystemIdentityInfo>,
+    /// Cursor to request the next page with, unset on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("env=prod", SystemIdentityTag::new("env", "prod"))]
+    #[case(" team =a=b", SystemIdentityTag::new("team", "a=b"))]
+    #[case("empty=", SystemIdentityTag::new("empty", ""))]
+    fn parse_tag(#[case] tag: &str, #[case] expected: SystemIdentityTag) {
+        assert_eq!(tag.parse::<SystemIdentityTag>().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::no_separator("env")]
+    #[case::no_key("=prod")]
+    fn invalid_tag(#[case] tag: &str) {
+        assert!(tag.parse::<SystemIdentityTag>().is_err());
+    }
+}
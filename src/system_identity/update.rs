@@ -0,0 +1,39 @@
+//! Changes to the name and metadata of an existing system identity.
+use super::query::SystemIdentityTag;
+
+/// Fields of a system identity to change. Unset fields are left unchanged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemIdentityUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Tags replacing the current ones, if supported by the organization.
+    pub tags: Option<Vec<SystemIdentityTag>>,
+}
+
+impl SystemIdentityUpdate {
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+
+    pub fn with_description(self, description: impl Into<String>) -> Self {
+        Self {
+            description: Some(description.into()),
+            ..self
+        }
+    }
+
+    pub fn with_tags(self, tags: Vec<SystemIdentityTag>) -> Self {
+        Self {
+            tags: Some(tags),
+            ..self
+        }
+    }
+
+    /// Whether the update leaves every field unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.description.is_none() && self.tags.is_none()
+    }
+}
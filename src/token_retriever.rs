@@ -1,17 +1,21 @@
 use crate::authenticator::{Authenticator, GrantType, TokenRetrievalRequest};
+use crate::jwt::claims::ClaimsBuilder;
+use crate::jwt::encryption::JweEncrypter;
 use crate::jwt::signer::JwtSigner;
 use crate::system_identity::input_data::auth_method::ClientSecret;
 use crate::token::Token;
 use crate::token_retriever::credential::{
-    AuthCredentialBuilder, ClientSecretAuthBuilder, JwtSignerAuthBuilder,
+    AuthCredentialBuilder, ClientSecretAuthBuilder, JwtSignerAuthBuilder, SecretFileAuthBuilder,
 };
 use crate::{ClientID, TokenRetriever, TokenRetrieverError};
 
 use ::http::Uri;
+use assertion_pool::AssertionPool;
 use credential::DEFAULT_AUDIENCE;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use tracing::debug;
 
+pub mod assertion_pool;
 pub mod credential;
 
 #[derive(Debug)]
@@ -47,12 +51,23 @@ where
                 match self.refresh_token() {
                     Ok(token) => {
                         debug!("authorization token refreshed");
+                        self.credential.credential_accepted();
                         *cached_token = Some(token);
                         break;
                     }
                     Err(e) => {
                         debug!("error refreshing token: {e}");
 
+                        // Retrying with another key does not count as an attempt.
+                        if is_invalid_client(&e) {
+                            if self.credential.credential_rejected() {
+                                debug!("credential rejected, retrying with the next one");
+                                continue;
+                            }
+                        } else {
+                            self.credential.credential_failed();
+                        }
+
                         attempt += 1;
                         if self.should_retry_refresh(attempt, &e) {
                             debug!("retrying to refresh token");
@@ -85,16 +100,64 @@ where
     /// a JWT with the private key to retrieve the token.
     pub fn new_with_jwt_signer(client_id: ClientID, authenticator: A, jwt_signer: J) -> Self {
         let aud = Uri::try_from(DEFAULT_AUDIENCE).expect("constant valid url value");
+        Self::new_with_jwt_signer_config(
+            client_id,
+            authenticator,
+            jwt_signer,
+            ClaimsBuilder::new(aud),
+        )
+    }
+
+    /// Encrypts the signed client assertions to the server's key (nested JWT in a JWE), for
+    /// token endpoints that require it.
+    pub fn with_assertion_encryption(mut self, encrypter: JweEncrypter) -> Self {
+        self.credential.encrypter = Some(encrypter);
+        self
+    }
+
+    /// Creates a new `TokenRetrieverWithCache` that signs JWTs with the claims described by
+    /// `claims` (audience, lifetime, `iat`/`nbf` and private claims).
+    pub fn new_with_jwt_signer_config(
+        client_id: ClientID,
+        authenticator: A,
+        jwt_signer: J,
+        claims: ClaimsBuilder,
+    ) -> Self {
         Self {
             client_id,
             tokens: Mutex::new(None),
-            credential: JwtSignerAuthBuilder { aud, jwt_signer },
+            credential: JwtSignerAuthBuilder {
+                claims,
+                jwt_signer: Arc::new(jwt_signer),
+                pool: None,
+                encrypter: None,
+            },
             authenticator,
             retries: 0,
         }
     }
 }
 
+impl<A, J> TokenRetrieverWithCache<A, JwtSignerAuthBuilder<J>>
+where
+    A: Authenticator,
+    J: JwtSigner + Send + Sync + 'static,
+{
+    /// Keeps `size` client assertions pre-signed in a background thread, so that refreshing the
+    /// token does not wait for the signature. Assertions are signed inline whenever the pool is
+    /// empty.
+    pub fn with_assertion_pool(mut self, size: usize) -> Self {
+        let pool = AssertionPool::start(
+            self.credential.jwt_signer.clone(),
+            self.credential.claims.clone(),
+            self.client_id.clone(),
+            size,
+        );
+        self.credential.pool = Some(pool);
+        self
+    }
+}
+
 impl<A> TokenRetrieverWithCache<A, ClientSecretAuthBuilder>
 where
     A: Authenticator,
@@ -114,6 +177,27 @@ where
     }
 }
 
+impl<A> TokenRetrieverWithCache<A, SecretFileAuthBuilder>
+where
+    A: Authenticator,
+{
+    /// Creates a new `TokenRetrieverWithCache` that uses a client secret stored in a file,
+    /// picking up the new secret whenever the file is replaced.
+    pub fn new_with_secret_file(
+        client_id: ClientID,
+        authenticator: A,
+        secret: SecretFileAuthBuilder,
+    ) -> Self {
+        Self {
+            client_id,
+            tokens: Mutex::new(None),
+            credential: secret,
+            authenticator,
+            retries: 0,
+        }
+    }
+}
+
 impl<A, C> TokenRetrieverWithCache<A, C>
 where
     A: Authenticator,
@@ -147,9 +231,13 @@ where
     }
 }
 
+fn is_invalid_client(err: &TokenRetrieverError) -> bool {
+    matches!(err, TokenRetrieverError::AuthenticatorError(e) if e.is_invalid_client())
+}
+
 #[cfg(test)]
 pub mod test {
-    use std::{thread, time};
+    use std::{fs, thread, time};
 
     use chrono::{TimeDelta, Utc};
     use mockall::mock;
@@ -158,8 +246,15 @@ pub mod test {
     use crate::authenticator::test::MockAuthenticatorMock;
 
     use crate::authenticator::AuthCredential;
+    use crate::jwt::claims::DEFAULT_JWT_CLAIM_EXP;
+    use crate::jwt::encryption::JweEncrypter;
+    use crate::jwt::encryption::tests::{decrypt, rsa_server_key};
+    use crate::jwt::signer::fallback::FallbackSigner;
+    use crate::jwt::signer::local::test::RS256_PUBLIC_KEY;
+    use crate::jwt::signer::reloading::ReloadingPrivateKeySigner;
     use crate::jwt::signer::tests::MockJwtSigner;
-    use crate::token_retriever::credential::DEFAULT_JWT_CLAIM_EXP;
+    use crate::key::generation::{KeyType, generate_key_pair};
+    use crate::token_retriever::assertion_pool::tests::wait_for;
     use crate::{
         TokenRetriever, TokenRetrieverError,
         authenticator::{
@@ -169,8 +264,13 @@ pub mod test {
         jwt::signed::SignedJwt,
         token::{Token, TokenType},
     };
+    use assert_matches::assert_matches;
 
     use super::{DEFAULT_AUDIENCE, TokenRetrieverWithCache};
+    use crate::jwt::claims::ClaimsBuilder;
+    use http::Uri;
+    use jsonwebtoken::{Algorithm, decode_header};
+    use tempfile::tempdir;
 
     mock! {
         pub TokenRetriever {}
@@ -427,4 +527,410 @@ pub mod test {
 
         assert!(cache_miss_token.is_err());
     }
+
+    #[test]
+    fn jwt_signer_config_claims() {
+        let client_id = "client_id";
+        let audience = "https://staging.newrelic.com/";
+
+        let mut jwt_signer = MockJwtSigner::new();
+        jwt_signer
+            .expect_sign()
+            .once()
+            .withf(move |claims| {
+                let now = Utc::now().timestamp() as u64;
+                claims.iss == client_id
+                    && claims.aud == audience
+                    && claims.exp == now + 60
+                    && claims.iat == Some(now - 10)
+                    && claims.nbf.is_none()
+                    && claims.private_claims["tenant"] == "edge"
+            })
+            .returning(move |_| {
+                Ok(SignedJwt {
+                    value: "client_assertion".into(),
+                })
+            });
+
+        let mut authenticator = MockAuthenticatorMock::default();
+        authenticator.expect_authenticate().once().returning(|_| {
+            Ok(TokenRetrievalResponse {
+                access_token: "fakeToken".into(),
+                expires_in: 10,
+                token_type: "Bearer".into(),
+            })
+        });
+
+        let claims = ClaimsBuilder::new(Uri::from_static(audience))
+            .with_lifetime(TimeDelta::seconds(60))
+            .with_issued_at(true)
+            .with_clock_skew(TimeDelta::seconds(10))
+            .with_private_claim("tenant", "edge".into());
+        let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer_config(
+            client_id.into(),
+            authenticator,
+            jwt_signer,
+            claims,
+        );
+
+        assert_eq!(
+            token_retriever.retrieve().unwrap().access_token(),
+            "fakeToken"
+        );
+    }
+
+    #[test]
+    fn assertion_pool_pre_signs_assertions() {
+        let client_id = "client_id";
+        let mut jwt_signer = MockJwtSigner::new();
+        jwt_signer.expect_sign().returning(move |_| {
+            // Only the pool thread signs, as the test waits for the pool to be filled
+            Ok(SignedJwt {
+                value: thread::current().name().unwrap_or("pool").into(),
+            })
+        });
+
+        let mut authenticator = MockAuthenticatorMock::default();
+        authenticator
+            .expect_authenticate()
+            .once()
+            .withf(|request| {
+                request.credential
+                    == AuthCredential::ClientAssertion {
+                        client_assertion_type: ClientAssertionType::JwtBearer,
+                        client_assertion: "pool".into(),
+                    }
+            })
+            .returning(|_| {
+                Ok(TokenRetrievalResponse {
+                    access_token: "fakeToken".into(),
+                    expires_in: 10,
+                    token_type: "Bearer".into(),
+                })
+            });
+
+        let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer(
+            client_id.into(),
+            authenticator,
+            jwt_signer,
+        )
+        .with_assertion_pool(1);
+        let pool = token_retriever.credential.pool.as_ref().unwrap();
+        wait_for(pool, 1);
+
+        assert_eq!(
+            token_retriever.retrieve().unwrap().access_token(),
+            "fakeToken"
+        );
+    }
+
+    #[test]
+    fn assertion_pool_discards_assertions_signed_before_key_reload() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("key");
+        let write_key = |key_type| {
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, generate_key_pair(&key_type).unwrap().private_key).unwrap();
+            fs::rename(&tmp_path, &path).unwrap();
+        };
+        write_key(KeyType::EcdsaP256);
+        let jwt_signer = ReloadingPrivateKeySigner::from_file(&path)
+            .unwrap()
+            .with_check_interval(time::Duration::ZERO);
+
+        let mut authenticator = MockAuthenticatorMock::default();
+        authenticator
+            .expect_authenticate()
+            .once()
+            .withf(|request| match &request.credential {
+                AuthCredential::ClientAssertion {
+                    client_assertion, ..
+                } => decode_header(client_assertion).unwrap().alg == Algorithm::EdDSA,
+                _ => false,
+            })
+            .returning(|_| {
+                Ok(TokenRetrievalResponse {
+                    access_token: "fakeToken".into(),
+                    expires_in: 10,
+                    token_type: "Bearer".into(),
+                })
+            });
+
+        let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer(
+            "client_id".into(),
+            authenticator,
+            jwt_signer,
+        )
+        .with_assertion_pool(1);
+        wait_for(token_retriever.credential.pool.as_ref().unwrap(), 1);
+
+        // The pooled assertion was signed with the previous key
+        write_key(KeyType::Ed25519);
+        assert_eq!(
+            token_retriever.retrieve().unwrap().access_token(),
+            "fakeToken"
+        );
+    }
+
+    #[test]
+    fn encrypted_assertion() {
+        let mut jwt_signer = MockJwtSigner::new();
+        jwt_signer.expect_sign().once().returning(|_| {
+            Ok(SignedJwt {
+                value: "client_assertion".into(),
+            })
+        });
+
+        let mut authenticator = MockAuthenticatorMock::default();
+        authenticator
+            .expect_authenticate()
+            .once()
+            .withf(|request| {
+                let AuthCredential::ClientAssertion {
+                    client_assertion, ..
+                } = &request.credential
+                else {
+                    return false;
+                };
+                let (header, payload) = decrypt(client_assertion, &rsa_server_key());
+                header["cty"] == "JWT" && payload == "client_assertion"
+            })
+            .returning(|_| {
+                Ok(TokenRetrievalResponse {
+                    access_token: "fakeToken".into(),
+                    expires_in: 10,
+                    token_type: "Bearer".into(),
+                })
+            });
+
+        let encrypter = JweEncrypter::from_public_key_pem(RS256_PUBLIC_KEY.as_bytes()).unwrap();
+        let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer(
+            "client_id".into(),
+            authenticator,
+            jwt_signer,
+        )
+        .with_assertion_encryption(encrypter);
+
+        assert_eq!(
+            token_retriever.retrieve().unwrap().access_token(),
+            "fakeToken"
+        );
+    }
+
+    #[test]
+    fn fallback_signer_retries_rejected_assertion() {
+        let signer = |value: &'static str, times: usize| {
+            let mut jwt_signer = MockJwtSigner::new();
+            jwt_signer
+                .expect_sign()
+                .times(times)
+                .returning(move |_| Ok(SignedJwt::from(value.to_string())));
+            jwt_signer
+        };
+        // The old key is only tried once, the new one is used first after being accepted.
+        let jwt_signer = FallbackSigner::new(signer("old", 1)).with_fallback(signer("new", 2));
+
+        let mut authenticator = MockAuthenticatorMock::default();
+        authenticator
+            .expect_authenticate()
+            .times(3)
+            .returning(|request| match request.credential {
+                AuthCredential::ClientAssertion {
+                    client_assertion, ..
+                } if client_assertion == "new" => Ok(TokenRetrievalResponse {
+                    access_token: "fakeToken".into(),
+                    expires_in: 0,
+                    token_type: "Bearer".into(),
+                }),
+                _ => Err(AuthenticateError::HttpResponseError(
+                    401,
+                    r#"{"error":"invalid_client"}"#.into(),
+                )),
+            });
+
+        let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer(
+            "client_id".into(),
+            authenticator,
+            jwt_signer,
+        );
+
+        for _ in 0..2 {
+            assert_eq!(
+                token_retriever.retrieve().unwrap().access_token(),
+                "fakeToken"
+            );
+            thread::sleep(time::Duration::from_millis(5));
+        }
+        assert_eq!(token_retriever.credential.jwt_signer.current(), 1);
+    }
+
+    #[test]
+    fn fallback_signer_starts_over_after_failed_refresh() {
+        let signer = |value: &'static str, times: usize| {
+            let mut jwt_signer = MockJwtSigner::new();
+            jwt_signer
+                .expect_sign()
+                .times(times)
+                .returning(move |_| Ok(SignedJwt::from(value.to_string())));
+            jwt_signer
+        };
+        // The old key is tried again on the refresh after the transport error.
+        let jwt_signer = FallbackSigner::new(signer("old", 2)).with_fallback(signer("new", 1));
+
+        let mut authenticator = MockAuthenticatorMock::default();
+        let mut sequence = mockall::Sequence::new();
+        authenticator
+            .expect_authenticate()
+            .once()
+            .in_sequence(&mut sequence)
+            .returning(|_| {
+                Err(AuthenticateError::HttpResponseError(
+                    401,
+                    r#"{"error":"invalid_client"}"#.into(),
+                ))
+            });
+        authenticator
+            .expect_authenticate()
+            .once()
+            .in_sequence(&mut sequence)
+            .returning(|_| Err(AuthenticateError::HttpTransportError("timeout".into())));
+        authenticator
+            .expect_authenticate()
+            .once()
+            .in_sequence(&mut sequence)
+            .returning(|request| {
+                assert_matches!(request.credential, AuthCredential::ClientAssertion {
+                    client_assertion, ..
+                } if client_assertion == "old");
+                Ok(TokenRetrievalResponse {
+                    access_token: "fakeToken".into(),
+                    expires_in: 10,
+                    token_type: "Bearer".into(),
+                })
+            });
+
+        let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer(
+            "client_id".into(),
+            authenticator,
+            jwt_signer,
+        );
+
+        assert_matches!(
+            token_retriever.retrieve(),
+            Err(TokenRetrieverError::AuthenticatorError(
+                AuthenticateError::HttpTransportError(_)
+            ))
+        );
+        assert_eq!(token_retriever.credential.jwt_signer.current(), 0);
+        assert_eq!(
+            token_retriever.retrieve().unwrap().access_token(),
+            "fakeToken"
+        );
+    }
+
+    #[test]
+    fn assertion_pool_discards_fallback_assertions_after_every_key_rejected() {
+        let signer = |value: &'static str| {
+            let mut jwt_signer = MockJwtSigner::new();
+            jwt_signer
+                .expect_sign()
+                .returning(move |_| Ok(SignedJwt::from(value.to_string())));
+            jwt_signer
+        };
+        let jwt_signer = FallbackSigner::new(signer("old")).with_fallback(signer("new"));
+
+        let assertion = |request: &TokenRetrievalRequest| match &request.credential {
+            AuthCredential::ClientAssertion {
+                client_assertion, ..
+            } => client_assertion.clone(),
+            _ => panic!("unexpected credential"),
+        };
+        let rejected = || {
+            Err(AuthenticateError::HttpResponseError(
+                401,
+                r#"{"error":"invalid_client"}"#.into(),
+            ))
+        };
+        let mut authenticator = MockAuthenticatorMock::default();
+        let mut sequence = Sequence::new();
+        authenticator
+            .expect_authenticate()
+            .once()
+            .in_sequence(&mut sequence)
+            .withf(move |request| assertion(request) == "old")
+            .returning(move |_| rejected());
+        authenticator
+            .expect_authenticate()
+            .once()
+            .in_sequence(&mut sequence)
+            .withf(move |request| assertion(request) == "new")
+            .returning(move |_| {
+                // Let the pool pre-sign with the new key before every key is rejected.
+                thread::sleep(time::Duration::from_millis(100));
+                rejected()
+            });
+        authenticator
+            .expect_authenticate()
+            .once()
+            .in_sequence(&mut sequence)
+            .withf(move |request| assertion(request) == "old")
+            .returning(|_| {
+                Ok(TokenRetrievalResponse {
+                    access_token: "fakeToken".into(),
+                    expires_in: 10,
+                    token_type: "Bearer".into(),
+                })
+            });
+
+        let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer(
+            "client_id".into(),
+            authenticator,
+            jwt_signer,
+        )
+        .with_assertion_pool(1);
+        wait_for(token_retriever.credential.pool.as_ref().unwrap(), 1);
+
+        assert_matches!(
+            token_retriever.retrieve(),
+            Err(TokenRetrieverError::AuthenticatorError(e)) if e.is_invalid_client()
+        );
+        // The assertion pre-signed with the new key is not sent on the next refresh.
+        assert_eq!(
+            token_retriever.retrieve().unwrap().access_token(),
+            "fakeToken"
+        );
+        assert_eq!(token_retriever.credential.jwt_signer.current(), 0);
+    }
+
+    #[test]
+    fn fallback_signer_every_key_rejected() {
+        let signer = |value: &'static str| {
+            let mut jwt_signer = MockJwtSigner::new();
+            jwt_signer
+                .expect_sign()
+                .once()
+                .returning(move |_| Ok(SignedJwt::from(value.to_string())));
+            jwt_signer
+        };
+        let jwt_signer = FallbackSigner::new(signer("old")).with_fallback(signer("new"));
+
+        let mut authenticator = MockAuthenticatorMock::default();
+        authenticator.expect_authenticate().times(2).returning(|_| {
+            Err(AuthenticateError::HttpResponseError(
+                401,
+                r#"{"error":"invalid_client"}"#.into(),
+            ))
+        });
+
+        let token_retriever = TokenRetrieverWithCache::new_with_jwt_signer(
+            "client_id".into(),
+            authenticator,
+            jwt_signer,
+        );
+
+        assert_matches!(
+            token_retriever.retrieve(),
+            Err(TokenRetrieverError::AuthenticatorError(e)) if e.is_invalid_client()
+        );
+    }
 }
@@ -0,0 +1,350 @@
+//! Pool of pre-signed client assertions.
+//! Signing with large RSA keys can take a noticeable time on slow CPUs. The pool signs a small
+//! number of assertions ahead of time in a background thread, so refreshing a token only needs
+//! to take one of them.
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::debug;
+
+use crate::ClientID;
+use crate::jwt::{claims::ClaimsBuilder, signed::SignedJwt, signer::JwtSigner};
+use crate::sync::lock_recovering_poison;
+
+/// How often the filler thread checks for expiring assertions when the pool is full.
+const REFILL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the filler thread waits before retrying after a signing error.
+const SIGNING_ERROR_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct PreSignedAssertion {
+    jwt: SignedJwt,
+    /// Expiration of the assertion (as UTC timestamp).
+    exp: u64,
+    /// Key generation of the signer when the assertion was signed.
+    key_generation: u64,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    assertions: VecDeque<PreSignedAssertion>,
+    stopped: bool,
+    /// Incremented when the pool is cleared, so assertions signed meanwhile are discarded.
+    generation: u64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<PoolState>,
+    condvar: Condvar,
+    size: usize,
+    /// Assertions expiring sooner than this many seconds are discarded.
+    min_validity: u64,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        // Assertions are signed without holding the lock, so the critical sections only run
+        // queue operations and flag updates, which a panic cannot leave half-applied.
+        lock_recovering_poison(&self.state)
+    }
+
+    fn evict_expiring(&self, state: &mut PoolState) {
+        let now = u64::try_from(Utc::now().timestamp()).unwrap_or_default();
+        state
+            .assertions
+            .retain(|assertion| assertion.exp >= now.saturating_add(self.min_validity));
+    }
+}
+
+/// Pre-signs client assertions in the background. Each assertion gets its own `jti`, and is
+/// discarded once less than half of its lifetime is left or once the signer switches to another
+/// key (see [`JwtSigner::key_generation`]). The background thread is stopped when the pool is
+/// dropped.
+#[derive(Debug)]
+pub struct AssertionPool {
+    client_id: ClientID,
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AssertionPool {
+    /// Starts filling a pool of `size` assertions for `client_id`.
+    pub fn start<J>(
+        jwt_signer: Arc<J>,
+        claims: ClaimsBuilder,
+        client_id: ClientID,
+        size: usize,
+    ) -> Self
+    where
+        J: JwtSigner + Send + Sync + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(PoolState::default()),
+            condvar: Condvar::new(),
+            size,
+            min_validity: u64::try_from(claims.lifetime().num_seconds() / 2).unwrap_or_default(),
+        });
+
+        let handle = {
+            let shared = shared.clone();
+            let client_id = client_id.clone();
+            thread::spawn(move || fill(&shared, jwt_signer.as_ref(), &claims, &client_id))
+        };
+
+        Self {
+            client_id,
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Takes a pre-signed assertion for `client_id`, if any is ready. Assertions signed with a
+    /// key generation other than `key_generation` are discarded.
+    pub fn take(&self, client_id: &str, key_generation: u64) -> Option<SignedJwt> {
+        if client_id != self.client_id {
+            return None;
+        }
+        let mut state = self.shared.lock();
+        self.shared.evict_expiring(&mut state);
+        let signed_assertions = state.assertions.len();
+        state
+            .assertions
+            .retain(|assertion| assertion.key_generation == key_generation);
+        if state.assertions.len() < signed_assertions {
+            debug!("discarding client assertions signed with a previous key");
+        }
+        let assertion = state.assertions.pop_front();
+        // Wake the filler up to replace it.
+        self.shared.condvar.notify_all();
+        assertion.map(|assertion| assertion.jwt)
+    }
+
+    /// Discards every pre-signed assertion, including the one being signed.
+    pub fn clear(&self) {
+        let mut state = self.shared.lock();
+        state.assertions.clear();
+        state.generation += 1;
+        self.shared.condvar.notify_all();
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.shared.lock().assertions.len()
+    }
+}
+
+impl Drop for AssertionPool {
+    fn drop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.condvar.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Body of the filler thread: keeps the pool full until it is stopped.
+fn fill<J: JwtSigner>(shared: &Shared, jwt_signer: &J, claims: &ClaimsBuilder, client_id: &str) {
+    loop {
+        let generation = {
+            let mut state = shared.lock();
+            loop {
+                if state.stopped {
+                    return;
+                }
+                shared.evict_expiring(&mut state);
+                if state.assertions.len() < shared.size {
+                    break;
+                }
+                state = shared
+                    .condvar
+                    .wait_timeout(state, REFILL_CHECK_INTERVAL)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+            state.generation
+        };
+
+        // Read before signing, so an assertion signed while the key changes is discarded.
+        let key_generation = jwt_signer.key_generation();
+        // Sign without holding the lock, so takers are never blocked by the signature.
+        let signed = claims
+            .build(client_id.to_owned())
+            .map_err(|e| e.to_string())
+            .and_then(|claims| {
+                let exp = claims.exp;
+                jwt_signer
+                    .sign(claims)
+                    .map(|jwt| PreSignedAssertion {
+                        jwt,
+                        exp,
+                        key_generation,
+                    })
+                    .map_err(|e| e.to_string())
+            });
+
+        let mut state = shared.lock();
+        match signed {
+            Ok(assertion) if state.generation == generation => {
+                state.assertions.push_back(assertion)
+            }
+            Ok(_) => debug!("discarding client assertion signed before the pool was cleared"),
+            Err(e) => {
+                debug!("error pre-signing client assertion: {e}");
+                // Wait before retrying, unless the pool is stopped meanwhile.
+                let _ = shared
+                    .condvar
+                    .wait_timeout_while(state, SIGNING_ERROR_BACKOFF, |state| !state.stopped);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashSet;
+    use std::time::Instant;
+
+    use chrono::TimeDelta;
+    use http::Uri;
+
+    use super::*;
+    use crate::jwt::error::JwtEncoderError;
+    use crate::jwt::signer::tests::MockJwtSigner;
+
+    fn claims_builder() -> ClaimsBuilder {
+        ClaimsBuilder::new(Uri::from_static("https://www.newrelic.com/"))
+    }
+
+    pub(crate) fn wait_for(pool: &AssertionPool, len: usize) {
+        let start = Instant::now();
+        while pool.len() != len {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "pool not filled in time"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn pool_is_filled_and_refilled() {
+        let mut jwt_signer = MockJwtSigner::new();
+        jwt_signer.expect_sign().returning(|claims| {
+            Ok(SignedJwt {
+                value: claims.jti.to_string(),
+            })
+        });
+
+        let pool = AssertionPool::start(
+            Arc::new(jwt_signer),
+            claims_builder(),
+            "client-id".to_string(),
+            3,
+        );
+        wait_for(&pool, 3);
+
+        let assertions = (0..3)
+            .map(|_| pool.take("client-id", 0).unwrap().value)
+            .collect::<HashSet<_>>();
+        assert_eq!(assertions.len(), 3, "each assertion has its own jti");
+
+        wait_for(&pool, 3);
+        assert!(pool.take("other-client", 0).is_none());
+    }
+
+    #[test]
+    fn cleared_pool_is_refilled() {
+        let mut jwt_signer = MockJwtSigner::new();
+        jwt_signer.expect_sign().returning(|claims| {
+            Ok(SignedJwt {
+                value: claims.jti.to_string(),
+            })
+        });
+        let pool = AssertionPool::start(
+            Arc::new(jwt_signer),
+            claims_builder(),
+            "client-id".to_string(),
+            2,
+        );
+        wait_for(&pool, 2);
+        let cleared = pool.shared.lock().assertions[0].jwt.value.clone();
+
+        pool.clear();
+        wait_for(&pool, 2);
+        let refilled = (0..2)
+            .map(|_| pool.take("client-id", 0).unwrap().value)
+            .collect::<HashSet<_>>();
+        assert!(!refilled.contains(&cleared));
+    }
+
+    #[test]
+    fn assertions_signed_with_previous_key_are_discarded() {
+        let mut jwt_signer = MockJwtSigner::new();
+        jwt_signer.expect_sign().returning(|claims| {
+            Ok(SignedJwt {
+                value: claims.jti.to_string(),
+            })
+        });
+        let pool = AssertionPool::start(
+            Arc::new(jwt_signer),
+            claims_builder(),
+            "client-id".to_string(),
+            2,
+        );
+        wait_for(&pool, 2);
+
+        assert!(pool.take("client-id", 1).is_none());
+        // The filler signs again with the key it is given
+        wait_for(&pool, 2);
+        assert!(pool.take("client-id", 0).is_some());
+    }
+
+    #[test]
+    fn expiring_assertions_are_discarded() {
+        let mut jwt_signer = MockJwtSigner::new();
+        jwt_signer.expect_sign().returning(|_| {
+            Ok(SignedJwt {
+                value: "assertion".into(),
+            })
+        });
+        let pool = AssertionPool::start(
+            Arc::new(jwt_signer),
+            claims_builder().with_lifetime(TimeDelta::seconds(60)),
+            "client-id".to_string(),
+            1,
+        );
+        wait_for(&pool, 1);
+
+        // Simulate an assertion that is about to expire
+        pool.shared.lock().assertions[0].exp = Utc::now().timestamp() as u64 + 10;
+        let mut state = pool.shared.lock();
+        pool.shared.evict_expiring(&mut state);
+        assert!(state.assertions.is_empty());
+    }
+
+    #[test]
+    fn signing_errors_leave_the_pool_empty() {
+        let mut jwt_signer = MockJwtSigner::new();
+        jwt_signer
+            .expect_sign()
+            .returning(|_| Err(JwtEncoderError::TokenEncoding("error".into())));
+        let pool = AssertionPool::start(
+            Arc::new(jwt_signer),
+            claims_builder(),
+            "client-id".to_string(),
+            2,
+        );
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(pool.take("client-id", 0).is_none());
+        // Dropping the pool stops the filler thread even while it backs off
+        let start = Instant::now();
+        drop(pool);
+        assert!(start.elapsed() < SIGNING_ERROR_BACKOFF);
+    }
+}
@@ -1,29 +1,47 @@
-use chrono::{TimeDelta, Utc};
-use http::Uri;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{
     TokenRetrieverError,
     authenticator::{AuthCredential, ClientAssertionType},
-    jwt::{claims::Claims, signer::JwtSigner},
+    jwt::{claims::ClaimsBuilder, encryption::JweEncrypter, signer::JwtSigner},
+    reload::Reloadable,
     system_identity::input_data::auth_method::ClientSecret,
 };
 
-/// A signed JWT should live enough for the System Identity Service to consume it.
-pub(super) const DEFAULT_JWT_CLAIM_EXP: TimeDelta = TimeDelta::seconds(180);
+use super::assertion_pool::AssertionPool;
+
 /// The "aud" (audience) claim identifies the recipients that the JWT is intended for.
-pub(super) const DEFAULT_AUDIENCE: &str = "https://www.newrelic.com/";
+pub(crate) const DEFAULT_AUDIENCE: &str = "https://www.newrelic.com/";
 
 pub trait AuthCredentialBuilder {
     fn build_request_auth_credential(
         &self,
         client_id: String,
     ) -> Result<AuthCredential, TokenRetrieverError>;
+
+    /// Notifies that the server rejected the credential as an invalid client. Returns whether
+    /// building a new credential may succeed.
+    fn credential_rejected(&self) -> bool {
+        false
+    }
+
+    /// Notifies that the server accepted the credential.
+    fn credential_accepted(&self) {}
+
+    /// Notifies that getting a token with the credential failed for another reason than the
+    /// credential being rejected.
+    fn credential_failed(&self) {}
 }
 
 #[derive(Debug)]
 pub struct JwtSignerAuthBuilder<J: JwtSigner> {
-    pub(super) aud: Uri,
-    pub(super) jwt_signer: J,
+    pub(super) claims: ClaimsBuilder,
+    pub(super) jwt_signer: Arc<J>,
+    pub(super) pool: Option<AssertionPool>,
+    pub(super) encrypter: Option<JweEncrypter>,
 }
 
 impl<J: JwtSigner> AuthCredentialBuilder for JwtSignerAuthBuilder<J> {
@@ -31,21 +49,53 @@ impl<J: JwtSigner> AuthCredentialBuilder for JwtSignerAuthBuilder<J> {
         &self,
         client_id: String,
     ) -> Result<AuthCredential, TokenRetrieverError> {
-        let expires_at = Utc::now() + DEFAULT_JWT_CLAIM_EXP;
-
-        let timestamp = expires_at.timestamp().try_into().map_err(|_| {
-            TokenRetrieverError::TokenRetrieverError("converting token expiration time".into())
-        })?;
-
-        let claims = Claims::new(client_id, self.aud.to_owned(), timestamp);
-
-        let signed_jwt = self.jwt_signer.sign(claims)?;
+        // Fall back to signing inline when the pool has not been filled yet.
+        let pre_signed = self
+            .pool
+            .as_ref()
+            .and_then(|pool| pool.take(&client_id, self.jwt_signer.key_generation()));
+        let signed_jwt = match pre_signed {
+            Some(signed_jwt) => signed_jwt,
+            None => {
+                let claims = self
+                    .claims
+                    .build(client_id)
+                    .map_err(|e| TokenRetrieverError::TokenRetrieverError(e.to_string()))?;
+                self.jwt_signer.sign(claims)?
+            }
+        };
+        let signed_jwt = match &self.encrypter {
+            Some(encrypter) => signed_jwt.encrypt(encrypter)?,
+            None => signed_jwt,
+        };
 
         Ok(AuthCredential::ClientAssertion {
             client_assertion_type: ClientAssertionType::JwtBearer,
             client_assertion: signed_jwt.value().into(),
         })
     }
+
+    fn credential_rejected(&self) -> bool {
+        let retry = self.jwt_signer.assertion_rejected();
+        if retry && let Some(pool) = &self.pool {
+            // Pre-signed assertions may have been signed with the rejected key.
+            pool.clear();
+        }
+        retry
+    }
+
+    fn credential_accepted(&self) {
+        self.jwt_signer.assertion_accepted();
+    }
+
+    fn credential_failed(&self) {
+        if self.jwt_signer.assertion_failed()
+            && let Some(pool) = &self.pool
+        {
+            // Pre-signed assertions may have been signed with the previous key.
+            pool.clear();
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -63,3 +113,92 @@ impl AuthCredentialBuilder for ClientSecretAuthBuilder {
         })
     }
 }
+
+/// Client secret read from a file, reloaded when the file changes.
+///
+/// The file is checked for changes when building a credential, at most once per check interval
+/// (5 seconds by default). Surrounding whitespace is ignored, and a file that is empty once
+/// trimmed is not loaded, keeping the previous secret.
+#[derive(Debug)]
+pub struct SecretFileAuthBuilder {
+    secret: Reloadable<ClientSecret, io::Error>,
+}
+
+impl SecretFileAuthBuilder {
+    /// Loads the client secret stored at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        let secret = Reloadable::new(path.into(), |path| {
+            let secret = std::fs::read_to_string(path)?;
+            match secret.trim() {
+                "" => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "empty client secret file",
+                )),
+                secret => Ok(ClientSecret::from(secret)),
+            }
+        })?;
+        Ok(Self { secret })
+    }
+
+    /// Sets how often the secret file is checked for changes.
+    pub fn with_check_interval(self, check_interval: Duration) -> Self {
+        Self {
+            secret: self.secret.with_check_interval(check_interval),
+        }
+    }
+}
+
+impl AuthCredentialBuilder for SecretFileAuthBuilder {
+    fn build_request_auth_credential(
+        &self,
+        _client_id: String,
+    ) -> Result<AuthCredential, TokenRetrieverError> {
+        Ok(AuthCredential::ClientSecret {
+            client_secret: self.secret.current().as_ref().clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn client_secret(builder: &SecretFileAuthBuilder) -> ClientSecret {
+        match builder.build_request_auth_credential("client-id".to_string()) {
+            Ok(AuthCredential::ClientSecret { client_secret }) => client_secret,
+            other => panic!("unexpected credential: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn secret_file_is_reloaded() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("secret");
+        fs::write(&path, "first-secret\n").unwrap();
+
+        let builder = SecretFileAuthBuilder::new(&path)
+            .unwrap()
+            .with_check_interval(Duration::ZERO);
+        assert_eq!(client_secret(&builder), ClientSecret::from("first-secret"));
+
+        fs::write(dir.path().join("secret.tmp"), "second-secret").unwrap();
+        fs::rename(dir.path().join("secret.tmp"), &path).unwrap();
+        assert_eq!(client_secret(&builder), ClientSecret::from("second-secret"));
+
+        // An empty secret file is not loaded
+        fs::write(&path, "\n").unwrap();
+        assert_eq!(client_secret(&builder), ClientSecret::from("second-secret"));
+    }
+
+    #[test]
+    fn empty_secret_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("secret");
+        fs::write(&path, " ").unwrap();
+        assert!(SecretFileAuthBuilder::new(&path).is_err());
+    }
+}